@@ -1,6 +1,9 @@
 //! Attachment API.
 
-use std::io::{Cursor, Read};
+use std::{
+    cell::RefCell,
+    io::{self, Cursor, Read},
+};
 
 use wasm_bindgen::prelude::*;
 
@@ -60,6 +63,172 @@ impl Attachment {
     }
 }
 
+/// An encryptor for attachments which don't fit in memory.
+///
+/// Chunks of the plaintext are passed to `push`, which returns the matching
+/// chunks of ciphertext, and `finish` returns the media encryption info once
+/// all of them have been encrypted. It can be wrapped in a `TransformStream`:
+///
+/// ```javascript
+/// const encryptor = new AttachmentEncryptor();
+/// const stream = new TransformStream({
+///     transform: (chunk, controller) => controller.enqueue(encryptor.push(chunk)),
+///     flush: () => { mediaEncryptionInfo = encryptor.finish(); },
+/// });
+/// ```
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct AttachmentEncryptor {
+    inner: Option<matrix_sdk_crypto::AttachmentEncryptor<'static, ChunkSource>>,
+}
+
+#[wasm_bindgen]
+impl AttachmentEncryptor {
+    /// Create a new encryptor, with a random key.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> AttachmentEncryptor {
+        Self { inner: Some(matrix_sdk_crypto::AttachmentEncryptor::new(ChunkSource::leak())) }
+    }
+
+    /// Encrypt the next chunk of the attachment.
+    ///
+    /// Returns the encrypted chunk, which has the same length.
+    #[wasm_bindgen]
+    pub fn push(&mut self, chunk: Vec<u8>) -> Result<Vec<u8>, JsError> {
+        let Some(encryptor) = self.inner.as_mut() else {
+            return Err(JsError::new("The attachment encryptor has already been finished"));
+        };
+
+        Ok(ChunkSource::process(encryptor, chunk)?)
+    }
+
+    /// Finish the encryption, once all the chunks have been pushed.
+    ///
+    /// Returns the media encryption info, as a JSON-encoded object. The
+    /// encryptor can't be used anymore afterwards.
+    #[wasm_bindgen]
+    pub fn finish(&mut self) -> Result<String, JsError> {
+        let Some(encryptor) = self.inner.take() else {
+            return Err(JsError::new("The attachment encryptor has already been finished"));
+        };
+
+        Ok(serde_json::to_string(&encryptor.finish())?)
+    }
+}
+
+impl Default for AttachmentEncryptor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A decryptor for attachments which don't fit in memory.
+///
+/// Chunks of the ciphertext are passed to `push`, which returns the matching
+/// chunks of plaintext, and `finish` checks the SHA-256 hash of the whole
+/// ciphertext once all of them have been decrypted.
+///
+/// **Warning**: The plaintext is returned before the hash is checked, so it
+/// must be treated as untrusted until `finish` has succeeded. For example, a
+/// media player can start playing it progressively, but it must be discarded
+/// if `finish` throws.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct AttachmentDecryptor {
+    inner: Option<matrix_sdk_crypto::AttachmentDecryptor<'static, ChunkSource>>,
+}
+
+#[wasm_bindgen]
+impl AttachmentDecryptor {
+    /// Create a new decryptor, from the media encryption info of the
+    /// attachment, as a JSON-encoded object.
+    #[wasm_bindgen(constructor)]
+    pub fn new(media_encryption_info: &str) -> Result<AttachmentDecryptor, JsError> {
+        let media_encryption_info = serde_json::from_str(media_encryption_info)?;
+
+        Ok(Self {
+            inner: Some(matrix_sdk_crypto::AttachmentDecryptor::new(
+                ChunkSource::leak(),
+                media_encryption_info,
+            )?),
+        })
+    }
+
+    /// Decrypt the next chunk of the attachment.
+    ///
+    /// Returns the decrypted chunk, which has the same length.
+    #[wasm_bindgen]
+    pub fn push(&mut self, chunk: Vec<u8>) -> Result<Vec<u8>, JsError> {
+        let Some(decryptor) = self.inner.as_mut() else {
+            return Err(JsError::new("The attachment decryptor has already been finished"));
+        };
+
+        Ok(ChunkSource::process(decryptor, chunk)?)
+    }
+
+    /// Finish the decryption, once all the chunks have been pushed.
+    ///
+    /// Throws if the hash of the ciphertext doesn't match the one of the
+    /// media encryption info, in which case all the plaintext returned by
+    /// `push` must be discarded. The decryptor can't be used anymore
+    /// afterwards.
+    #[wasm_bindgen]
+    pub fn finish(&mut self) -> Result<(), JsError> {
+        let Some(mut decryptor) = self.inner.take() else {
+            return Err(JsError::new("The attachment decryptor has already been finished"));
+        };
+
+        // The current chunk is empty, so this only reads the end of the input,
+        // which makes the decryptor check the hash.
+        decryptor.read_to_end(&mut Vec::new())?;
+
+        Ok(())
+    }
+}
+
+thread_local! {
+    /// The chunk being read by [`ChunkSource`].
+    static CURRENT_CHUNK: RefCell<Cursor<Vec<u8>>> = RefCell::new(Cursor::new(Vec::new()));
+}
+
+/// The reader of the streaming attachment encryptors and decryptors.
+///
+/// `matrix_sdk_crypto`'s encryptors and decryptors borrow their reader for
+/// their whole lifetime, which a `#[wasm_bindgen]` type can't do. This reader
+/// is zero-sized, so leaking it to get a `'static` borrow doesn't allocate,
+/// and it reads the chunk being processed from [`CURRENT_CHUNK`] instead.
+///
+/// The wrapped readers finalize the hash of the attachment as soon as their
+/// reader returns no data, so it must only be read while the current chunk
+/// has some left.
+#[derive(Debug)]
+struct ChunkSource;
+
+impl ChunkSource {
+    fn leak() -> &'static mut Self {
+        Box::leak(Box::new(Self))
+    }
+
+    /// Run `chunk` through `reader`, which wraps a [`ChunkSource`].
+    fn process(reader: &mut impl Read, chunk: Vec<u8>) -> io::Result<Vec<u8>> {
+        let mut output = vec![0; chunk.len()];
+
+        CURRENT_CHUNK.with(|current| *current.borrow_mut() = Cursor::new(chunk));
+        // `read_exact` stops once the chunk has been read, so the reader never
+        // sees the end of the input.
+        let result = reader.read_exact(&mut output);
+        CURRENT_CHUNK.with(|current| *current.borrow_mut() = Cursor::new(Vec::new()));
+
+        result.map(|_| output)
+    }
+}
+
+impl Read for ChunkSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        CURRENT_CHUNK.with(|current| current.borrow_mut().read(buf))
+    }
+}
+
 /// An encrypted attachment, usually created from `Attachment.encrypt`.
 #[wasm_bindgen]
 #[derive(Debug)]
@@ -1,10 +1,19 @@
 //! Megolm backup types
 
-use js_sys::JsString;
-use matrix_sdk_crypto::{backups::MegolmV1BackupKey as InnerMegolmV1BackupKey, store};
+use std::collections::BTreeMap;
+
+use hmac::Hmac;
+use js_sys::{Array, Function, JsString, Map, JSON};
+use matrix_sdk_common::ruma::OwnedRoomId;
+use matrix_sdk_crypto::{
+    backups::MegolmV1BackupKey as InnerMegolmV1BackupKey, olm::BackedUpRoomKey, store,
+};
+use serde::Deserialize;
+use sha2::Sha512;
 use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
 
-use crate::impl_from_to_inner;
+use crate::{identifiers, impl_from_to_inner};
 
 /// The private part of the backup key, the one used for recovery.
 #[derive(Debug, Clone)]
@@ -57,6 +66,62 @@ impl BackupDecryptionKey {
         self.inner.to_base64().into()
     }
 
+    /// Try to create a [`BackupDecryptionKey`] from a recovery key, as shown
+    /// to users: the base58 encoding of the key, prefixed with `0x8B01` and
+    /// followed by a parity byte.
+    ///
+    /// Whitespace is ignored. If the recovery key is invalid, the error
+    /// message says what's wrong with it, so that it can be shown to the user:
+    /// for example, which character can't appear in a recovery key.
+    #[wasm_bindgen(js_name = "fromRecoveryKey")]
+    pub fn from_recovery_key(recovery_key: &str) -> Result<BackupDecryptionKey, JsError> {
+        let recovery_key: String = recovery_key.chars().filter(|c| !c.is_whitespace()).collect();
+        check_recovery_key(&recovery_key)?;
+
+        let inner = store::types::BackupDecryptionKey::from_base58(&recovery_key).map_err(|e| {
+            JsError::new(&format!(
+                "The recovery key is invalid ({e}); please check that it has been typed correctly"
+            ))
+        })?;
+
+        Ok(Self { inner })
+    }
+
+    /// Convert the backup decryption key to a recovery key, as shown to users.
+    ///
+    /// See {@link BackupDecryptionKey.fromRecoveryKey}.
+    #[wasm_bindgen(js_name = "toRecoveryKey")]
+    pub fn to_recovery_key(&self) -> JsString {
+        self.inner.to_base58().into()
+    }
+
+    /// Derive a [`BackupDecryptionKey`] from a passphrase, with PBKDF2 using
+    /// SHA-512.
+    ///
+    /// `salt` and `iterations` are the `private_key_salt` and
+    /// `private_key_iterations` properties of the `auth_data` of the backup.
+    #[wasm_bindgen(js_name = "fromPassphrase")]
+    pub fn from_passphrase(
+        passphrase: &str,
+        salt: &str,
+        iterations: u32,
+    ) -> Result<BackupDecryptionKey, JsError> {
+        if iterations == 0 {
+            return Err(JsError::new("The number of iterations must be greater than 0"));
+        }
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        pbkdf2::pbkdf2::<Hmac<Sha512>>(
+            passphrase.as_bytes(),
+            salt.as_bytes(),
+            iterations,
+            &mut *key,
+        )
+        .expect("HMAC can take a key of any size");
+
+        Ok(Self { inner: store::types::BackupDecryptionKey::from_bytes(&key) })
+    }
+
     /// Get the public part of the backup key.
     #[wasm_bindgen(getter, js_name = "megolmV1PublicKey")]
     pub fn megolm_v1_public_key(&self) -> MegolmV1BackupKey {
@@ -76,6 +141,206 @@ impl BackupDecryptionKey {
     ) -> Result<String, JsError> {
         self.inner.decrypt_v1(&ephemeral_key, &mac, &ciphertext).map_err(|e| e.into())
     }
+
+    /// Decrypt all the room keys of a `/room_keys/keys` response, ready to be
+    /// passed to {@link OlmMachine.importBackedUpRoomKeys}.
+    ///
+    /// The room keys which can't be decrypted don't make the whole response
+    /// fail: they are listed in {@link DecryptedKeysBackupData.failures}.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys_backup_data` - The JSON-encoded body of the response.
+    /// * `backup_version` - The version of the backup the response is from.
+    /// * `progress_listener` - An optional callback, called after each room key
+    ///   with the number of room keys processed so far and the total number of
+    ///   room keys.
+    #[wasm_bindgen(js_name = "decryptKeysBackupData")]
+    pub fn decrypt_keys_backup_data(
+        &self,
+        keys_backup_data: &str,
+        backup_version: String,
+        #[wasm_bindgen(unchecked_param_type = "(progress: number, total: number) => void")]
+        progress_listener: Option<Function>,
+    ) -> Result<DecryptedKeysBackupData, JsError> {
+        let keys_backup_data: KeysBackupData = serde_json::from_str(keys_backup_data)?;
+        let total: usize = keys_backup_data.rooms.values().map(|room| room.sessions.len()).sum();
+
+        let keys = Map::new();
+        let mut failures = Vec::new();
+        let mut progress = 0;
+
+        for (room_id, room) in keys_backup_data.rooms {
+            let room_id = identifiers::RoomId::from(room_id);
+            let room_keys = Map::new();
+
+            for (session_id, key_backup_data) in room.sessions {
+                let room_key = self.decrypt_key_backup_data(key_backup_data).and_then(|room_key| {
+                    JSON::parse(&room_key).map_err(|e| format!("Invalid room key: {e:?}"))
+                });
+
+                match room_key {
+                    Ok(room_key) => {
+                        room_keys.set(&JsString::from(session_id.as_str()), &room_key);
+                    }
+                    Err(error) => failures.push(BackupDecryptionFailure {
+                        room_id: room_id.clone(),
+                        session_id,
+                        error,
+                    }),
+                }
+
+                progress += 1;
+                if let Some(callback) = &progress_listener {
+                    callback
+                        .call2(&JsValue::NULL, &JsValue::from(progress), &JsValue::from(total))
+                        .expect("Progress listener passed to `decryptKeysBackupData` failed");
+                }
+            }
+
+            if room_keys.size() > 0 {
+                keys.set(&room_id.into(), &room_keys);
+            }
+        }
+
+        Ok(DecryptedKeysBackupData { backup_version, keys, failures })
+    }
+}
+
+impl BackupDecryptionKey {
+    /// Decrypt the `KeyBackupData` of a single room key into its JSON
+    /// encoding, or return why it failed.
+    pub(crate) fn decrypt_key_backup_data(
+        &self,
+        key_backup_data: serde_json::Value,
+    ) -> Result<String, String> {
+        let session_data = serde_json::from_value::<KeyBackupData>(key_backup_data)
+            .map_err(|e| format!("Invalid key backup data: {e}"))?
+            .session_data;
+        let room_key = self
+            .inner
+            .decrypt_v1(&session_data.ephemeral, &session_data.mac, &session_data.ciphertext)
+            .map_err(|e| e.to_string())?;
+
+        // Check that the room key can be imported, so that it fails here rather than
+        // in `importBackedUpRoomKeys`.
+        serde_json::from_str::<BackedUpRoomKey>(&room_key)
+            .map_err(|e| format!("Invalid room key: {e}"))?;
+
+        Ok(room_key)
+    }
+}
+
+/// The number of characters of a recovery key, without whitespace.
+const RECOVERY_KEY_LENGTH: usize = 48;
+
+/// Check the characters and the length of a recovery key, to give a helpful
+/// error if it has been mistyped.
+fn check_recovery_key(recovery_key: &str) -> Result<(), JsError> {
+    let invalid = recovery_key
+        .chars()
+        .enumerate()
+        .find(|(_, c)| !c.is_ascii_alphanumeric() || matches!(c, '0' | 'O' | 'I' | 'l'));
+
+    if let Some((position, c)) = invalid {
+        let suggestion = match c {
+            '0' | 'O' => ": did you mean 'o'?",
+            'I' | 'l' => ": did you mean '1', 'i' or 'L'?",
+            _ => "",
+        };
+
+        return Err(JsError::new(&format!(
+            "The recovery key contains '{c}' at position {}, which can't appear in a recovery \
+             key{suggestion}",
+            position + 1
+        )));
+    }
+
+    if recovery_key.len() != RECOVERY_KEY_LENGTH {
+        return Err(JsError::new(&format!(
+            "The recovery key should be {RECOVERY_KEY_LENGTH} characters long, not counting \
+             spaces, but it is {} characters long",
+            recovery_key.len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// The body of a `/room_keys/keys` response.
+#[derive(Deserialize)]
+struct KeysBackupData {
+    rooms: BTreeMap<OwnedRoomId, RoomKeyBackup>,
+}
+
+/// The room keys of a room, in a `/room_keys/keys` response.
+#[derive(Deserialize)]
+struct RoomKeyBackup {
+    /// The room keys by session ID. They are only parsed once decrypted, so
+    /// that a malformed one doesn't make the whole response fail.
+    sessions: BTreeMap<String, serde_json::Value>,
+}
+
+/// A single backed up room key.
+#[derive(Deserialize)]
+struct KeyBackupData {
+    session_data: EncryptedSessionData,
+}
+
+/// The encrypted part of a backed up room key, for the
+/// `m.megolm_backup.v1.curve25519-aes-sha2` algorithm.
+#[derive(Deserialize)]
+struct EncryptedSessionData {
+    ephemeral: String,
+    ciphertext: String,
+    mac: String,
+}
+
+/// The result of {@link BackupDecryptionKey.decryptKeysBackupData}.
+#[derive(Debug)]
+#[wasm_bindgen]
+pub struct DecryptedKeysBackupData {
+    /// The version of the backup the room keys are from.
+    #[wasm_bindgen(js_name = "backupVersion", getter_with_clone)]
+    pub backup_version: String,
+
+    keys: Map,
+    failures: Vec<BackupDecryptionFailure>,
+}
+
+#[wasm_bindgen]
+impl DecryptedKeysBackupData {
+    /// The decrypted room keys, as expected by
+    /// {@link OlmMachine.importBackedUpRoomKeys}: a Map from {@link RoomId} to
+    /// a Map from session ID to the decrypted session data.
+    #[wasm_bindgen(getter, unchecked_return_type = "Map<RoomId, Map<string, any>>")]
+    pub fn keys(&self) -> Map {
+        self.keys.clone()
+    }
+
+    /// The room keys which couldn't be decrypted.
+    #[wasm_bindgen(getter, unchecked_return_type = "BackupDecryptionFailure[]")]
+    pub fn failures(&self) -> Array {
+        self.failures.iter().cloned().map(JsValue::from).collect()
+    }
+}
+
+/// A room key which {@link BackupDecryptionKey.decryptKeysBackupData}
+/// couldn't decrypt.
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct BackupDecryptionFailure {
+    /// The room of the room key.
+    #[wasm_bindgen(readonly, js_name = "roomId")]
+    pub room_id: identifiers::RoomId,
+
+    /// The session ID of the room key.
+    #[wasm_bindgen(readonly, js_name = "sessionId")]
+    pub session_id: String,
+
+    /// Why the room key couldn't be decrypted.
+    #[wasm_bindgen(readonly)]
+    pub error: String,
 }
 
 /// Struct holding the number of room keys we have.
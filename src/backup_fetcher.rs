@@ -0,0 +1,234 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fetching, from the backup, of the room keys which were missing to decrypt
+//! a room event.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+};
+
+use matrix_sdk_common::ruma::{
+    serde::Raw, MilliSecondsSinceUnixEpoch, OwnedRoomId, RoomId, TransactionId,
+};
+use matrix_sdk_crypto::{
+    olm::{BackedUpRoomKey, ExportedRoomKey},
+    types::events::room::encrypted::EncryptedEvent,
+    CryptoStoreError,
+};
+use tracing::{debug, warn};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    backup::BackupDecryptionKey, error::DecryptionErrorCode, requests::KeysBackupFetchRequest,
+    utd_tracker,
+};
+
+/// The minimum time between two requests for the same room key, in
+/// milliseconds.
+const FETCH_INTERVAL_MS: u64 = 5 * 60 * 1000;
+
+/// A request which has been handed out by
+/// [`BackupFetcher::outgoing_requests`] and whose response hasn't been
+/// received yet.
+#[derive(Debug)]
+struct InFlightRequest {
+    room_id: OwnedRoomId,
+    session_id: String,
+    backup_version: String,
+
+    /// The time at which the request was handed out, in milliseconds since
+    /// the Unix epoch.
+    requested_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct FetcherState {
+    /// The room keys to request, by room and session ID.
+    queued: BTreeSet<(OwnedRoomId, String)>,
+
+    /// The requests whose response hasn't been received yet, by request ID.
+    ///
+    /// They are forgotten after [`FETCH_INTERVAL_MS`], as the room key is
+    /// then requested again anyway.
+    in_flight: HashMap<String, InFlightRequest>,
+
+    /// The time at which each room key was last requested, in milliseconds
+    /// since the Unix epoch.
+    requested_at: HashMap<(OwnedRoomId, String), u64>,
+}
+
+impl FetcherState {
+    /// Forget the room keys and requests older than [`FETCH_INTERVAL_MS`].
+    fn expire(&mut self, now: u64) {
+        let is_recent = |requested_at: u64| now.saturating_sub(requested_at) < FETCH_INTERVAL_MS;
+
+        self.requested_at.retain(|_, requested_at| is_recent(*requested_at));
+        self.in_flight.retain(|_, request| is_recent(request.requested_at));
+    }
+}
+
+/// Remembers the room keys which were missing to decrypt a room event, and
+/// fetches them from the backup with [`KeysBackupFetchRequest`]s.
+///
+/// Each room key is requested at most once every [`FETCH_INTERVAL_MS`], so
+/// that the events of a session which isn't in the backup don't cause a
+/// request each time they fail to decrypt.
+#[derive(Debug, Default)]
+pub(crate) struct BackupFetcher {
+    state: RefCell<FetcherState>,
+}
+
+impl BackupFetcher {
+    /// Queue the room key of an event which failed to decrypt with the given
+    /// error code, if it is missing and hasn't been requested recently.
+    pub(crate) fn missing_room_key(
+        &self,
+        room_id: &RoomId,
+        event: &Raw<EncryptedEvent>,
+        code: DecryptionErrorCode,
+    ) {
+        if !matches!(code, DecryptionErrorCode::MissingRoomKey) {
+            return;
+        }
+
+        let Some(session_id) = utd_tracker::session_id(event) else { return };
+        let key = (room_id.to_owned(), session_id);
+        let mut state = self.state.borrow_mut();
+
+        state.expire(now_ms());
+
+        if !state.requested_at.contains_key(&key) {
+            state.queued.insert(key);
+        }
+    }
+
+    /// Get the requests for the queued room keys.
+    ///
+    /// The room keys are only requested if the backup decryption key is
+    /// stored, along with the version of the backup it belongs to. Otherwise,
+    /// they are forgotten.
+    pub(crate) async fn outgoing_requests(
+        &self,
+        machine: &matrix_sdk_crypto::OlmMachine,
+    ) -> Result<Vec<KeysBackupFetchRequest>, CryptoStoreError> {
+        if self.state.borrow().queued.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let backup_keys = machine.backup_machine().get_backup_keys().await?;
+        let mut state = self.state.borrow_mut();
+        let queued = std::mem::take(&mut state.queued);
+
+        let (Some(_), Some(backup_version)) =
+            (backup_keys.decryption_key, backup_keys.backup_version)
+        else {
+            debug!(
+                count = queued.len(),
+                "No backup decryption key, not fetching the missing room keys"
+            );
+            return Ok(Vec::new());
+        };
+
+        let now = now_ms();
+        state.expire(now);
+
+        Ok(queued
+            .into_iter()
+            .map(|(room_id, session_id)| {
+                let request_id = TransactionId::new().to_string();
+                let request = KeysBackupFetchRequest::new(
+                    request_id.as_str().into(),
+                    room_id.as_str().into(),
+                    session_id.as_str().into(),
+                    backup_version.as_str().into(),
+                );
+
+                state.requested_at.insert((room_id.clone(), session_id.clone()), now);
+                state.in_flight.insert(
+                    request_id,
+                    InFlightRequest {
+                        room_id,
+                        session_id,
+                        backup_version: backup_version.clone(),
+                        requested_at: now,
+                    },
+                );
+
+                request
+            })
+            .collect())
+    }
+
+    /// Import the room key from the response to a [`KeysBackupFetchRequest`].
+    ///
+    /// The room key is imported as done by
+    /// `OlmMachine.importBackedUpRoomKeys`, so that it is marked as backed
+    /// up. Responses telling that the room key isn't in the backup are
+    /// ignored, as are the room keys of a backup which is no longer the
+    /// current one, and the responses received more than
+    /// [`FETCH_INTERVAL_MS`] after the request was handed out.
+    pub(crate) async fn receive_response(
+        &self,
+        machine: &matrix_sdk_crypto::OlmMachine,
+        request_id: &str,
+        response: &str,
+    ) -> Result<(), JsError> {
+        let Some(request) = self.state.borrow_mut().in_flight.remove(request_id) else {
+            debug!(request_id, "Unknown or expired KeysBackupFetchRequest, ignoring the response");
+            return Ok(());
+        };
+        let InFlightRequest { room_id, session_id, backup_version, .. } = request;
+
+        let key_backup_data: serde_json::Value = serde_json::from_str(response)?;
+        if key_backup_data.get("session_data").is_none() {
+            // The server replied with an error, most likely `M_NOT_FOUND`.
+            debug!(?room_id, session_id, "The room key is not in the backup");
+            return Ok(());
+        }
+
+        let backup_keys = machine.backup_machine().get_backup_keys().await?;
+        let decryption_key = match (backup_keys.decryption_key, backup_keys.backup_version) {
+            (Some(key), Some(version)) if version == backup_version => {
+                BackupDecryptionKey::from(key)
+            }
+            _ => {
+                debug!(?room_id, session_id, "The backup has changed, ignoring the room key");
+                return Ok(());
+            }
+        };
+
+        let room_key =
+            match decryption_key.decrypt_key_backup_data(key_backup_data).and_then(|room_key| {
+                serde_json::from_str::<BackedUpRoomKey>(&room_key).map_err(|e| e.to_string())
+            }) {
+                Ok(room_key) => room_key,
+                Err(e) => {
+                    warn!(?room_id, session_id, "Error decrypting a room key from the backup: {e}");
+                    return Ok(());
+                }
+            };
+
+        let room_key = ExportedRoomKey::from_backed_up_room_key(room_id, session_id, room_key);
+        machine.store().import_room_keys(vec![room_key], Some(&backup_version), |_, _| {}).await?;
+
+        Ok(())
+    }
+}
+
+/// The current time, in milliseconds since the Unix epoch.
+fn now_ms() -> u64 {
+    u64::from(MilliSecondsSinceUnixEpoch::now().get())
+}
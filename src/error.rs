@@ -32,7 +32,7 @@ pub enum DecryptionErrorCode {
 }
 
 /// Js Decryption error with code.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[wasm_bindgen(getter_with_clone)]
 pub struct MegolmDecryptionError {
     /// Description code for the error. See `DecryptionErrorCode`
@@ -44,6 +44,10 @@ pub struct MegolmDecryptionError {
 
     /// The withheld code, if any.
     withheld_code: Option<WithheldCode>,
+
+    /// For [`DecryptionErrorCode::UnknownMessageIndex`], the first index known
+    /// by our session and the index of the message.
+    message_indices: Option<(u32, u32)>,
 }
 
 #[wasm_bindgen]
@@ -54,6 +58,7 @@ impl MegolmDecryptionError {
             code: DecryptionErrorCode::UnableToDecrypt,
             description: desc.into(),
             withheld_code: None,
+            message_indices: None,
         }
     }
 
@@ -86,7 +91,7 @@ impl From<MegolmError> for MegolmDecryptionError {
                                 withheld_code: Option<WithheldCode>|
          -> MegolmDecryptionError {
             let description = value.to_string().into();
-            MegolmDecryptionError { code, description, withheld_code }
+            MegolmDecryptionError { code, description, withheld_code, message_indices: None }
         };
 
         match &value {
@@ -94,8 +99,12 @@ impl From<MegolmError> for MegolmDecryptionError {
                 decryption_error(DecryptionErrorCode::MissingRoomKey, withheld_code.clone())
             }
             MegolmError::Decryption(vodozemac::megolm::DecryptionError::UnknownMessageIndex(
-                ..,
-            )) => decryption_error(DecryptionErrorCode::UnknownMessageIndex, None),
+                first_known_index,
+                message_index,
+            )) => MegolmDecryptionError {
+                message_indices: Some((*first_known_index, *message_index)),
+                ..decryption_error(DecryptionErrorCode::UnknownMessageIndex, None)
+            },
             MegolmError::MismatchedIdentityKeys { .. } => {
                 decryption_error(DecryptionErrorCode::UnknownMessageIndex, None)
             }
@@ -125,3 +134,133 @@ impl From<MegolmError> for MegolmDecryptionError {
         }
     }
 }
+
+/// The likely cause of a decryption failure, as returned by
+/// {@link OlmMachine.classifyDecryptionError}.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtdCause {
+    /// We don't know why the event couldn't be decrypted.
+    Unknown,
+    /// The event was sent before we joined the room, so we were not expected
+    /// to receive its room key.
+    SentBeforeWeJoined,
+    /// The event was sent before this device was created, so it could only be
+    /// decrypted with a key from the key backup or another device.
+    SentBeforeDeviceCreation,
+    /// The sender withheld the room key from us because this device is not
+    /// verified (`m.unverified`).
+    WithheldForUnverifiedDevice,
+    /// The sender withheld the room key from us for another reason.
+    WithheldBySender,
+    /// We couldn't link the event back to a known device of its sender, and
+    /// the trust requirement rejects such events.
+    UnknownDevice,
+    /// The device of the sender is not cross-signed, and the trust requirement
+    /// rejects such events.
+    UnsignedDevice,
+    /// The identity of the sender was previously verified but has changed.
+    VerificationViolation,
+}
+
+/// The classification of a decryption failure, with the data it is based on.
+#[derive(Debug, Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct UtdClassification {
+    /// The likely cause of the failure.
+    #[wasm_bindgen(readonly)]
+    pub cause: UtdCause,
+
+    /// The error code of the failure.
+    #[wasm_bindgen(readonly)]
+    pub code: DecryptionErrorCode,
+
+    /// The `origin_server_ts` of the event, if any.
+    #[wasm_bindgen(readonly, js_name = "eventTimestampMs")]
+    pub event_timestamp_ms: Option<f64>,
+
+    /// The time at which this device was created, see
+    /// {@link OlmMachine.deviceCreationTimeMs}.
+    #[wasm_bindgen(readonly, js_name = "deviceCreationTimeMs")]
+    pub device_creation_time_ms: f64,
+
+    /// The withheld code of the room key, if any.
+    ///
+    /// Example: "m.unverified"
+    #[wasm_bindgen(readonly, js_name = "withheldCode")]
+    pub withheld_code: Option<String>,
+
+    /// The ID of the Megolm session of the event, if any.
+    #[wasm_bindgen(readonly, js_name = "sessionId")]
+    pub session_id: Option<String>,
+
+    /// For {@link DecryptionErrorCode.UnknownMessageIndex}, the index of the
+    /// message in its session.
+    #[wasm_bindgen(readonly, js_name = "messageIndex")]
+    pub message_index: Option<u32>,
+
+    /// For {@link DecryptionErrorCode.UnknownMessageIndex}, the first message
+    /// index which our copy of the session can decrypt.
+    #[wasm_bindgen(readonly, js_name = "firstKnownIndex")]
+    pub first_known_index: Option<u32>,
+}
+
+#[wasm_bindgen]
+impl UtdClassification {
+    /// Whether the failure was expected, that is whether its cause is known.
+    ///
+    /// Unexpected failures are the ones worth reporting as bugs.
+    #[wasm_bindgen(getter, js_name = "isExpected")]
+    pub fn is_expected(&self) -> bool {
+        self.cause != UtdCause::Unknown
+    }
+}
+
+impl UtdClassification {
+    /// Classify a decryption failure.
+    ///
+    /// `joined_at_ms` is the time at which we joined the room, if known.
+    pub(crate) fn new(
+        error: &MegolmDecryptionError,
+        event_timestamp_ms: Option<f64>,
+        session_id: Option<String>,
+        device_creation_time_ms: f64,
+        joined_at_ms: Option<f64>,
+    ) -> Self {
+        let sent_before = |time: Option<f64>| matches!((event_timestamp_ms, time), (Some(event), Some(time)) if event < time);
+
+        let cause = match (&error.withheld_code, error.code) {
+            (Some(WithheldCode::Unverified), _) => UtdCause::WithheldForUnverifiedDevice,
+            (Some(_), _) => UtdCause::WithheldBySender,
+            (None, DecryptionErrorCode::UnknownSenderDevice) => UtdCause::UnknownDevice,
+            (None, DecryptionErrorCode::UnsignedSenderDevice) => UtdCause::UnsignedDevice,
+            (None, DecryptionErrorCode::SenderIdentityVerificationViolation) => {
+                UtdCause::VerificationViolation
+            }
+            (
+                None,
+                DecryptionErrorCode::MissingRoomKey | DecryptionErrorCode::UnknownMessageIndex,
+            ) => {
+                if sent_before(joined_at_ms) {
+                    UtdCause::SentBeforeWeJoined
+                } else if sent_before(Some(device_creation_time_ms)) {
+                    UtdCause::SentBeforeDeviceCreation
+                } else {
+                    UtdCause::Unknown
+                }
+            }
+            (None, _) => UtdCause::Unknown,
+        };
+
+        Self {
+            cause,
+            code: error.code,
+            event_timestamp_ms,
+            device_creation_time_ms,
+            withheld_code: error.withheld_code.as_ref().map(|code| code.as_str().to_owned()),
+            session_id,
+            message_index: error.message_indices.map(|(_, index)| index),
+            first_known_index: error.message_indices.map(|(first, _)| first),
+        }
+    }
+}
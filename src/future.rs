@@ -1,9 +1,10 @@
 use std::future::Future;
 
+use futures_util::future::{AbortHandle, Abortable};
 use js_sys::Promise;
-use tracing::instrument::WithSubscriber;
+use tracing::{instrument::WithSubscriber, Dispatch};
 use wasm_bindgen::{JsError, JsValue, UnwrapThrowExt};
-use wasm_bindgen_futures::spawn_local;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
 
 /**
  * Convert a Rust [`Future`] which returns [`Result<T, JsError>`] into a
@@ -52,3 +53,58 @@ where
         });
     })
 }
+
+/**
+ * A future running in the background with [`spawn_local`], which can be
+ * aborted.
+ *
+ * Aborting the task with [`BackgroundTask::abort`] waits until the future,
+ * and everything it holds, has been dropped.
+ */
+#[derive(Debug)]
+pub(crate) struct BackgroundTask {
+    abort_handle: AbortHandle,
+    stopped: Promise,
+}
+
+impl BackgroundTask {
+    /// Spawn `future` in the background, with the given tracing subscriber.
+    pub(crate) fn spawn<F>(future: F, tracing_subscriber: &Dispatch) -> Self
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let future = Abortable::new(future, abort_registration);
+
+        let mut resolve_stopped = None;
+        let stopped = Promise::new(&mut |resolve, _| resolve_stopped = Some(resolve));
+        let resolve_stopped = resolve_stopped.unwrap_throw();
+
+        spawn_local(
+            async move {
+                // The future is dropped at the end of this statement, whether
+                // it completed or was aborted.
+                let _ = future.await;
+                resolve_stopped.call0(&JsValue::UNDEFINED).unwrap_throw();
+            }
+            .with_subscriber(tracing_subscriber.clone()),
+        );
+
+        Self { abort_handle, stopped }
+    }
+
+    /// Abort the task, and wait until its future has been dropped.
+    pub(crate) async fn abort(self) {
+        let stopped = self.stopped.clone();
+        drop(self);
+
+        // The promise is only ever resolved.
+        let _ = JsFuture::from(stopped).await;
+    }
+}
+
+impl Drop for BackgroundTask {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
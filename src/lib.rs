@@ -20,6 +20,7 @@
 
 pub mod attachment;
 pub mod backup;
+mod backup_fetcher;
 pub mod dehydrated_devices;
 pub mod device;
 pub mod encryption;
@@ -35,10 +36,12 @@ pub mod olm;
 pub mod qr_login;
 pub mod requests;
 pub mod responses;
+pub mod secret_storage;
 pub mod store;
 pub mod sync_events;
 mod tracing;
 pub mod types;
+mod utd_tracker;
 pub mod verification;
 pub mod vodozemac;
 
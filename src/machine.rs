@@ -1,27 +1,36 @@
 //! The crypto specific Olm objects.
 
 use std::{
-    collections::{BTreeMap, HashSet},
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     io::{Cursor, Read},
     iter,
     ops::Deref,
     pin::{pin, Pin},
+    rc::Rc,
+    sync::Arc,
     time::Duration,
 };
 
-use futures_util::{pin_mut, Stream, StreamExt};
+use futures_util::{lock::Mutex, pin_mut, Stream, StreamExt};
 use js_sys::{Array, Function, JsString, Map, Promise, Set};
 use matrix_sdk_common::ruma::{
     self,
     events::{room::EncryptedFile, secret::request::SecretName},
     serde::Raw,
-    OneTimeKeyAlgorithm, OwnedDeviceId, OwnedTransactionId, OwnedUserId, UInt,
+    OneTimeKeyAlgorithm, OwnedDeviceId, OwnedRoomId, OwnedTransactionId, OwnedUserId, UInt,
 };
 use matrix_sdk_crypto::{
     backups::MegolmV1BackupKey,
     olm::{BackedUpRoomKey, ExportedRoomKey},
-    store::types::{Changes, DeviceChanges, IdentityChanges, SecretsInboxItem},
-    types::{events::room_key_bundle::RoomKeyBundleContent, RoomKeyBackupInfo},
+    store::{
+        types::{Changes, DeviceChanges, IdentityChanges, SecretsInboxItem},
+        DynCryptoStore,
+    },
+    types::{
+        events::{room::encrypted::EncryptedEvent, room_key_bundle::RoomKeyBundleContent},
+        RoomKeyBackupInfo,
+    },
     CryptoStoreError, EncryptionSyncChanges, MediaEncryptionInfo, OlmMachineBuilder,
 };
 use serde::{ser::SerializeSeq, Serialize, Serializer};
@@ -34,22 +43,25 @@ use zeroize::Zeroizing;
 use crate::{
     attachment,
     backup::{BackupDecryptionKey, BackupKeys, RoomKeyCounts},
+    backup_fetcher::BackupFetcher,
     dehydrated_devices::DehydratedDevices,
     device, encryption,
-    error::MegolmDecryptionError,
-    future::{future_to_promise, future_to_promise_with_custom_error},
+    error::{DecryptionErrorCode, MegolmDecryptionError, UtdClassification},
+    future::{future_to_promise, future_to_promise_with_custom_error, BackgroundTask},
     identifiers, identities, olm,
     requests::{
         self, outgoing_request_to_js_value, CrossSigningBootstrapRequests, ToDeviceRequest,
     },
     responses::{self, response_from_string, UnsupportedAlgorithmError},
-    store::{self, RoomKeyInfo, RoomKeyWithheldInfo, StoreHandle},
+    secret_storage,
+    store::{self, RoomKeyInfo, RoomKeyWithheldInfo, StoreBackend, StoreHandle},
     sync_events,
     tracing::{logger_to_dispatcher, JsLogger},
     types::{
         self, processed_to_device_event_to_js_value, RoomKeyImportResult, RoomSettings,
         SignatureVerification, StoredRoomKeyBundleData,
     },
+    utd_tracker::{self, UtdTracker},
     verification, vodozemac,
 };
 
@@ -72,6 +84,33 @@ pub struct OlmMachine {
 
     /// The tracing subscriber associated with this machine
     tracing_subscriber: Dispatch,
+
+    /// Where the data of the store backing this machine is kept
+    store_backend: StoreBackend,
+
+    /// The events which couldn't be decrypted, to decrypt again once their
+    /// room key arrives
+    utd_tracker: Rc<UtdTracker>,
+
+    /// The room keys which were missing to decrypt an event, to fetch from
+    /// the backup
+    backup_fetcher: Rc<BackupFetcher>,
+
+    /// The progress listener given to `backupRoomKeys`, to call once its
+    /// request has been marked as sent
+    backup_progress_listener: Rc<RefCell<Option<Function>>>,
+
+    /// Serialises the writes of the times at which inbound group sessions
+    /// are stored with the pruning of the sessions
+    session_times_lock: Rc<Mutex<()>>,
+
+    /// The inbound group sessions read while decrypting a batch of events,
+    /// so that each of them is only read once
+    inbound_group_session_cache: store::InboundGroupSessionCache,
+
+    /// The tasks running in the background for this machine, to abort when
+    /// it is closed
+    background_tasks: Rc<RefCell<Vec<BackgroundTask>>>,
 }
 
 #[wasm_bindgen]
@@ -168,12 +207,34 @@ impl OlmMachine {
         store_handle: StoreHandle,
         tracing_subscriber: Dispatch,
     ) -> Result<OlmMachine, JsError> {
+        let store_backend = store_handle.backend.clone();
+        let inbound_group_session_cache = store::InboundGroupSessionCache::default();
         let inner = OlmMachineBuilder::new(user_id.as_ref(), device_id.as_ref())
-            .with_crypto_store(store_handle)
+            .with_crypto_store(store::CachingStore::new(
+                store_handle.store.clone(),
+                inbound_group_session_cache.clone(),
+            ))
             .build()
             .await?;
 
-        Ok(OlmMachine { inner, tracing_subscriber })
+        let session_times_lock = Rc::new(Mutex::new(()));
+        let session_times_task = record_inbound_group_session_times(
+            &inner,
+            &tracing_subscriber,
+            session_times_lock.clone(),
+        );
+
+        Ok(OlmMachine {
+            inner,
+            tracing_subscriber,
+            store_backend,
+            utd_tracker: Default::default(),
+            backup_fetcher: Default::default(),
+            backup_progress_listener: Default::default(),
+            session_times_lock,
+            inbound_group_session_cache,
+            background_tasks: Rc::new(RefCell::new(vec![session_times_task])),
+        })
     }
 
     /// The unique user ID that owns this `OlmMachine` instance.
@@ -365,65 +426,47 @@ impl OlmMachine {
         #[wasm_bindgen(unchecked_optional_param_type = "DecryptionSettings")]
         decryption_settings: Option<encryption::DecryptionSettings>,
     ) -> Result<Promise, JsError> {
-        let _guard = dispatcher::set_default(&self.tracing_subscriber);
         let to_device_events = serde_json::from_str(to_device_events)?;
-        let changed_devices = changed_devices.inner.clone();
-        let one_time_keys_counts: BTreeMap<OneTimeKeyAlgorithm, UInt> = one_time_keys_counts
-            .entries()
-            .into_iter()
-            .filter_map(|js_value| {
-                let pair = Array::from(&js_value.ok()?);
-                let (key, value) = (
-                    OneTimeKeyAlgorithm::from(pair.at(0).as_string()?),
-                    UInt::new(pair.at(1).as_f64()? as u64)?,
-                );
-
-                Some((key, value))
-            })
-            .collect();
-
-        // Convert the unused_fallback_keys JS Set to a `Vec<OneTimeKeyAlgorithm>`
-        let unused_fallback_keys: Option<Vec<OneTimeKeyAlgorithm>> =
-            unused_fallback_keys.map(|fallback_keys| {
-                fallback_keys
-                    .values()
-                    .into_iter()
-                    .filter_map(|js_value| {
-                        Some(OneTimeKeyAlgorithm::from(js_value.ok()?.as_string()?))
-                    })
-                    .collect()
-            });
 
-        let me = self.inner.clone();
-        let decryption_settings = (&decryption_settings.unwrap_or(
-            encryption::DecryptionSettings::new(encryption::TrustRequirement::Untrusted),
+        Ok(self.receive_sync_changes_helper(
+            to_device_events,
+            changed_devices,
+            one_time_keys_counts,
+            unused_fallback_keys,
+            decryption_settings,
         ))
-            .into();
-
-        Ok(future_to_promise(async move {
-            // we discard the list of updated room keys in the result; JS applications are
-            // expected to use register_room_key_updated_callback to receive updated room
-            // keys.
-            let (processed_to_device_events, _) = me
-                .receive_sync_changes(
-                    EncryptionSyncChanges {
-                        to_device_events,
-                        changed_devices: &changed_devices,
-                        one_time_keys_counts: &one_time_keys_counts,
-                        unused_fallback_keys: unused_fallback_keys.as_deref(),
-
-                        // matrix-sdk-crypto does not (currently) use `next_batch_token`.
-                        next_batch_token: None,
-                    },
-                    &decryption_settings,
-                )
-                .await?;
+    }
 
-            Ok(processed_to_device_events
-                .into_iter()
-                .filter_map(processed_to_device_event_to_js_value)
-                .collect::<Vec<_>>())
-        }))
+    /// Handle to-device events and one-time key counts from a sync
+    /// response.
+    ///
+    /// This is the same as {@link receiveSyncChanges}, except that the
+    /// to-device events are given as plain objects rather than as JSON.
+    #[wasm_bindgen(
+        js_name = "receiveSyncChangesObject",
+        unchecked_return_type = "Promise<ProcessedToDeviceEvent[]>"
+    )]
+    pub fn receive_sync_changes_object(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "Record<string, any>[]")] to_device_events: Array,
+        changed_devices: &sync_events::DeviceLists,
+        #[wasm_bindgen(unchecked_param_type = "Map<string, number>")] one_time_keys_counts: &Map,
+        #[wasm_bindgen(unchecked_optional_param_type = "Set<string>")] unused_fallback_keys: Option<
+            Set,
+        >,
+        #[wasm_bindgen(unchecked_optional_param_type = "DecryptionSettings")]
+        decryption_settings: Option<encryption::DecryptionSettings>,
+    ) -> Result<Promise, JsError> {
+        let to_device_events =
+            to_device_events.iter().map(types::js_object_to_raw).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(self.receive_sync_changes_helper(
+            to_device_events,
+            changed_devices,
+            one_time_keys_counts,
+            unused_fallback_keys,
+            decryption_settings,
+        ))
     }
 
     /// Get the outgoing requests that need to be sent out.
@@ -434,12 +477,18 @@ impl OlmMachine {
     ///   * {@link KeysClaimRequest},
     ///   * {@link ToDeviceRequest},
     ///   * {@link SignatureUploadRequest},
-    ///   * {@link RoomMessageRequest}, or
-    ///   * {@link KeysBackupRequest}.
+    ///   * {@link RoomMessageRequest},
+    ///   * {@link KeysBackupRequest}, or
+    ///   * {@link KeysBackupFetchRequest}.
     ///
     /// Those requests need to be sent out to the server and the
     /// responses need to be passed back to the state machine
     /// using {@link OlmMachine.markRequestAsSent}.
+    ///
+    /// A {@link KeysBackupFetchRequest} is returned for each room key which
+    /// was missing to decrypt an event, if the backup decryption key is
+    /// stored (see {@link OlmMachine.saveBackupDecryptionKey}). The same room
+    /// key is requested at most once every 5 minutes.
     #[wasm_bindgen(
         js_name = "outgoingRequests",
         unchecked_return_type = "Promise<OutgoingRequest[]>"
@@ -447,16 +496,23 @@ impl OlmMachine {
     pub fn outgoing_requests(&self) -> Promise {
         let _guard = dispatcher::set_default(&self.tracing_subscriber);
         let me = self.inner.clone();
+        let backup_fetcher = self.backup_fetcher.clone();
 
         future_to_promise(async move {
-            Ok(me
+            let requests = me
                 .outgoing_requests()
                 .await?
                 .into_iter()
                 .map(outgoing_request_to_js_value)
                 .collect::<Result<Vec<JsValue>, _>>()?
                 .into_iter()
-                .collect::<Array>())
+                .collect::<Array>();
+
+            for request in backup_fetcher.outgoing_requests(&me).await? {
+                requests.push(&request.into());
+            }
+
+            Ok(requests)
         })
     }
 
@@ -479,11 +535,37 @@ impl OlmMachine {
         response: &str,
     ) -> Result<Promise, JsError> {
         let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let me = self.inner.clone();
+
+        if let requests::RequestType::KeysBackupFetch = request_type {
+            let backup_fetcher = self.backup_fetcher.clone();
+            let request_id = request_id.to_owned();
+            let response = response.to_owned();
+
+            return Ok(future_to_promise(async move {
+                backup_fetcher.receive_response(&me, &request_id, &response).await?;
+                Ok(true)
+            }));
+        }
+
+        let is_keys_backup = matches!(request_type, requests::RequestType::KeysBackup);
         let transaction_id = OwnedTransactionId::from(request_id);
         let response = response_from_string(response)?;
         let incoming_response = responses::OwnedResponse::try_from((request_type, response))?;
 
-        let me = self.inner.clone();
+        if is_keys_backup {
+            let progress_listener = self.backup_progress_listener.borrow().clone();
+
+            return Ok(future_to_promise_with_custom_error::<_, _, JsValue>(async move {
+                me.mark_request_as_sent(&transaction_id, &incoming_response)
+                    .await
+                    .map_err(JsError::from)?;
+                if let Some(progress_listener) = &progress_listener {
+                    report_backup_progress(&me, progress_listener).await?;
+                }
+                Ok(true)
+            }));
+        }
 
         Ok(future_to_promise(async move {
             Ok(me.mark_request_as_sent(&transaction_id, &incoming_response).await.map(|_| true)?)
@@ -554,6 +636,33 @@ impl OlmMachine {
         }))
     }
 
+    /// Encrypt a room message for the given room.
+    ///
+    /// This is the same as {@link encryptRoomEvent}, except that the content
+    /// is given and returned as a plain object rather than as JSON.
+    #[wasm_bindgen(
+        js_name = "encryptRoomEventObject",
+        unchecked_return_type = "Promise<Record<string, any>>"
+    )]
+    pub fn encrypt_room_event_object(
+        &self,
+        room_id: &identifiers::RoomId,
+        event_type: String,
+        #[wasm_bindgen(unchecked_param_type = "Record<string, any>")] content: JsValue,
+    ) -> Result<Promise, JsError> {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let room_id = room_id.inner.clone();
+        let content = types::js_object_to_raw(content)?;
+        let me = self.inner.clone();
+
+        Ok(future_to_promise(async move {
+            let encryption_result =
+                me.encrypt_room_event_raw(&room_id, event_type.as_ref(), &content).await?;
+
+            types::json_to_js_object(encryption_result.content.json().get())
+        }))
+    }
+
     /// Encrypt a state event for the given room.
     ///
     /// This method encrypts a state event for the specified room, using the
@@ -607,6 +716,41 @@ impl OlmMachine {
         }))
     }
 
+    /// Encrypt a state event for the given room.
+    ///
+    /// This is the same as {@link encryptStateEvent}, except that the content
+    /// is given, and the encrypted event returned, as a plain object rather
+    /// than as JSON.
+    #[wasm_bindgen(
+        js_name = "encryptStateEventObject",
+        unchecked_return_type = "Promise<Record<string, any>>"
+    )]
+    pub fn encrypt_state_event_object(
+        &self,
+        room_id: &identifiers::RoomId,
+        event_type: String,
+        state_key: String,
+        #[wasm_bindgen(unchecked_param_type = "Record<string, any>")] content: JsValue,
+    ) -> Result<Promise, JsError> {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let room_id = room_id.inner.clone();
+        let content = types::js_object_to_raw(content)?;
+        let me = self.inner.clone();
+
+        Ok(future_to_promise(async move {
+            let encrypted = me
+                .encrypt_state_event_raw(
+                    &room_id,
+                    event_type.as_ref(),
+                    state_key.as_ref(),
+                    &content,
+                )
+                .await?;
+
+            types::json_to_js_object(encrypted.json().get())
+        }))
+    }
+
     /// Decrypt an event from a room timeline.
     ///
     /// # Arguments
@@ -628,32 +772,122 @@ impl OlmMachine {
         room_id: &identifiers::RoomId,
         decryption_settings: &encryption::DecryptionSettings,
     ) -> Result<Promise, JsError> {
-        let _guard = dispatcher::set_default(&self.tracing_subscriber);
-        let event: Raw<_> = serde_json::from_str(event)?;
-        let room_id = room_id.inner.clone();
-        let decryption_settings = decryption_settings.into();
-        let me = self.inner.clone();
+        let event = serde_json::from_str(event)?;
+        Ok(self.decrypt_room_event_promise(event, room_id, decryption_settings))
+    }
 
-        Ok(future_to_promise_with_custom_error::<
-            _,
-            responses::DecryptedRoomEvent,
-            MegolmDecryptionError,
-        >(async move {
-            let decrypted = me
-                .decrypt_room_event(&event, room_id.as_ref(), &decryption_settings)
-                .await
-                .map_err(MegolmDecryptionError::from)?;
-
-            responses::DecryptedRoomEvent::try_from(decrypted).map_err(
-                |e: UnsupportedAlgorithmError| {
-                    // This happens if we somehow encounter a room event whose encryption info we
-                    // don't understand (e.g., it is encrypted with Olm rather than
-                    // Megolm). That seems pretty unlikely. If it happens, let's
-                    // just treat it as a generic UTD.
-                    MegolmDecryptionError::unable_to_decrypt(format!("{e:#}"))
-                },
-            )
-        }))
+    /// Decrypt an event from a room timeline.
+    ///
+    /// This is the same as {@link decryptRoomEvent}, except that the event is
+    /// given as a plain object rather than as JSON. The decrypted event is
+    /// available as a plain object through
+    /// {@link DecryptedRoomEvent.eventObject}.
+    #[wasm_bindgen(
+        js_name = "decryptRoomEventObject",
+        unchecked_return_type = "Promise<DecryptedRoomEvent>"
+    )]
+    pub fn decrypt_room_event_object(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "Record<string, any>")] event: JsValue,
+        room_id: &identifiers::RoomId,
+        decryption_settings: &encryption::DecryptionSettings,
+    ) -> Result<Promise, JsError> {
+        let event = types::js_object_to_raw(event)?;
+        Ok(self.decrypt_room_event_promise(event, room_id, decryption_settings))
+    }
+
+    /// Decrypt a batch of events from a room timeline, for example when
+    /// back-paginating.
+    ///
+    /// The events are decrypted in order, as by {@link decryptRoomEvent}, but
+    /// each session is only read from the store once for the whole batch.
+    /// Once an event fails to decrypt because its room key is missing, the
+    /// other events encrypted with the same session fail with the same error.
+    ///
+    /// # Arguments
+    ///
+    /// * `events`, the JSON-encoded events that should be decrypted.
+    /// * `room_id`, the ID of the room where the events were sent to.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` which resolves to an array with one entry per event, in the
+    /// same order: either a {@link DecryptedRoomEvent} instance, or a
+    /// {@link MegolmDecryptionError} instance if that event couldn't be
+    /// decrypted.
+    #[wasm_bindgen(
+        js_name = "decryptRoomEvents",
+        unchecked_return_type = "Promise<Array<DecryptedRoomEvent | MegolmDecryptionError>>"
+    )]
+    pub fn decrypt_room_events(
+        &self,
+        events: Vec<String>,
+        room_id: &identifiers::RoomId,
+        decryption_settings: &encryption::DecryptionSettings,
+    ) -> Promise {
+        let events = events
+            .iter()
+            .map(|event| serde_json::from_str(event).map_err(|e| format!("{e:#}")))
+            .collect();
+
+        self.decrypt_room_events_promise(events, room_id, decryption_settings)
+    }
+
+    /// Decrypt a batch of events from a room timeline.
+    ///
+    /// This is the same as {@link decryptRoomEvents}, except that the events
+    /// are given as plain objects rather than as JSON. The decrypted events
+    /// are available as plain objects through
+    /// {@link DecryptedRoomEvent.eventObject}.
+    #[wasm_bindgen(
+        js_name = "decryptRoomEventsObject",
+        unchecked_return_type = "Promise<Array<DecryptedRoomEvent | MegolmDecryptionError>>"
+    )]
+    pub fn decrypt_room_events_object(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "Record<string, any>[]")] events: Array,
+        room_id: &identifiers::RoomId,
+        decryption_settings: &encryption::DecryptionSettings,
+    ) -> Promise {
+        let events = events
+            .iter()
+            .map(|event| types::js_object_to_raw(event).map_err(|e| format!("{e:#}")))
+            .collect();
+
+        self.decrypt_room_events_promise(events, room_id, decryption_settings)
+    }
+
+    /// Classify a failure to decrypt an event, to tell the expected failures
+    /// apart from the ones which may be caused by a bug.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event which couldn't be decrypted.
+    /// * `error` - The {@link MegolmDecryptionError} with which decrypting the
+    ///   event failed.
+    /// * `joined_at_ms` - The time at which we joined the room, in milliseconds
+    ///   since the Unix epoch, if known.
+    #[wasm_bindgen(js_name = "classifyDecryptionError")]
+    pub fn classify_decryption_error(
+        &self,
+        event: &str,
+        error: &MegolmDecryptionError,
+        joined_at_ms: Option<f64>,
+    ) -> Result<UtdClassification, JsError> {
+        let event: Raw<EncryptedEvent> = serde_json::from_str(event)?;
+        let event_timestamp_ms = event
+            .get_field::<ruma::MilliSecondsSinceUnixEpoch>("origin_server_ts")
+            .ok()
+            .flatten()
+            .map(|timestamp| timestamp.get().into());
+
+        Ok(UtdClassification::new(
+            error,
+            event_timestamp_ms,
+            utd_tracker::session_id(&event),
+            self.device_creation_time_ms(),
+            joined_at_ms,
+        ))
     }
 
     /// Get encryption info for a decrypted timeline event.
@@ -759,6 +993,182 @@ impl OlmMachine {
         })
     }
 
+    /// Set up recovery: create the cross-signing keys if needed, a new backup
+    /// key and a new secret storage key, and encrypt the secrets with the
+    /// latter.
+    ///
+    /// Nothing is uploaded or saved by this method. The caller should:
+    ///
+    /// 1. send the {@link RecoverySetup.crossSigningRequests},
+    /// 2. create the backup version with {@link
+    ///    RecoverySetup.backupVersionBody}, then activate it with {@link
+    ///    enableBackupV1} and save {@link RecoverySetup.backupDecryptionKey}
+    ///    with {@link saveBackupDecryptionKey},
+    /// 3. upload the {@link RecoverySetup.accountData} events.
+    ///
+    /// Until then, the current backup, if any, is left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - An optional passphrase to derive the secret storage key
+    ///   from. Otherwise, the key is random and can only be recovered with the
+    ///   recovery key.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for a {@link RecoverySetup}.
+    #[wasm_bindgen(js_name = "setupRecovery", unchecked_return_type = "Promise<RecoverySetup>")]
+    pub fn setup_recovery(&self, passphrase: Option<String>) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let me = self.inner.clone();
+
+        future_to_promise(async move {
+            let cross_signing_requests =
+                CrossSigningBootstrapRequests::try_from(me.bootstrap_cross_signing(false).await?)?;
+            let cross_signing_keys = me.export_cross_signing_keys().await?;
+            let Some(cross_signing_keys) = cross_signing_keys else {
+                return Err(JsError::new("The private cross-signing keys are not available"));
+            };
+
+            let backup_decryption_key = matrix_sdk_crypto::store::types::BackupDecryptionKey::new();
+
+            let public_key = backup_decryption_key.megolm_v1_public_key();
+            let auth_data = json!({ "public_key": public_key.to_base64() });
+            let signatures = me.sign(&serde_json::to_string(&auth_data)?).await?;
+            let backup_version_body = json!({
+                "algorithm": public_key.backup_algorithm(),
+                "auth_data": {
+                    "public_key": public_key.to_base64(),
+                    "signatures": signatures,
+                },
+            });
+
+            let key = match passphrase {
+                Some(passphrase) => {
+                    secret_storage::SecretStorageKey::create_from_passphrase(&passphrase)
+                }
+                None => secret_storage::SecretStorageKey::create_random_key(),
+            };
+
+            let mut account_data = BTreeMap::new();
+            account_data.insert(key.event_type(), key.event_content()?);
+            account_data.insert(
+                secret_storage::DEFAULT_KEY_EVENT_TYPE.to_owned(),
+                json!({ "key": key.key_id() }).to_string(),
+            );
+
+            let secrets = [
+                (SecretName::CrossSigningMasterKey, cross_signing_keys.master_key),
+                (SecretName::CrossSigningSelfSigningKey, cross_signing_keys.self_signing_key),
+                (SecretName::CrossSigningUserSigningKey, cross_signing_keys.user_signing_key),
+                (SecretName::RecoveryKey, Some(backup_decryption_key.to_base64())),
+            ];
+            for (secret_name, secret) in secrets {
+                let Some(secret) = secret else {
+                    return Err(JsError::new(&format!(
+                        "The secret {secret_name} is not available"
+                    )));
+                };
+                let content = key.encrypt_secret(secret_name.as_str(), &secret, None)?;
+                account_data.insert(secret_name.to_string(), content);
+            }
+
+            Ok(secret_storage::RecoverySetup {
+                recovery_key: key.to_recovery_key(),
+                cross_signing_requests,
+                backup_decryption_key: backup_decryption_key.into(),
+                backup_version_body: backup_version_body.to_string(),
+                account_data,
+            })
+        })
+    }
+
+    /// Recover the secrets set up with {@link setupRecovery}, possibly on
+    /// another device, and verify this device with them.
+    ///
+    /// The cross-signing keys and the backup key are decrypted from the
+    /// account data with the default secret storage key, and imported as with
+    /// {@link importSecretsBundle}.
+    ///
+    /// # Arguments
+    ///
+    /// * `recovery_key_or_passphrase` - The recovery key, or the passphrase the
+    ///   secret storage key was derived from.
+    /// * `account_data` - The account data of the user: a Map from event type
+    ///   to JSON-encoded content. It must contain at least the default key, its
+    ///   description and the cross-signing secrets.
+    /// * `backup_version` - The version of the current backup. If it is not
+    ///   given, the backup key is saved in the store without a version, and the
+    ///   backup has to be activated with {@link saveBackupDecryptionKey} and
+    ///   {@link enableBackupV1}.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for the {@link SignatureUploadRequest} which uploads the
+    /// signature of this device. It doesn't need to be passed to
+    /// {@link markRequestAsSent}.
+    #[wasm_bindgen(unchecked_return_type = "Promise<SignatureUploadRequest>")]
+    pub fn recover(
+        &self,
+        recovery_key_or_passphrase: String,
+        #[wasm_bindgen(unchecked_param_type = "Map<string, string>")] account_data: JsValue,
+        backup_version: Option<String>,
+    ) -> Result<Promise, JsError> {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let account_data: BTreeMap<String, String> = serde_wasm_bindgen::from_value(account_data)?;
+
+        let key = secret_storage::SecretStorageKey::from_default_key(
+            &recovery_key_or_passphrase,
+            &account_data,
+        )?;
+        let cross_signing_secret = |secret_name: SecretName| {
+            key.decrypt_from_account_data(&secret_name, &account_data)?.ok_or_else(|| {
+                JsError::new(&format!("The secret {secret_name} is not in the account data"))
+            })
+        };
+        let cross_signing = matrix_sdk_crypto::types::CrossSigningSecrets {
+            master_key: cross_signing_secret(SecretName::CrossSigningMasterKey)?,
+            self_signing_key: cross_signing_secret(SecretName::CrossSigningSelfSigningKey)?,
+            user_signing_key: cross_signing_secret(SecretName::CrossSigningUserSigningKey)?,
+        };
+        let backup_decryption_key = key
+            .decrypt_from_account_data(&SecretName::RecoveryKey, &account_data)?
+            .map(|key| matrix_sdk_crypto::store::types::BackupDecryptionKey::from_base64(&key))
+            .transpose()?;
+
+        let me = self.inner.clone();
+
+        Ok(future_to_promise(async move {
+            use matrix_sdk_crypto::types::{BackupSecrets, MegolmBackupV1Curve25519AesSha2Secrets};
+
+            let backup = match (backup_decryption_key, backup_version) {
+                (Some(key), Some(backup_version)) => {
+                    Some(BackupSecrets::MegolmBackupV1Curve25519AesSha2(
+                        MegolmBackupV1Curve25519AesSha2Secrets { key, backup_version },
+                    ))
+                }
+                (Some(key), None) => {
+                    me.backup_machine().save_decryption_key(Some(key), None).await?;
+                    None
+                }
+                (None, _) => None,
+            };
+
+            me.store()
+                .import_secrets_bundle(&matrix_sdk_crypto::types::SecretsBundle {
+                    cross_signing,
+                    backup,
+                })
+                .await?;
+
+            let Some(device) = me.get_device(me.user_id(), me.device_id(), None).await? else {
+                return Err(JsError::new("Our own device is not in the store"));
+            };
+
+            Ok(requests::SignatureUploadRequest::try_from(&device.verify().await?)?)
+        }))
+    }
+
     /// Export all the private cross signing keys we have.
     ///
     /// The export will contain the seeds for the ed25519 keys as
@@ -934,13 +1344,315 @@ impl OlmMachine {
         })
     }
 
-    /// Generate an "out-of-band" key query request for the given set of users.
+    /// Encrypt a to-device event for the devices of the given users, using Olm
+    /// encryption.
     ///
-    /// This can be useful if we need the results from `getIdentity` or
-    /// `getUserDevices` to be as up-to-date as possible.
+    /// The recipient devices are selected with `collect_strategy`. The event
+    /// is only encrypted for the devices we have an Olm session with: call
+    /// {@link getMissingSessions} beforehand to establish the missing ones.
+    /// Our own device is never a recipient.
     ///
-    /// Returns a `KeysQueryRequest` object. The response of the request should
-    /// be passed to the `OlmMachine` with the `mark_request_as_sent`.
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event to encrypt.
+    /// * `content` - The content of the event, as a plain object.
+    /// * `users` - The users whose devices should receive the event.
+    /// * `collect_strategy` - The strategy to select the recipient devices.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for an {@link EncryptToDeviceResult}, holding the
+    /// requests to send and the devices which were skipped.
+    #[wasm_bindgen(
+        js_name = "encryptToDeviceEventForUsers",
+        unchecked_return_type = "Promise<EncryptToDeviceResult>"
+    )]
+    pub fn encrypt_to_device_event_for_users(
+        &self,
+        event_type: String,
+        #[wasm_bindgen(unchecked_param_type = "Record<string, any>")] content: JsValue,
+        users: Vec<identifiers::UserId>,
+        collect_strategy: &encryption::CollectStrategy,
+    ) -> Result<Promise, JsError> {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let content = types::js_object_to_json(content)?;
+        let users = users.iter().map(|user| user.inner.clone()).collect::<Vec<_>>();
+        let collect_strategy = collect_strategy.clone().into();
+        let me = self.inner.clone();
+
+        Ok(future_to_promise(async move {
+            let mut devices = Vec::new();
+            for user_id in &users {
+                for device in me.get_user_devices(user_id, None).await?.devices() {
+                    if device.user_id() != me.user_id() || device.device_id() != me.device_id() {
+                        devices.push(device.deref().clone());
+                    }
+                }
+            }
+
+            let (requests, withheld) = me
+                .encrypt_content_for_devices(devices, &event_type, &content, collect_strategy)
+                .await?;
+
+            Ok(types::EncryptToDeviceResult {
+                requests: requests
+                    .iter()
+                    .map(|request| ToDeviceRequest::try_from(request.deref()).map(JsValue::from))
+                    .collect::<Result<Array, _>>()?,
+                skipped: withheld
+                    .into_iter()
+                    .map(|(device, code)| types::SkippedDevice {
+                        user_id: device.user_id().to_owned().into(),
+                        device_id: device.device_id().to_owned().into(),
+                        reason: code.as_str().to_owned(),
+                    })
+                    .collect(),
+            })
+        }))
+    }
+
+    /// Get the state of the current outbound group session of the given room,
+    /// to debug why some recipients can't decrypt our messages.
+    ///
+    /// The session is the one the machine saves to the store each time it
+    /// shares, uses or invalidates it.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room.
+    /// * `encryption_settings` - The current encryption settings of the room,
+    ///   as would be passed to {@link shareRoomKey}, to tell whether the
+    ///   session is due for rotation.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for an {@link OutboundGroupSessionInfo}, or `undefined` if
+    /// there is no outbound group session for the room.
+    #[wasm_bindgen(
+        js_name = "getOutboundGroupSessionInfo",
+        unchecked_return_type = "Promise<OutboundGroupSessionInfo | undefined>"
+    )]
+    pub fn get_outbound_group_session_info(
+        &self,
+        room_id: &identifiers::RoomId,
+        encryption_settings: &encryption::EncryptionSettings,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let room_id = room_id.inner.clone();
+        let encryption_settings =
+            matrix_sdk_crypto::olm::EncryptionSettings::from(encryption_settings);
+        let me = self.inner.clone();
+
+        future_to_promise(async move {
+            let Some(session) =
+                me.store().crypto_store().get_outbound_group_session(&room_id).await?
+            else {
+                return Ok(None);
+            };
+
+            Ok(Some(types::OutboundGroupSessionInfo::new(&session, &encryption_settings).await))
+        })
+    }
+
+    /// Work out which devices would receive the room key if
+    /// {@link shareRoomKey} was called with the same arguments, and which
+    /// wouldn't, and why.
+    ///
+    /// This is a dry run: no session is created, shared or modified. The
+    /// result is based on the devices and identities currently in the store,
+    /// so the outgoing key queries should be sent out first. Our own device is
+    /// never a recipient.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room.
+    /// * `users` - The users whose devices would receive the room key.
+    /// * `encryption_settings` - The settings which would be passed to {@link
+    ///   shareRoomKey}.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for a {@link RoomKeySharePlan}.
+    #[wasm_bindgen(
+        js_name = "planRoomKeyShare",
+        unchecked_return_type = "Promise<RoomKeySharePlan>"
+    )]
+    pub fn plan_room_key_share(
+        &self,
+        room_id: &identifiers::RoomId,
+        users: Vec<identifiers::UserId>,
+        encryption_settings: &encryption::EncryptionSettings,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let room_id = room_id.inner.clone();
+        let users = users.iter().map(|user| user.inner.clone()).collect::<Vec<_>>();
+        let strategy =
+            matrix_sdk_crypto::olm::EncryptionSettings::from(encryption_settings).sharing_strategy;
+        let me = self.inner.clone();
+
+        future_to_promise(async move {
+            use matrix_sdk_crypto::{CollectStrategy, UserIdentity};
+
+            let store = me.store().crypto_store();
+            let mut plan = types::RoomKeySharePlan::new();
+
+            // The identity-based strategy refuses to share without a cross-signing
+            // identity, or from a device which isn't signed by it.
+            if matches!(strategy, CollectStrategy::IdentityBasedStrategy) {
+                let own_identity = me.get_identity(me.user_id(), None).await?;
+                let own_device = me.get_device(me.user_id(), me.device_id(), None).await?;
+                if !matches!(own_identity, Some(UserIdentity::Own(_)))
+                    || !own_device.is_some_and(|device| device.is_cross_signed_by_owner())
+                {
+                    plan.would_fail = true;
+                }
+            }
+
+            for user_id in &users {
+                let owner_identity = me.get_identity(user_id, None).await?;
+
+                for device in me.get_user_devices(user_id, None).await?.devices() {
+                    if device.user_id() == me.user_id() && device.device_id() == me.device_id() {
+                        continue;
+                    }
+
+                    let reason =
+                        share_strategy_withheld_reason(&strategy, &device, owner_identity.as_ref());
+
+                    let reason = match (reason, device.curve25519_key()) {
+                        (Some(reason), _) => Some(reason),
+                        (None, Some(sender_key)) => store
+                            .get_sessions(&sender_key.to_base64())
+                            .await?
+                            .map_or(true, |sessions| sessions.is_empty())
+                            .then_some(types::RoomKeyWithheldReason::NoOlmSession),
+                        (None, None) => Some(types::RoomKeyWithheldReason::NoOlmSession),
+                    };
+
+                    match reason {
+                        Some(reason) => {
+                            plan.add_withheld(user_id.as_str(), device.device_id().as_str(), reason)
+                        }
+                        None => plan.add_recipient(user_id.as_str(), device.device_id().as_str()),
+                    }
+                }
+            }
+
+            info!(?room_id, would_fail = plan.would_fail, "Planned the room key share");
+
+            Ok(plan)
+        })
+    }
+
+    /// Prepare a room for encryption by sharing a room key with the devices of
+    /// its members, sending out the required requests with `transport`.
+    ///
+    /// This drives the whole pipeline: the members are added to the tracked
+    /// users (see {@link updateTrackedUsers}), their keys are queried (see
+    /// {@link queryKeysForUsers}), Olm sessions are established with the
+    /// devices we have none with (see {@link getMissingSessions}), and the
+    /// room key is shared (see {@link shareRoomKey}). Each request is marked
+    /// as sent with the response returned by `transport`.
+    ///
+    /// The same care as with {@link shareRoomKey} should be taken that only
+    /// one such call at a time is in flight for the same room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room.
+    /// * `members` - The members of the room.
+    /// * `encryption_settings` - The settings to share the room key with.
+    /// * `transport` - A callback which sends out the given request, and
+    ///   resolves to the body of the response. If it throws or rejects, this
+    ///   method rejects with the same error.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` which resolves once the room key has been shared.
+    ///
+    /// Items inside `members` will be invalidated by this method. Be careful
+    /// not to use the `UserId`s after this method has been called.
+    #[wasm_bindgen(js_name = "prepareRoomForEncryption", unchecked_return_type = "Promise<void>")]
+    pub fn prepare_room_for_encryption(
+        &self,
+        room_id: &identifiers::RoomId,
+        members: Vec<identifiers::UserId>,
+        encryption_settings: &encryption::EncryptionSettings,
+        #[wasm_bindgen(unchecked_param_type = "(request: OutgoingRequest) => Promise<string>")]
+        transport: Function,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let room_id = room_id.inner.clone();
+        let members = members.iter().map(|user| user.inner.clone()).collect::<Vec<_>>();
+        let encryption_settings =
+            matrix_sdk_crypto::olm::EncryptionSettings::from(encryption_settings);
+        let me = self.inner.clone();
+
+        future_to_promise_with_custom_error::<_, _, JsValue>(async move {
+            me.update_tracked_users(members.iter().map(AsRef::as_ref))
+                .await
+                .map_err(JsError::from)?;
+
+            // Get the device lists of the members up to date.
+            let (request_id, request) = me.query_keys_for_users(members.iter().map(AsRef::as_ref));
+            let request_id = request_id.to_string();
+            let request = requests::KeysQueryRequest::try_from((request_id.clone(), &request))
+                .map_err(JsError::from)?;
+            Self::send_with_transport(
+                &me,
+                &transport,
+                &request_id,
+                requests::RequestType::KeysQuery,
+                request.into(),
+            )
+            .await?;
+
+            if let Some((transaction_id, request)) = me
+                .get_missing_sessions(members.iter().map(AsRef::as_ref))
+                .await
+                .map_err(JsError::from)?
+            {
+                let request_id = transaction_id.to_string();
+                let request = requests::KeysClaimRequest::try_from((request_id.clone(), &request))
+                    .map_err(JsError::from)?;
+                Self::send_with_transport(
+                    &me,
+                    &transport,
+                    &request_id,
+                    requests::RequestType::KeysClaim,
+                    request.into(),
+                )
+                .await?;
+            }
+
+            let to_device_requests = me
+                .share_room_key(&room_id, members.iter().map(AsRef::as_ref), encryption_settings)
+                .await
+                .map_err(JsError::from)?;
+            for request in to_device_requests {
+                let request_id = request.txn_id.to_string();
+                let request = ToDeviceRequest::try_from(request.deref()).map_err(JsError::from)?;
+                Self::send_with_transport(
+                    &me,
+                    &transport,
+                    &request_id,
+                    requests::RequestType::ToDevice,
+                    request.into(),
+                )
+                .await?;
+            }
+
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Generate an "out-of-band" key query request for the given set of users.
+    ///
+    /// This can be useful if we need the results from `getIdentity` or
+    /// `getUserDevices` to be as up-to-date as possible.
+    ///
+    /// Returns a `KeysQueryRequest` object. The response of the request should
+    /// be passed to the `OlmMachine` with the `mark_request_as_sent`.
     ///
     /// Items inside `users` will be invalidated by this method. Be careful not
     /// to use the `UserId`s after this method has been called.
@@ -1475,28 +2187,109 @@ impl OlmMachine {
         })
     }
 
+    /// Reset the backup state to start backing up to a new backup version,
+    /// for example once the user has reset their backup.
+    ///
+    /// The new backup replaces the active one, and all the room keys are
+    /// marked as not backed up, so that {@link OlmMachine.backupRoomKeys}
+    /// uploads them all again. Any pending {@link KeysBackupRequest} for the
+    /// previous backup is discarded.
+    ///
+    /// The new version is stored as the backup version (see
+    /// {@link OlmMachine.getBackupKeys}). The stored backup decryption key is
+    /// left as it is: if it doesn't match the new public key, the key of the
+    /// new backup should be saved with {@link
+    /// OlmMachine.saveBackupDecryptionKey}.
+    ///
+    /// The backup state of the room keys is reset in the store, then the new
+    /// version is stored, and only then is the backup switched over. If one of
+    /// the first two steps fails, the previous backup stays active; at worst,
+    /// some of its room keys are uploaded to it again.
+    ///
+    /// **Warning**: As with {@link OlmMachine.enableBackupV1}, the caller
+    /// needs to make sure that the new public key is trusted.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_version` - The version of the new backup.
+    /// * `new_public_key` - The base64-encoded public key of the new backup.
+    #[wasm_bindgen(js_name = "resetBackupState", unchecked_return_type = "Promise<void>")]
+    pub fn reset_backup_state(
+        &self,
+        new_version: String,
+        new_public_key: String,
+    ) -> Result<Promise, JsError> {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let backup_key = MegolmV1BackupKey::from_base64(&new_public_key)?;
+        backup_key.set_version(new_version.clone());
+
+        let me = self.inner.clone();
+
+        Ok(future_to_promise(async move {
+            let backup_machine = me.backup_machine();
+
+            me.store().crypto_store().reset_backup_state().await?;
+            backup_machine.save_decryption_key(None, Some(new_version)).await?;
+
+            // The store is in its new state, so switch over even if disabling the previous
+            // backup fails to reset the backup state of the room keys once more: it only
+            // has to drop the previous backup key and its pending request.
+            if let Err(e) = backup_machine.disable_backup().await {
+                warn!("Error resetting the backup state of the room keys again: {e}");
+            }
+            backup_machine.enable_backup_v1(backup_key).await?;
+
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+
     /// Encrypt a batch of room keys and return a request that needs to be sent
     /// out to backup the room keys.
     ///
+    /// # Arguments
+    ///
+    /// * `progress_listener` - An optional callback, called with the number of
+    ///   room keys backed up to the active backup so far and the total number
+    ///   of room keys, as returned by {@link OlmMachine.roomKeyCounts}. It is
+    ///   called once the returned request has been marked as sent with {@link
+    ///   OlmMachine.markRequestAsSent}, so that the counts include its room
+    ///   keys, or straight away if the backup is already complete, in which
+    ///   case both numbers are equal. If it throws, the promise returned by
+    ///   either method rejects with the same error.
+    ///
     /// Returns an optional {@link KeysBackupRequest}.
     #[wasm_bindgen(
         js_name = "backupRoomKeys",
         unchecked_return_type = "Promise<KeysBackupRequest | undefined>"
     )]
-    pub fn backup_room_keys(&self) -> Promise {
+    pub fn backup_room_keys(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "(backedUp: number, total: number) => void")]
+        progress_listener: Option<Function>,
+    ) -> Promise {
         let _guard = dispatcher::set_default(&self.tracing_subscriber);
         let me = self.inner.clone();
+        let backup_progress_listener = self.backup_progress_listener.clone();
 
-        future_to_promise(async move {
-            match me.backup_machine().backup().await? {
-                Some((transaction_id, keys_backup_request)) => {
-                    Ok(Some(requests::KeysBackupRequest::try_from((
+        future_to_promise_with_custom_error::<_, _, JsValue>(async move {
+            let request = me.backup_machine().backup().await.map_err(JsError::from)?;
+            *backup_progress_listener.borrow_mut() = progress_listener.clone();
+
+            match request {
+                Some((transaction_id, keys_backup_request)) => Ok(Some(
+                    requests::KeysBackupRequest::try_from((
                         transaction_id.to_string(),
                         &keys_backup_request,
-                    ))?))
-                }
+                    ))
+                    .map_err(JsError::from)?,
+                )),
 
-                None => Ok(None),
+                None => {
+                    if let Some(progress_listener) = &progress_listener {
+                        report_backup_progress(&me, progress_listener).await?;
+                    }
+                    Ok(None)
+                }
             }
         })
     }
@@ -1618,6 +2411,33 @@ impl OlmMachine {
         );
     }
 
+    /// Register a callback which will be called with the events which
+    /// couldn't be decrypted earlier, once their room key arrives.
+    ///
+    /// Once a callback is registered, the events for which
+    /// {@link decryptRoomEvent} or {@link decryptRoomEvents} fail with
+    /// {@link DecryptionErrorCode.MissingRoomKey} or
+    /// {@link DecryptionErrorCode.UnknownMessageIndex} are remembered, and
+    /// decrypted again when a matching room key is received: from a sync, a
+    /// key forward, a backup or a room key bundle. Up to 1000 events are
+    /// remembered at a time; past that, the oldest ones are forgotten.
+    ///
+    /// `callback` should be a function that takes two arguments (the
+    /// {@link RoomId} of the room, and an array of {@link DecryptedRoomEvent})
+    /// and returns a Promise. Registering a new callback replaces the previous
+    /// one.
+    #[wasm_bindgen(js_name = "registerEventsDecryptedCallback")]
+    pub fn register_events_decrypted_callback(
+        &self,
+        #[wasm_bindgen(
+            unchecked_param_type = "(roomId: RoomId, events: DecryptedRoomEvent[]) => Promise<void>"
+        )]
+        callback: Function,
+    ) {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        self.utd_tracker.start(self.inner.clone(), callback, &self.tracing_subscriber);
+    }
+
     /// Register a callback which will be called whenever we receive a
     /// notification that some room keys have been withheld.
     ///
@@ -2191,16 +3011,393 @@ impl OlmMachine {
         })
     }
 
+    /// Get the inbound group sessions we have for the given room.
+    ///
+    /// This is meant for debugging, to explain why a given event can or
+    /// can't be decrypted. Note that the crypto store doesn't keep track of
+    /// forwarding chains: use {@link InboundGroupSession.hasBeenImported} to
+    /// find out whether a session was received directly from its sender.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room.
+    #[wasm_bindgen(
+        js_name = "getInboundGroupSessions",
+        unchecked_return_type = "Promise<InboundGroupSession[]>"
+    )]
+    pub fn get_inbound_group_sessions(&self, room_id: &identifiers::RoomId) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let me = self.inner.clone();
+        let room_id = room_id.inner.clone();
+
+        future_to_promise(async move {
+            let store = me.store().crypto_store();
+            let not_backed_up = match current_backup_version(&me).await? {
+                Some(version) => Some(sessions_not_backed_up(&store, &version).await?),
+                None => None,
+            };
+
+            let sessions = Array::new();
+            for session in store.get_inbound_group_sessions_by_room_id(&room_id).await? {
+                let session_id = session.session_id();
+                let backed_up = not_backed_up.as_ref().is_some_and(|not_backed_up| {
+                    !not_backed_up.contains(&(room_id.clone(), session_id.to_owned()))
+                });
+                let stored_at = store::session_times::get(&store, &room_id, session_id).await?;
+
+                sessions.push(
+                    &olm::InboundGroupSession::with_store_info(session, backed_up, stored_at)
+                        .into(),
+                );
+            }
+
+            Ok(sessions)
+        })
+    }
+
+    /// Delete the inbound group sessions matching the given policy, to keep
+    /// the size of the store under control.
+    ///
+    /// Only sessions which have been backed up to the current backup are ever
+    /// deleted, so that no key is lost: see {@link OlmMachine.roomKeyCounts}.
+    /// The call is rejected if no backup is enabled, or if its version is not
+    /// stored with its decryption key. The age of a session is the time since
+    /// it was first stored by this library, which is unknown for sessions
+    /// stored by older versions.
+    ///
+    /// Sessions can be deleted from IndexedDB-based stores, and from stores
+    /// opened with {@link StoreHandle.openCustom}. For memory stores, only dry
+    /// runs are supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - A {@link PrunePolicy} object.
+    #[wasm_bindgen(
+        js_name = "pruneInboundGroupSessions",
+        unchecked_return_type = "Promise<PruneResult>"
+    )]
+    pub fn prune_inbound_group_sessions(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "PrunePolicy")] policy: JsValue,
+    ) -> Result<Promise, JsError> {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let me = self.inner.clone();
+        let store_backend = self.store_backend.clone();
+        let session_times_lock = self.session_times_lock.clone();
+        let inbound_group_session_cache = self.inbound_group_session_cache.clone();
+        let policy: types::PrunePolicy = serde_wasm_bindgen::from_value(policy)?;
+
+        Ok(future_to_promise(async move {
+            let store = me.store().crypto_store();
+            let now = u64::from(ruma::MilliSecondsSinceUnixEpoch::now().get());
+
+            if current_backup_version(&me).await?.is_none() {
+                return Err(JsError::new(
+                    "Inbound group sessions can only be pruned while a backup is enabled, \
+                    and its version is stored",
+                ));
+            }
+
+            let candidates = match &policy.room_ids {
+                Some(room_ids) => {
+                    let mut candidates = Vec::new();
+                    for room_id in room_ids {
+                        candidates
+                            .extend(store.get_inbound_group_sessions_by_room_id(room_id).await?);
+                    }
+                    candidates
+                }
+                None => store.get_inbound_group_sessions().await?,
+            };
+
+            let mut sessions = BTreeMap::<_, BTreeSet<_>>::new();
+            for session in candidates {
+                let room_id = session.room_id();
+                let session_id = session.session_id();
+
+                // The backup state of the sessions is reset whenever the backup
+                // changes, so this means backed up to the current backup.
+                if !session.backed_up() {
+                    continue;
+                }
+
+                if let Some(older_than_ms) = policy.older_than_ms {
+                    match store::session_times::get(&store, room_id, session_id).await? {
+                        Some(time) if now.saturating_sub(time) as f64 > older_than_ms => {}
+                        _ => continue,
+                    }
+                }
+
+                sessions.entry(room_id.to_owned()).or_default().insert(session_id.to_owned());
+            }
+
+            if !policy.dry_run {
+                let _guard = session_times_lock.lock().await;
+                let deleted = store_backend.delete_inbound_group_sessions(&sessions).await;
+                inbound_group_session_cache.clear();
+                deleted?;
+
+                for (room_id, session_ids) in &sessions {
+                    store::session_times::remove(
+                        &store,
+                        room_id,
+                        session_ids.iter().map(String::as_str),
+                    )
+                    .await?;
+                }
+            }
+
+            Ok(types::PruneResult {
+                dry_run: policy.dry_run,
+                count: sessions.values().map(BTreeSet::len).sum(),
+                sessions,
+            })
+        }))
+    }
+
+    /// Collect statistics about the content of the store backing this
+    /// machine.
+    ///
+    /// See {@link StoreHandle.stats}.
+    #[wasm_bindgen(js_name = "storeStats", unchecked_return_type = "Promise<StoreStats>")]
+    pub fn store_stats(&self) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let me = self.inner.clone();
+        let store_backend = self.store_backend.clone();
+
+        future_to_promise(async move {
+            store::store_stats(&me.store().crypto_store(), &store_backend).await
+        })
+    }
+
     /// Shut down the `OlmMachine`.
     ///
     /// The `OlmMachine` cannot be used after this method has been called.
     ///
     /// All associated resources will be closed too, like IndexedDB
     /// connections.
-    pub fn close(self) {}
+    ///
+    /// # Arguments
+    ///
+    /// * `wipe` - If `true`, all the data of the store backing this machine is
+    ///   deleted too. For an IndexedDB-based store, the `StoreHandle` used to
+    ///   create the machine, if any, must have been freed first; see {@link
+    ///   StoreHandle.delete}.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` which resolves once the background tasks of the machine,
+    /// such as the one started by {@link registerEventsDecryptedCallback},
+    /// have stopped, and the store has been wiped, if requested.
+    #[wasm_bindgen(unchecked_return_type = "Promise<void>")]
+    pub fn close(self, wipe: Option<bool>) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let Self { inner, store_backend, utd_tracker, background_tasks, .. } = self;
+
+        // Drop our reference to the store, and wait for the background tasks
+        // holding one to stop before wiping it, so that its connections are
+        // closed.
+        drop(inner);
+
+        future_to_promise(async move {
+            utd_tracker.stop().await;
+            let background_tasks = std::mem::take(&mut *background_tasks.borrow_mut());
+            for task in background_tasks {
+                task.abort().await;
+            }
+
+            if wipe.unwrap_or(false) {
+                store_backend.wipe().await?;
+            }
+
+            Ok(JsValue::UNDEFINED)
+        })
+    }
 }
 
 impl OlmMachine {
+    /// Shared helper for `receive_sync_changes` and
+    /// `receive_sync_changes_object`.
+    fn receive_sync_changes_helper(
+        &self,
+        to_device_events: Vec<Raw<ruma::events::AnyToDeviceEvent>>,
+        changed_devices: &sync_events::DeviceLists,
+        one_time_keys_counts: &Map,
+        unused_fallback_keys: Option<Set>,
+        decryption_settings: Option<encryption::DecryptionSettings>,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let changed_devices = changed_devices.inner.clone();
+        let one_time_keys_counts: BTreeMap<OneTimeKeyAlgorithm, UInt> = one_time_keys_counts
+            .entries()
+            .into_iter()
+            .filter_map(|js_value| {
+                let pair = Array::from(&js_value.ok()?);
+                let (key, value) = (
+                    OneTimeKeyAlgorithm::from(pair.at(0).as_string()?),
+                    UInt::new(pair.at(1).as_f64()? as u64)?,
+                );
+
+                Some((key, value))
+            })
+            .collect();
+
+        // Convert the unused_fallback_keys JS Set to a `Vec<OneTimeKeyAlgorithm>`
+        let unused_fallback_keys: Option<Vec<OneTimeKeyAlgorithm>> =
+            unused_fallback_keys.map(|fallback_keys| {
+                fallback_keys
+                    .values()
+                    .into_iter()
+                    .filter_map(|js_value| {
+                        Some(OneTimeKeyAlgorithm::from(js_value.ok()?.as_string()?))
+                    })
+                    .collect()
+            });
+
+        let me = self.inner.clone();
+        let decryption_settings = (&decryption_settings.unwrap_or(
+            encryption::DecryptionSettings::new(encryption::TrustRequirement::Untrusted),
+        ))
+            .into();
+
+        future_to_promise(async move {
+            // we discard the list of updated room keys in the result; JS applications are
+            // expected to use register_room_key_updated_callback to receive updated room
+            // keys.
+            let (processed_to_device_events, _) = me
+                .receive_sync_changes(
+                    EncryptionSyncChanges {
+                        to_device_events,
+                        changed_devices: &changed_devices,
+                        one_time_keys_counts: &one_time_keys_counts,
+                        unused_fallback_keys: unused_fallback_keys.as_deref(),
+
+                        // matrix-sdk-crypto does not (currently) use `next_batch_token`.
+                        next_batch_token: None,
+                    },
+                    &decryption_settings,
+                )
+                .await?;
+
+            Ok(processed_to_device_events
+                .into_iter()
+                .filter_map(processed_to_device_event_to_js_value)
+                .collect::<Vec<_>>())
+        })
+    }
+
+    /// Shared helper for `decrypt_room_events` and
+    /// `decrypt_room_events_object`, given the events or the errors with which
+    /// they couldn't be read.
+    fn decrypt_room_events_promise(
+        &self,
+        events: Vec<Result<Raw<EncryptedEvent>, String>>,
+        room_id: &identifiers::RoomId,
+        decryption_settings: &encryption::DecryptionSettings,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let room_id = room_id.inner.clone();
+        let decryption_settings = decryption_settings.into();
+        let me = self.inner.clone();
+        let utd_tracker = self.utd_tracker.clone();
+        let backup_fetcher = self.backup_fetcher.clone();
+        let inbound_group_session_cache = self.inbound_group_session_cache.clone();
+
+        future_to_promise(async move {
+            let _batch = inbound_group_session_cache.batch();
+            let results = Array::new();
+            let mut missing_sessions = HashMap::<String, MegolmDecryptionError>::new();
+
+            for event in events {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        let error = MegolmDecryptionError::unable_to_decrypt(e);
+                        results.push(&error.into());
+                        continue;
+                    }
+                };
+
+                let session_id = utd_tracker::session_id(&event);
+
+                if let Some(error) = session_id.as_ref().and_then(|id| missing_sessions.get(id)) {
+                    utd_tracker.track(&room_id, &event, &decryption_settings, error.code);
+                    backup_fetcher.missing_room_key(&room_id, &event, error.code);
+                    results.push(&error.clone().into());
+                    continue;
+                }
+
+                match Self::decrypt_room_event_helper(&me, &event, &room_id, &decryption_settings)
+                    .await
+                {
+                    Ok(decrypted) => results.push(&decrypted.into()),
+                    Err(error) => {
+                        utd_tracker.track(&room_id, &event, &decryption_settings, error.code);
+                        backup_fetcher.missing_room_key(&room_id, &event, error.code);
+
+                        if let (Some(session_id), DecryptionErrorCode::MissingRoomKey) =
+                            (session_id, error.code)
+                        {
+                            missing_sessions.insert(session_id, error.clone());
+                        }
+                        results.push(&error.into())
+                    }
+                };
+            }
+
+            Ok(results)
+        })
+    }
+
+    /// Shared helper for `decrypt_room_event` and `decrypt_room_event_object`.
+    fn decrypt_room_event_promise(
+        &self,
+        event: Raw<EncryptedEvent>,
+        room_id: &identifiers::RoomId,
+        decryption_settings: &encryption::DecryptionSettings,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&self.tracing_subscriber);
+        let room_id = room_id.inner.clone();
+        let decryption_settings = decryption_settings.into();
+        let me = self.inner.clone();
+        let utd_tracker = self.utd_tracker.clone();
+        let backup_fetcher = self.backup_fetcher.clone();
+
+        future_to_promise_with_custom_error::<_, responses::DecryptedRoomEvent, MegolmDecryptionError>(
+            async move {
+                Self::decrypt_room_event_helper(&me, &event, &room_id, &decryption_settings)
+                    .await
+                    .inspect_err(|error| {
+                        utd_tracker.track(&room_id, &event, &decryption_settings, error.code);
+                        backup_fetcher.missing_room_key(&room_id, &event, error.code);
+                    })
+            },
+        )
+    }
+
+    /// Shared helper for `decrypt_room_event` and `decrypt_room_events`.
+    pub(crate) async fn decrypt_room_event_helper(
+        inner: &matrix_sdk_crypto::OlmMachine,
+        event: &Raw<EncryptedEvent>,
+        room_id: &ruma::RoomId,
+        decryption_settings: &matrix_sdk_crypto::DecryptionSettings,
+    ) -> Result<responses::DecryptedRoomEvent, MegolmDecryptionError> {
+        let decrypted = inner
+            .decrypt_room_event(event, room_id, decryption_settings)
+            .await
+            .map_err(MegolmDecryptionError::from)?;
+
+        responses::DecryptedRoomEvent::try_from(decrypted).map_err(
+            |e: UnsupportedAlgorithmError| {
+                // This happens if we somehow encounter a room event whose encryption info we
+                // don't understand (e.g., it is encrypted with Olm rather than
+                // Megolm). That seems pretty unlikely. If it happens, let's
+                // just treat it as a generic UTD.
+                MegolmDecryptionError::unable_to_decrypt(format!("{e:#}"))
+            },
+        )
+    }
+
     /// Shared helper for `import_exported_room_keys` and `import_room_keys`.
     ///
     /// Wraps the progress listener in a Rust closure and runs
@@ -2219,6 +3416,191 @@ impl OlmMachine {
             })
             .await
     }
+
+    /// Send an outgoing request with the `transport` passed to
+    /// `prepare_room_for_encryption`, and mark it as sent with the response.
+    async fn send_with_transport(
+        inner: &matrix_sdk_crypto::OlmMachine,
+        transport: &Function,
+        request_id: &str,
+        request_type: requests::RequestType,
+        request: JsValue,
+    ) -> Result<(), JsValue> {
+        let response = promise_result_to_future(transport.call1(&JsValue::NULL, &request)).await?;
+        let response = response.as_string().ok_or_else(|| {
+            JsError::new("prepareRoomForEncryption: the transport must resolve to a string")
+        })?;
+        let response = response_from_string(&response).map_err(JsError::from)?;
+        let response = responses::OwnedResponse::try_from((request_type, response))?;
+
+        inner
+            .mark_request_as_sent(&OwnedTransactionId::from(request_id), &response)
+            .await
+            .map_err(JsError::from)?;
+
+        Ok(())
+    }
+}
+
+/// Why the room key would be withheld from `device` when sharing it with
+/// `strategy`, if it would be.
+///
+/// matrix-sdk-crypto doesn't expose its share strategy, so this follows the
+/// rules of its `share_strategy` module, strategy by strategy: blacklisted
+/// devices, devices ignored by the user, dehydrated devices, and verification
+/// violations of the owner of the device are handled the same way. The checks
+/// of our own device, which make the whole share fail, are done by
+/// `plan_room_key_share`.
+fn share_strategy_withheld_reason(
+    strategy: &matrix_sdk_crypto::CollectStrategy,
+    device: &matrix_sdk_crypto::Device,
+    owner_identity: Option<&matrix_sdk_crypto::UserIdentity>,
+) -> Option<types::RoomKeyWithheldReason> {
+    use matrix_sdk_crypto::{CollectStrategy, LocalTrust, UserIdentity};
+
+    let (owner_verified, owner_violation) = match owner_identity {
+        Some(UserIdentity::Own(identity)) => {
+            (identity.is_verified(), identity.has_verification_violation())
+        }
+        Some(UserIdentity::Other(identity)) => {
+            (identity.is_verified(), identity.has_verification_violation())
+        }
+        None => (false, false),
+    };
+
+    // Dehydrated devices must be signed by their owner, and their owner must
+    // not be in verification violation.
+    let withhold_dehydrated = device.is_dehydrated()
+        && (owner_identity.is_none() || !device.is_cross_signed_by_owner() || owner_violation);
+
+    match strategy {
+        CollectStrategy::AllDevices => {
+            if device.is_blacklisted() {
+                Some(types::RoomKeyWithheldReason::Blacklisted)
+            } else if withhold_dehydrated {
+                Some(types::RoomKeyWithheldReason::Unverified)
+            } else {
+                None
+            }
+        }
+        CollectStrategy::ErrorOnVerifiedUserProblem => {
+            if owner_violation {
+                Some(types::RoomKeyWithheldReason::VerificationViolation)
+            } else if device.is_blacklisted() {
+                Some(types::RoomKeyWithheldReason::Blacklisted)
+            } else if device.local_trust_state() == LocalTrust::Ignored {
+                None
+            } else if owner_verified && !device.is_cross_signed_by_owner() {
+                Some(types::RoomKeyWithheldReason::VerificationViolation)
+            } else if withhold_dehydrated {
+                Some(types::RoomKeyWithheldReason::Unverified)
+            } else {
+                None
+            }
+        }
+        CollectStrategy::IdentityBasedStrategy => {
+            if owner_violation {
+                Some(types::RoomKeyWithheldReason::VerificationViolation)
+            } else if !device.is_cross_signed_by_owner() {
+                Some(types::RoomKeyWithheldReason::Unverified)
+            } else {
+                None
+            }
+        }
+        CollectStrategy::OnlyTrustedDevices => match device.local_trust_state() {
+            LocalTrust::BlackListed => Some(types::RoomKeyWithheldReason::Blacklisted),
+            LocalTrust::Ignored | LocalTrust::Verified => None,
+            LocalTrust::Unset => (!device.is_cross_signing_trusted())
+                .then_some(types::RoomKeyWithheldReason::Unverified),
+        },
+    }
+}
+
+/// The version of the current backup: the backup must be enabled, and its
+/// version stored along with its decryption key.
+async fn current_backup_version(
+    machine: &matrix_sdk_crypto::OlmMachine,
+) -> Result<Option<String>, CryptoStoreError> {
+    let backup_machine = machine.backup_machine();
+
+    Ok(match backup_machine.get_backup_keys().await?.backup_version {
+        Some(version) if backup_machine.enabled().await => Some(version),
+        _ => None,
+    })
+}
+
+/// The room and session IDs of the inbound group sessions which have not been
+/// backed up to the given backup yet.
+///
+/// The store keeps track of the backup each session was backed up to, unlike
+/// [`matrix_sdk_crypto::olm::InboundGroupSession::backed_up`] which can be
+/// stale once the backup changes.
+async fn sessions_not_backed_up(
+    store: &DynCryptoStore,
+    backup_version: &str,
+) -> Result<BTreeSet<(OwnedRoomId, String)>, CryptoStoreError> {
+    Ok(store
+        .inbound_group_sessions_for_backup(backup_version, usize::MAX)
+        .await?
+        .iter()
+        .map(|session| (session.room_id().to_owned(), session.session_id().to_owned()))
+        .collect())
+}
+
+/// Call the progress listener given to {@link OlmMachine.backupRoomKeys} with
+/// the current room key counts.
+async fn report_backup_progress(
+    machine: &matrix_sdk_crypto::OlmMachine,
+    progress_listener: &Function,
+) -> Result<(), JsValue> {
+    let counts = RoomKeyCounts::from(
+        machine.backup_machine().room_key_counts().await.map_err(JsError::from)?,
+    );
+    progress_listener.call2(
+        &JsValue::NULL,
+        &JsValue::from(counts.backed_up),
+        &JsValue::from(counts.total),
+    )?;
+
+    Ok(())
+}
+
+/// Record the time at which each new inbound group session is stored, for
+/// {@link OlmMachine.pruneInboundGroupSessions}.
+fn record_inbound_group_session_times(
+    machine: &matrix_sdk_crypto::OlmMachine,
+    tracing_subscriber: &Dispatch,
+    lock: Rc<Mutex<()>>,
+) -> BackgroundTask {
+    let stream = machine.store().room_keys_received_stream();
+    // Only keep a weak reference to the store, so that it is closed once the
+    // machine is dropped, which also ends the stream.
+    let store = Arc::downgrade(&machine.store().crypto_store());
+
+    let future = async move {
+        pin_mut!(stream);
+
+        while let Some(item) = stream.next().await {
+            let Ok(room_keys) = item else { continue };
+            let Some(store) = store.upgrade() else { break };
+            let _guard = lock.lock().await;
+
+            let mut session_ids = BTreeMap::<_, Vec<_>>::new();
+            for room_key in &room_keys {
+                session_ids
+                    .entry(&room_key.room_id)
+                    .or_default()
+                    .push(room_key.session_id.as_str());
+            }
+
+            for (room_id, session_ids) in session_ids {
+                if let Err(e) = store::session_times::record(&store, room_id, session_ids).await {
+                    warn!("Failed to record the time of inbound group sessions: {e:?}");
+                }
+            }
+        }
+    };
+    BackgroundTask::spawn(future, tracing_subscriber)
 }
 
 /// Helper for `register_*_callback` methods: fires off a background job (or
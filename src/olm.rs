@@ -1,8 +1,14 @@
 //! Olm types.
 
+use matrix_sdk_common::ruma::DeviceKeyAlgorithm;
+use matrix_sdk_crypto::types::SigningKey;
 use wasm_bindgen::prelude::*;
 
-use crate::{identifiers, impl_from_to_inner, vodozemac::Curve25519PublicKey};
+use crate::{
+    encryption::EncryptionAlgorithm,
+    identifiers, impl_from_to_inner,
+    vodozemac::{Curve25519PublicKey, Ed25519PublicKey},
+};
 
 /// Struct representing the state of our private cross signing keys,
 /// it shows which private cross signing keys we have locally stored.
@@ -45,9 +51,29 @@ impl CrossSigningStatus {
 #[derive(Debug)]
 pub struct InboundGroupSession {
     inner: matrix_sdk_crypto::olm::InboundGroupSession,
+    backed_up: bool,
+    stored_at: Option<u64>,
 }
 
-impl_from_to_inner!(matrix_sdk_crypto::olm::InboundGroupSession => InboundGroupSession);
+impl InboundGroupSession {
+    /// Wrap a session along with what the store knows about it: whether it
+    /// has been backed up to the current backup, and the time at which it was
+    /// first stored, in milliseconds since the Unix epoch, if known.
+    pub(crate) fn with_store_info(
+        inner: matrix_sdk_crypto::olm::InboundGroupSession,
+        backed_up: bool,
+        stored_at: Option<u64>,
+    ) -> Self {
+        Self { inner, backed_up, stored_at }
+    }
+}
+
+impl From<matrix_sdk_crypto::olm::InboundGroupSession> for InboundGroupSession {
+    fn from(inner: matrix_sdk_crypto::olm::InboundGroupSession) -> Self {
+        let backed_up = inner.backed_up();
+        Self::with_store_info(inner, backed_up, None)
+    }
+}
 
 #[wasm_bindgen]
 impl InboundGroupSession {
@@ -76,4 +102,60 @@ impl InboundGroupSession {
     pub fn has_been_imported(&self) -> bool {
         self.inner.has_been_imported()
     }
+
+    /// The index of the first message which this session can decrypt.
+    ///
+    /// Messages encrypted with an earlier index can't be decrypted with this
+    /// session.
+    #[wasm_bindgen(getter, js_name = "firstKnownIndex")]
+    pub fn first_known_index(&self) -> u32 {
+        self.inner.first_known_index()
+    }
+
+    /// The encryption algorithm of this session.
+    #[wasm_bindgen(getter)]
+    pub fn algorithm(&self) -> EncryptionAlgorithm {
+        self.inner.algorithm().clone().into()
+    }
+
+    /// The Ed25519 key which the sender of this session claimed to own, if
+    /// any.
+    ///
+    /// Unless the session was received directly from its sender, as opposed to
+    /// being imported, this claim can't be verified.
+    #[wasm_bindgen(getter, js_name = "senderClaimedEd25519Key")]
+    pub fn sender_claimed_ed25519_key(&self) -> Option<Ed25519PublicKey> {
+        match self.inner.signing_keys().get(&DeviceKeyAlgorithm::Ed25519) {
+            Some(SigningKey::Ed25519(key)) => Some((*key).into()),
+            _ => None,
+        }
+    }
+
+    /// Whether this session has been backed up to the server-side key backup.
+    ///
+    /// For sessions returned by {@link OlmMachine.getInboundGroupSessions},
+    /// this tells whether the session has been backed up to the current
+    /// backup, and is `false` if no backup is enabled.
+    #[wasm_bindgen(getter, js_name = "backedUp")]
+    pub fn backed_up(&self) -> bool {
+        self.backed_up
+    }
+
+    /// Whether this session may be shared with users who join the room later,
+    /// as per MSC3061.
+    #[wasm_bindgen(getter, js_name = "sharedHistory")]
+    pub fn shared_history(&self) -> bool {
+        self.inner.shared_history()
+    }
+
+    /// The time at which this session was first stored, whether it was
+    /// received or imported, in milliseconds since the Unix epoch.
+    ///
+    /// Only known for sessions returned by
+    /// {@link OlmMachine.getInboundGroupSessions} which were stored by this
+    /// version of the library or a later one.
+    #[wasm_bindgen(getter, js_name = "storedAt")]
+    pub fn stored_at(&self) -> Option<f64> {
+        self.stored_at.map(|time| time as f64)
+    }
 }
@@ -40,7 +40,8 @@ type OutgoingRequest =
     | ToDeviceRequest
     | SignatureUploadRequest
     | RoomMessageRequest
-    | KeysBackupRequest;
+    | KeysBackupRequest
+    | KeysBackupFetchRequest;
 "#;
 
 /// A subset of outgoing requests used for verification.
@@ -340,6 +341,56 @@ impl KeysBackupRequest {
     }
 }
 
+/// A request that will fetch a single room key from the backup
+/// ([specification]).
+///
+/// It is emitted by {@link OlmMachine.outgoingRequests} for the room keys
+/// which were missing to decrypt an event, if a backup decryption key is
+/// stored. The response should be passed to
+/// {@link OlmMachine.markRequestAsSent} even if the server replies that the
+/// room key isn't in the backup.
+///
+/// [specification]: https://spec.matrix.org/unstable/client-server-api/#get_matrixclientv3room_keyskeysroomidsessionid
+#[derive(Debug)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct KeysBackupFetchRequest {
+    /// The request ID.
+    #[wasm_bindgen(readonly)]
+    pub id: JsString,
+
+    /// The ID of the room of the room key.
+    #[wasm_bindgen(readonly, js_name = "roomId")]
+    pub room_id: JsString,
+
+    /// The ID of the Megolm session of the room key.
+    #[wasm_bindgen(readonly, js_name = "sessionId")]
+    pub session_id: JsString,
+
+    /// The backup version to fetch the room key from.
+    #[wasm_bindgen(readonly)]
+    pub version: JsString,
+}
+
+#[wasm_bindgen]
+impl KeysBackupFetchRequest {
+    /// Create a new `KeysBackupFetchRequest`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        id: JsString,
+        room_id: JsString,
+        session_id: JsString,
+        version: JsString,
+    ) -> KeysBackupFetchRequest {
+        Self { id, room_id, session_id, version }
+    }
+
+    /// Get its request type.
+    #[wasm_bindgen(getter, js_name = "type")]
+    pub fn request_type(&self) -> RequestType {
+        RequestType::KeysBackupFetch
+    }
+}
+
 macro_rules! request {
     (
         $destination_request:ident from $source_request:ident
@@ -438,6 +489,42 @@ request!(ToDeviceRequest from OriginalToDeviceRequest extracts event_type: strin
 request!(RoomMessageRequest from OriginalRoomMessageRequest extracts room_id: string, txn_id: string, event_type: event_type, content: json);
 request!(KeysBackupRequest from OriginalKeysBackupRequest extracts version: string and groups rooms);
 
+/// Implement the `bodyObject` getter of request types, which returns the
+/// JSON-encoded body of the request as a plain object.
+macro_rules! body_object {
+    ( $( $request:ident . $body_field:ident ),+ $(,)? ) => {
+        $(
+            #[wasm_bindgen]
+            impl $request {
+                /// The body of the HTTP request, as a plain object rather than
+                /// as JSON.
+                ///
+                /// A new object is built on each access.
+                #[wasm_bindgen(
+                    getter,
+                    js_name = "bodyObject",
+                    unchecked_return_type = "Record<string, any>"
+                )]
+                pub fn body_object(&self) -> Result<JsValue, JsError> {
+                    crate::types::json_to_js_object(&String::from(&self.$body_field))
+                }
+            }
+        )+
+    };
+}
+
+body_object!(
+    KeysUploadRequest.body,
+    KeysQueryRequest.body,
+    KeysClaimRequest.body,
+    ToDeviceRequest.body,
+    SignatureUploadRequest.signed_keys,
+    RoomMessageRequest.content,
+    KeysBackupRequest.body,
+    UploadSigningKeysRequest.body,
+    PutDehydratedDeviceRequest.body,
+);
+
 // Specific conversion for signature upload as they can be returned directly by
 // some verification API and not via `outgoing_requests()`. If returned by
 // `outgoing_requests()` they would need to be marked as sent using `id`,
@@ -564,6 +651,9 @@ pub enum RequestType {
 
     /// Represents a `KeysBackupRequest`.
     KeysBackup,
+
+    /// Represents a `KeysBackupFetchRequest`.
+    KeysBackupFetch,
 }
 
 /** Other Requests * */
@@ -684,7 +774,7 @@ impl TryFrom<OriginalPutDehydratedDeviceRequest> for PutDehydratedDeviceRequest
 /// A set of requests to be executed when bootstrapping cross-signing using
 /// {@link OlmMachine.bootstrapCrossSigning}.
 #[wasm_bindgen(getter_with_clone)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CrossSigningBootstrapRequests {
     /// An optional request to upload a device key.
     ///
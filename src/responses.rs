@@ -1,6 +1,6 @@
 //! Types related to responses.
 
-use std::sync::Arc;
+use std::{cell::OnceCell, sync::Arc};
 
 use js_sys::{Array, JsString};
 pub(crate) use matrix_sdk_common::ruma::api::client::{
@@ -21,7 +21,7 @@ use matrix_sdk_crypto::types::requests::AnyIncomingResponse;
 use thiserror::Error;
 use wasm_bindgen::prelude::*;
 
-use crate::{encryption, identifiers, requests::RequestType};
+use crate::{encryption, identifiers, requests::RequestType, types};
 
 pub(crate) fn response_from_string(body: &str) -> http::Result<http::Response<Vec<u8>>> {
     http::Response::builder().status(200).body(body.as_bytes().to_vec())
@@ -125,6 +125,12 @@ impl TryFrom<(RequestType, http::Response<Vec<u8>>)> for OwnedResponse {
             RequestType::KeysBackup => {
                 KeysBackupResponse::try_from_http_response(response).map(Into::into)
             }
+
+            RequestType::KeysBackupFetch => {
+                return Err(JsError::new(
+                    "The responses to `KeysBackupFetchRequest`s are handled by the `OlmMachine`",
+                ))
+            }
         }
         .map_err(JsError::from)
     }
@@ -158,11 +164,31 @@ pub struct DecryptedRoomEvent {
     #[wasm_bindgen(readonly)]
     pub event: JsString,
 
+    /// The decrypted event, kept to build `eventObject` from.
+    json: Box<serde_json::value::RawValue>,
+
+    /// The decrypted event as a plain object, once it has been built.
+    event_object: OnceCell<JsValue>,
+
     encryption_info: EncryptionInfo,
 }
 
 #[wasm_bindgen]
 impl DecryptedRoomEvent {
+    /// The decrypted event, as a plain object.
+    ///
+    /// The object is built on first access, and the same object is returned
+    /// afterwards.
+    #[wasm_bindgen(getter, js_name = "eventObject", unchecked_return_type = "Record<string, any>")]
+    pub fn event_object(&self) -> Result<JsValue, JsError> {
+        if let Some(object) = self.event_object.get() {
+            return Ok(object.clone());
+        }
+
+        let object = types::json_to_js_object(self.json.get())?;
+        Ok(self.event_object.get_or_init(|| object).clone())
+    }
+
     /// The user ID of the event sender, note this is untrusted data
     /// unless the `verification_state` is as well trusted.
     #[wasm_bindgen(getter)]
@@ -245,7 +271,8 @@ impl TryFrom<matrix_sdk_common::deserialized_responses::DecryptedRoomEvent> for
         value: matrix_sdk_common::deserialized_responses::DecryptedRoomEvent,
     ) -> Result<Self, Self::Error> {
         let encryption_info = value.encryption_info.clone().try_into()?;
-        Ok(Self { event: value.event.json().get().into(), encryption_info })
+        let json = value.event.into_json();
+        Ok(Self { event: json.get().into(), json, event_object: OnceCell::new(), encryption_info })
     }
 }
 
@@ -0,0 +1,273 @@
+//! Secret storage
+//!
+//! WASM wrapper for `matrix_sdk_crypto::secret_storage`.
+
+use std::collections::BTreeMap;
+
+use js_sys::{JsString, Map};
+use matrix_sdk_common::ruma::events::{
+    secret::request::SecretName,
+    secret_storage::{key::SecretStorageKeyEventContent, secret::SecretEventContent},
+};
+use matrix_sdk_crypto::secret_storage::{self, AesHmacSha2EncryptedData};
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::{
+    backup::BackupDecryptionKey, impl_from_to_inner, requests::CrossSigningBootstrapRequests,
+};
+
+/// The type of the account data event holding the ID of the default secret
+/// storage key.
+pub(crate) const DEFAULT_KEY_EVENT_TYPE: &str = "m.secret_storage.default_key";
+
+/// The content of the `m.secret_storage.default_key` account data event.
+#[derive(Deserialize)]
+struct DefaultKeyContent {
+    key: String,
+}
+
+/// A key for secret storage (also known as 4S, or SSSS), which encrypts
+/// secrets such as the cross-signing private keys or the backup decryption
+/// key into the user's account data.
+///
+/// The key is described by an `m.secret_storage.key.<keyId>` account data
+/// event, which holds a MAC that proves that a key typed by the user is the
+/// right one, and each secret is stored in an account data event named after
+/// the secret.
+///
+/// See [the specification](https://spec.matrix.org/v1.11/client-server-api/#storage).
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct SecretStorageKey {
+    pub(crate) inner: secret_storage::SecretStorageKey,
+}
+
+impl_from_to_inner!(secret_storage::SecretStorageKey => SecretStorageKey);
+
+#[wasm_bindgen]
+impl SecretStorageKey {
+    /// Create a new random key, with a random ID.
+    ///
+    /// It can be shown to the user as a recovery key with
+    /// {@link SecretStorageKey.toRecoveryKey}.
+    #[wasm_bindgen(js_name = "createRandomKey")]
+    pub fn create_random_key() -> SecretStorageKey {
+        secret_storage::SecretStorageKey::new().into()
+    }
+
+    /// Create a new key, with a random ID, derived from the given passphrase
+    /// with a random salt.
+    ///
+    /// The key can be derived again from the passphrase with
+    /// {@link SecretStorageKey.fromAccountData}, as its description holds the
+    /// salt.
+    #[wasm_bindgen(js_name = "createFromPassphrase")]
+    pub fn create_from_passphrase(passphrase: &str) -> SecretStorageKey {
+        secret_storage::SecretStorageKey::new_from_passphrase(passphrase).into()
+    }
+
+    /// Import a key from the recovery key or the passphrase typed by the user,
+    /// and the description of the key.
+    ///
+    /// Throws if the description isn't valid, or if its MAC shows that the
+    /// recovery key or passphrase doesn't match it.
+    ///
+    /// # Arguments
+    ///
+    /// * `recovery_key_or_passphrase` - The recovery key, or the passphrase if
+    ///   the description says the key is derived from one.
+    /// * `key_id` - The ID of the key: the suffix of the type of the
+    ///   `m.secret_storage.key.<keyId>` account data event.
+    /// * `description` - The JSON-encoded content of that event.
+    #[wasm_bindgen(js_name = "fromAccountData")]
+    pub fn from_account_data(
+        recovery_key_or_passphrase: &str,
+        key_id: &str,
+        description: &str,
+    ) -> Result<SecretStorageKey, JsError> {
+        let mut description: SecretStorageKeyEventContent = serde_json::from_str(description)?;
+        // The ID of the key is part of the event type, so it isn't deserialized along
+        // with the content.
+        description.key_id = key_id.to_owned();
+
+        Ok(secret_storage::SecretStorageKey::from_account_data(
+            recovery_key_or_passphrase,
+            description,
+        )?
+        .into())
+    }
+
+    /// The ID of the key.
+    #[wasm_bindgen(getter, js_name = "keyId")]
+    pub fn key_id(&self) -> String {
+        self.inner.key_id().to_owned()
+    }
+
+    /// The type of the account data event describing the key:
+    /// `m.secret_storage.key.<keyId>`.
+    #[wasm_bindgen(getter, js_name = "eventType")]
+    pub fn event_type(&self) -> String {
+        self.inner.event_type().to_string()
+    }
+
+    /// The JSON-encoded content of the account data event describing the
+    /// key, to upload along with the secrets.
+    #[wasm_bindgen(getter, js_name = "eventContent")]
+    pub fn event_content(&self) -> Result<String, JsError> {
+        Ok(serde_json::to_string(self.inner.event_content())?)
+    }
+
+    /// Encode the key as a recovery key, to show to the user.
+    #[wasm_bindgen(js_name = "toRecoveryKey")]
+    pub fn to_recovery_key(&self) -> String {
+        self.inner.to_base58()
+    }
+
+    /// Encrypt a secret with this key.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_name` - The name of the secret, which is also the type of the
+    ///   account data event it is stored in, for example
+    ///   `m.cross_signing.master`, `m.megolm_backup.v1`, or a custom name.
+    /// * `secret` - The secret. The secrets defined by the specification are
+    ///   unpadded base64-encoded keys.
+    /// * `existing_content` - The JSON-encoded content of the account data
+    ///   event the secret is currently stored in, if any. The secret is added
+    ///   to it, so that it can still be decrypted with the other keys it is
+    ///   encrypted with.
+    ///
+    /// # Returns
+    ///
+    /// The JSON-encoded content of the account data event to upload.
+    #[wasm_bindgen(js_name = "encryptSecret")]
+    pub fn encrypt_secret(
+        &self,
+        secret_name: &str,
+        secret: &str,
+        existing_content: Option<String>,
+    ) -> Result<String, JsError> {
+        let mut content = match existing_content {
+            Some(content) => serde_json::from_str(&content)?,
+            None => SecretEventContent::new(BTreeMap::new()),
+        };
+
+        let encrypted =
+            self.inner.encrypt(secret.as_bytes().to_vec(), &SecretName::from(secret_name));
+        content.encrypted.insert(self.inner.key_id().to_owned(), encrypted.into());
+
+        Ok(serde_json::to_string(&content)?)
+    }
+
+    /// Decrypt a secret encrypted with this key.
+    ///
+    /// Throws if the secret isn't encrypted with this key, or if its MAC
+    /// doesn't match, which means that it has been tampered with or that it
+    /// is stored under another name.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_name` - The name of the secret, see {@link
+    ///   SecretStorageKey.encryptSecret}.
+    /// * `content` - The JSON-encoded content of the account data event the
+    ///   secret is stored in.
+    #[wasm_bindgen(js_name = "decryptSecret")]
+    pub fn decrypt_secret(&self, secret_name: &str, content: &str) -> Result<String, JsError> {
+        let content: SecretEventContent = serde_json::from_str(content)?;
+
+        let Some(encrypted) = content.encrypted.get(self.inner.key_id()) else {
+            return Err(JsError::new(&format!(
+                "The secret {secret_name} is not encrypted with the key {}",
+                self.inner.key_id()
+            )));
+        };
+
+        let encrypted = AesHmacSha2EncryptedData::try_from(encrypted.to_owned())?;
+        let secret =
+            Zeroizing::new(self.inner.decrypt(&encrypted, &SecretName::from(secret_name))?);
+
+        Ok(String::from_utf8(secret.to_vec())?)
+    }
+}
+
+impl SecretStorageKey {
+    /// Import the default key described in `account_data`, a map from account
+    /// data event type to JSON-encoded content, from the recovery key or the
+    /// passphrase typed by the user.
+    pub(crate) fn from_default_key(
+        recovery_key_or_passphrase: &str,
+        account_data: &BTreeMap<String, String>,
+    ) -> Result<SecretStorageKey, JsError> {
+        let Some(default_key) = account_data.get(DEFAULT_KEY_EVENT_TYPE) else {
+            return Err(JsError::new("No default secret storage key in the account data"));
+        };
+        let key_id = serde_json::from_str::<DefaultKeyContent>(default_key)?.key;
+
+        let Some(description) = account_data.get(&format!("m.secret_storage.key.{key_id}")) else {
+            return Err(JsError::new(&format!(
+                "No description of the secret storage key {key_id} in the account data"
+            )));
+        };
+
+        Self::from_account_data(recovery_key_or_passphrase, &key_id, description)
+    }
+
+    /// Decrypt the secret with the given name from `account_data`, if it is
+    /// there.
+    pub(crate) fn decrypt_from_account_data(
+        &self,
+        secret_name: &SecretName,
+        account_data: &BTreeMap<String, String>,
+    ) -> Result<Option<String>, JsError> {
+        account_data
+            .get(secret_name.as_str())
+            .map(|content| self.decrypt_secret(secret_name.as_str(), content))
+            .transpose()
+    }
+}
+
+/// The result of {@link OlmMachine.setupRecovery}.
+#[derive(Debug)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct RecoverySetup {
+    /// The recovery key, to show to the user. It is needed to recover the
+    /// secrets on another device, along with {@link OlmMachine.recover},
+    /// unless a passphrase was given.
+    #[wasm_bindgen(readonly, js_name = "recoveryKey")]
+    pub recovery_key: String,
+
+    /// The requests to upload the cross-signing keys. They should be sent
+    /// first, as described in {@link OlmMachine.bootstrapCrossSigning}.
+    #[wasm_bindgen(readonly, js_name = "crossSigningRequests")]
+    pub cross_signing_requests: CrossSigningBootstrapRequests,
+
+    /// The key of the new backup.
+    #[wasm_bindgen(readonly, js_name = "backupDecryptionKey")]
+    pub backup_decryption_key: BackupDecryptionKey,
+
+    /// The JSON-encoded body of the request to create the new backup version
+    /// (`POST /room_keys/version`), signed by our device and our master key.
+    #[wasm_bindgen(readonly, js_name = "backupVersionBody")]
+    pub backup_version_body: String,
+
+    pub(crate) account_data: BTreeMap<String, String>,
+}
+
+#[wasm_bindgen]
+impl RecoverySetup {
+    /// The account data events to upload, as a Map from event type to
+    /// JSON-encoded content: the description of the secret storage key, the
+    /// default key, and the encrypted secrets.
+    #[wasm_bindgen(js_name = "accountData", unchecked_return_type = "Map<string, string>")]
+    pub fn account_data(&self) -> Map {
+        let map = Map::new();
+
+        for (event_type, content) in &self.account_data {
+            map.set(&JsString::from(event_type.as_str()), &JsString::from(content.as_str()));
+        }
+
+        map
+    }
+}
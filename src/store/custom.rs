@@ -0,0 +1,1179 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A crypto store whose persistence is provided by JavaScript.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use js_sys::{Array, Promise, Uint8Array};
+use matrix_sdk_common::{
+    cross_process_lock::CrossProcessLockGeneration,
+    ruma::{
+        events::secret::request::SecretName, DeviceId, OwnedDeviceId, OwnedRoomId, RoomId,
+        TransactionId, UserId,
+    },
+};
+use matrix_sdk_crypto::{
+    olm::{
+        Account, InboundGroupSession, OlmMessageHash, OutboundGroupSession, PickledAccount,
+        PickledCrossSigningIdentity, PickledInboundGroupSession, PickledOutboundGroupSession,
+        PickledSession, PrivateCrossSigningIdentity, SenderDataType, Session,
+    },
+    store::{
+        types::{
+            BackupDecryptionKey, BackupKeys, Changes, DehydratedDeviceKey, PendingChanges,
+            RoomKeyCounts, RoomKeyWithheldEntry, RoomPendingKeyBundleDetails, RoomSettings,
+            SecretsInboxItem, StoredRoomKeyBundleData, TrackedUser,
+        },
+        CryptoStore, CryptoStoreError, MemoryStore,
+    },
+    vodozemac::Curve25519PublicKey,
+    DeviceData, GossipRequest, SecretInfo, UserIdentityData,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+type Result<T, E = CryptoStoreError> = std::result::Result<T, E>;
+
+/// The TypeScript type for [`CryptoStoreAdapter`].
+#[wasm_bindgen(typescript_custom_section)]
+const CRYPTO_STORE_ADAPTER_TYPE: &str = r#"
+/**
+ * A JavaScript-side key/value store which can back a crypto store opened with
+ * {@link StoreHandle.openCustom}.
+ *
+ * Records are grouped into named collections. Keys are strings, and values are
+ * opaque byte arrays which must be returned unchanged.
+ *
+ * The adapter has no way to update records atomically, so the leases taken on
+ * the store are kept in memory: they are not shared with other JavaScript
+ * contexts (tabs, workers, processes) using the same underlying storage.
+ */
+interface CryptoStoreAdapter {
+    get(collection: string, key: string): Promise<Uint8Array | undefined>;
+    put(collection: string, key: string, value: Uint8Array): Promise<void>;
+    delete(collection: string, key: string): Promise<void>;
+    iterate(collection: string): Promise<Array<[string, Uint8Array]>>;
+}"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// The type of a JavaScript-side `CryptoStoreAdapter` object.
+    #[wasm_bindgen(typescript_type = "CryptoStoreAdapter")]
+    #[derive(Debug, Clone)]
+    pub type CryptoStoreAdapter;
+
+    #[wasm_bindgen(method, catch, js_name = "get")]
+    fn get_js(this: &CryptoStoreAdapter, collection: &str, key: &str) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = "put")]
+    fn put_js(
+        this: &CryptoStoreAdapter,
+        collection: &str,
+        key: &str,
+        value: Uint8Array,
+    ) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = "delete")]
+    fn delete_js(
+        this: &CryptoStoreAdapter,
+        collection: &str,
+        key: &str,
+    ) -> Result<Promise, JsValue>;
+
+    #[wasm_bindgen(method, catch, js_name = "iterate")]
+    fn iterate_js(this: &CryptoStoreAdapter, collection: &str) -> Result<Promise, JsValue>;
+}
+
+/// The names of the collections used by [`CustomStore`].
+pub(crate) mod collections {
+    pub const CORE: &str = "core";
+    pub const SESSIONS: &str = "sessions";
+    pub const MESSAGE_HASHES: &str = "message_hashes";
+    pub const INBOUND_GROUP_SESSIONS: &str = "inbound_group_sessions";
+    pub const OUTBOUND_GROUP_SESSIONS: &str = "outbound_group_sessions";
+    pub const GOSSIP_REQUESTS: &str = "gossip_requests";
+    pub const IDENTITIES: &str = "identities";
+    pub const DEVICES: &str = "devices";
+    pub const TRACKED_USERS: &str = "tracked_users";
+    pub const WITHHELD_SESSIONS: &str = "withheld_sessions";
+    pub const ROOM_SETTINGS: &str = "room_settings";
+    pub const SECRETS_INBOX: &str = "secrets_inbox";
+    pub const RECEIVED_ROOM_KEY_BUNDLES: &str = "received_room_key_bundles";
+    pub const ROOM_KEY_BACKUPS_FULLY_DOWNLOADED: &str = "room_key_backups_fully_downloaded";
+    pub const ROOMS_PENDING_KEY_BUNDLE: &str = "rooms_pending_key_bundle";
+    pub const CUSTOM_VALUES: &str = "custom_values";
+
+    /// Records which could not be read, moved aside by an integrity check.
+    ///
+    /// This collection is never loaded.
+    pub const QUARANTINE: &str = "quarantine";
+
+    /// Every collection, in the order in which they are loaded.
+    pub const ALL: &[&str] = &[
+        CORE,
+        SESSIONS,
+        MESSAGE_HASHES,
+        INBOUND_GROUP_SESSIONS,
+        OUTBOUND_GROUP_SESSIONS,
+        GOSSIP_REQUESTS,
+        IDENTITIES,
+        DEVICES,
+        TRACKED_USERS,
+        WITHHELD_SESSIONS,
+        ROOM_SETTINGS,
+        SECRETS_INBOX,
+        RECEIVED_ROOM_KEY_BUNDLES,
+        ROOM_KEY_BACKUPS_FULLY_DOWNLOADED,
+        ROOMS_PENDING_KEY_BUNDLE,
+        CUSTOM_VALUES,
+    ];
+}
+
+/// Keys of the singleton records in the [`collections::CORE`] collection.
+pub(crate) mod core_keys {
+    pub const ACCOUNT: &str = "account";
+    pub const PRIVATE_IDENTITY: &str = "private_identity";
+    pub const BACKUP_VERSION: &str = "backup_version";
+    pub const BACKUP_DECRYPTION_KEY: &str = "backup_decryption_key";
+    pub const DEHYDRATED_DEVICE_PICKLE_KEY: &str = "dehydrated_device_pickle_key";
+    pub const NEXT_BATCH_TOKEN: &str = "next_batch_token";
+}
+
+/// An error reported by the JavaScript adapter, or by the (de)serialization of
+/// one of its records.
+#[derive(Debug, thiserror::Error)]
+#[error("Custom crypto store error: {0}")]
+pub(crate) struct AdapterError(pub(crate) String);
+
+impl AdapterError {
+    fn from_js(value: JsValue) -> CryptoStoreError {
+        CryptoStoreError::backend(Self(format!("{value:?}")))
+    }
+}
+
+/// The record stored for each inbound group session.
+///
+/// The backup version is kept next to the pickle, since it is tracked by the
+/// store rather than by the session itself.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct InboundGroupSessionRecord {
+    pub(crate) pickle: PickledInboundGroupSession,
+    pub(crate) backed_up_to: Option<String>,
+}
+
+/// An inbound group session held by an [`InboundGroupSessions`] set.
+#[derive(Debug)]
+struct StoredInboundGroupSession {
+    /// The serialized [`InboundGroupSessionRecord`], as written to the
+    /// adapter.
+    record: Vec<u8>,
+    backed_up_to: Option<String>,
+}
+
+impl StoredInboundGroupSession {
+    fn new(record: &InboundGroupSessionRecord) -> Result<Self> {
+        Ok(Self { record: serde_json::to_vec(record)?, backed_up_to: record.backed_up_to.clone() })
+    }
+
+    fn record(&self) -> Result<InboundGroupSessionRecord> {
+        Ok(serde_json::from_slice(&self.record)?)
+    }
+
+    fn session(&self) -> Result<InboundGroupSession> {
+        Ok(InboundGroupSession::from_pickle(self.record()?.pickle)?)
+    }
+}
+
+/// The inbound group sessions of a [`CustomStore`], by room and session ID.
+///
+/// They are kept apart from its [`MemoryStore`], which has no way to forget a
+/// session, so that [`delete_inbound_group_session`] frees them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InboundGroupSessions(
+    Rc<RefCell<BTreeMap<(OwnedRoomId, String), StoredInboundGroupSession>>>,
+);
+
+impl InboundGroupSessions {
+    fn get(&self, room_id: &RoomId, session_id: &str) -> Result<Option<InboundGroupSession>> {
+        self.0
+            .borrow()
+            .get(&(room_id.to_owned(), session_id.to_owned()))
+            .map(StoredInboundGroupSession::session)
+            .transpose()
+    }
+
+    /// Get the sessions for which `filter` returns `true`, at most `limit` of
+    /// them.
+    fn filter(
+        &self,
+        limit: usize,
+        mut filter: impl FnMut(&RoomId, &StoredInboundGroupSession) -> bool,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.0
+            .borrow()
+            .iter()
+            .filter(|((room_id, _), stored)| filter(room_id, stored))
+            .take(limit)
+            .map(|(_, stored)| stored.session())
+            .collect()
+    }
+
+    /// Write a session to the adapter, then keep it.
+    ///
+    /// If `backed_up_to` is `None`, the backup version that was previously
+    /// recorded for the session is preserved.
+    async fn save(
+        &self,
+        adapter: &CryptoStoreAdapter,
+        session: &InboundGroupSession,
+        backed_up_to: Option<&str>,
+    ) -> Result<()> {
+        let key = (session.room_id().to_owned(), session.session_id().to_owned());
+        let backed_up_to = match backed_up_to {
+            Some(version) => Some(version.to_owned()),
+            None => self.0.borrow().get(&key).and_then(|stored| stored.backed_up_to.clone()),
+        };
+
+        let record = InboundGroupSessionRecord { pickle: session.pickle().await, backed_up_to };
+        self.put(adapter, key, StoredInboundGroupSession::new(&record)?).await
+    }
+
+    /// Record the backup version a session has been backed up to, if any.
+    async fn set_backed_up_to(
+        &self,
+        adapter: &CryptoStoreAdapter,
+        room_id: &RoomId,
+        session_id: &str,
+        backed_up_to: Option<&str>,
+    ) -> Result<()> {
+        let key = (room_id.to_owned(), session_id.to_owned());
+        let Some(mut record) =
+            self.0.borrow().get(&key).map(StoredInboundGroupSession::record).transpose()?
+        else {
+            return Ok(());
+        };
+
+        record.pickle.backed_up = backed_up_to.is_some();
+        record.backed_up_to = backed_up_to.map(ToOwned::to_owned);
+        self.put(adapter, key, StoredInboundGroupSession::new(&record)?).await
+    }
+
+    async fn put(
+        &self,
+        adapter: &CryptoStoreAdapter,
+        (room_id, session_id): (OwnedRoomId, String),
+        stored: StoredInboundGroupSession,
+    ) -> Result<()> {
+        put_raw(
+            adapter,
+            collections::INBOUND_GROUP_SESSIONS,
+            &inbound_group_session_key(&room_id, &session_id),
+            &stored.record,
+        )
+        .await?;
+        self.0.borrow_mut().insert((room_id, session_id), stored);
+
+        Ok(())
+    }
+}
+
+/// A crypto store which keeps its working set in a [`MemoryStore`], and writes
+/// every change through to a [`CryptoStoreAdapter`].
+///
+/// The whole content of the adapter is read when the store is opened. The
+/// inbound group sessions are kept aside, in an [`InboundGroupSessions`] set.
+#[derive(Debug)]
+pub(crate) struct CustomStore {
+    adapter: CryptoStoreAdapter,
+    memory: MemoryStore,
+    inbound_group_sessions: InboundGroupSessions,
+}
+
+impl CustomStore {
+    /// Open a store backed by the given adapter, loading all of its records
+    /// into memory.
+    pub(crate) async fn open(adapter: CryptoStoreAdapter) -> Result<Self> {
+        let memory = MemoryStore::new();
+        let mut changes = Changes::default();
+
+        let account =
+            match get_record::<PickledAccount>(&adapter, collections::CORE, core_keys::ACCOUNT)
+                .await?
+            {
+                Some(pickle) => Some(Account::from_pickle(pickle)?),
+                None => None,
+            };
+
+        if let Some(pickle) = get_record::<PickledCrossSigningIdentity>(
+            &adapter,
+            collections::CORE,
+            core_keys::PRIVATE_IDENTITY,
+        )
+        .await?
+        {
+            changes.private_identity = Some(PrivateCrossSigningIdentity::from_pickle(pickle)?);
+        }
+
+        changes.backup_version =
+            get_record(&adapter, collections::CORE, core_keys::BACKUP_VERSION).await?;
+        changes.backup_decryption_key =
+            get_record::<String>(&adapter, collections::CORE, core_keys::BACKUP_DECRYPTION_KEY)
+                .await?
+                .map(|key| BackupDecryptionKey::from_base64(&key))
+                .transpose()
+                .map_err(|e| CryptoStoreError::backend(AdapterError(e.to_string())))?;
+        changes.dehydrated_device_pickle_key = get_record::<String>(
+            &adapter,
+            collections::CORE,
+            core_keys::DEHYDRATED_DEVICE_PICKLE_KEY,
+        )
+        .await?
+        .map(|key| DehydratedDeviceKey::from_base64(&key))
+        .transpose()
+        .map_err(|e| CryptoStoreError::backend(AdapterError(e.to_string())))?;
+        changes.next_batch_token =
+            get_record(&adapter, collections::CORE, core_keys::NEXT_BATCH_TOKEN).await?;
+
+        if let Some(account) = &account {
+            // Olm and outbound group sessions embed parts of our own account.
+            let device_keys = account.device_keys();
+            let device_id: Arc<DeviceId> = account.device_id().into();
+            let identity_keys = Arc::new(account.identity_keys());
+
+            for (_, pickle) in
+                iterate_records::<PickledSession>(&adapter, collections::SESSIONS).await?
+            {
+                changes.sessions.push(Session::from_pickle(device_keys.clone(), pickle)?);
+            }
+
+            for (_, pickle) in iterate_records::<PickledOutboundGroupSession>(
+                &adapter,
+                collections::OUTBOUND_GROUP_SESSIONS,
+            )
+            .await?
+            {
+                changes.outbound_group_sessions.push(OutboundGroupSession::from_pickle(
+                    device_id.clone(),
+                    identity_keys.clone(),
+                    pickle,
+                )?);
+            }
+        }
+
+        changes.message_hashes = iterate_values(&adapter, collections::MESSAGE_HASHES).await?;
+        changes.key_requests = iterate_values(&adapter, collections::GOSSIP_REQUESTS).await?;
+        changes.identities.new = iterate_values(&adapter, collections::IDENTITIES).await?;
+        changes.devices.new = iterate_values(&adapter, collections::DEVICES).await?;
+        changes.secrets = iterate_values(&adapter, collections::SECRETS_INBOX).await?;
+        changes.received_room_key_bundles =
+            iterate_values(&adapter, collections::RECEIVED_ROOM_KEY_BUNDLES).await?;
+
+        for (_, entry) in
+            iterate_records::<RoomKeyWithheldEntry>(&adapter, collections::WITHHELD_SESSIONS)
+                .await?
+        {
+            changes
+                .withheld_session_info
+                .entry(entry.content.room_id().to_owned())
+                .or_default()
+                .insert(entry.content.megolm_session_id().to_owned(), entry);
+        }
+
+        for (room_id, settings) in
+            iterate_records::<RoomSettings>(&adapter, collections::ROOM_SETTINGS).await?
+        {
+            changes.room_settings.insert(parse_room_id(&room_id)?, settings);
+        }
+
+        for (room_id, _) in
+            iterate_records::<bool>(&adapter, collections::ROOM_KEY_BACKUPS_FULLY_DOWNLOADED)
+                .await?
+        {
+            changes.room_key_backups_fully_downloaded.insert(parse_room_id(&room_id)?);
+        }
+
+        for (room_id, details) in iterate_records::<RoomPendingKeyBundleDetails>(
+            &adapter,
+            collections::ROOMS_PENDING_KEY_BUNDLE,
+        )
+        .await?
+        {
+            changes.rooms_pending_key_bundle.insert(parse_room_id(&room_id)?, Some(details));
+        }
+
+        let inbound_group_sessions = InboundGroupSessions::default();
+        for (_, value) in iterate_raw(&adapter, collections::INBOUND_GROUP_SESSIONS).await? {
+            let record: InboundGroupSessionRecord = serde_json::from_slice(&value)?;
+            let backed_up_to = record.backed_up_to;
+            let session = InboundGroupSession::from_pickle(record.pickle)?;
+
+            inbound_group_sessions.0.borrow_mut().insert(
+                (session.room_id().to_owned(), session.session_id().to_owned()),
+                StoredInboundGroupSession { record: value, backed_up_to },
+            );
+        }
+
+        let tracked_users: Vec<(String, bool)> =
+            iterate_records(&adapter, collections::TRACKED_USERS).await?;
+        let tracked_users = tracked_users
+            .iter()
+            .map(|(user_id, dirty)| Ok((parse_user_id(user_id)?, *dirty)))
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(account) = account {
+            memory.save_pending_changes(PendingChanges { account: Some(account) }).await?;
+        }
+        memory.save_changes(changes).await?;
+
+        memory
+            .save_tracked_users(
+                &tracked_users
+                    .iter()
+                    .map(|(user_id, dirty)| (user_id.as_ref(), *dirty))
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+
+        for (key, value) in iterate_raw(&adapter, collections::CUSTOM_VALUES).await? {
+            memory.set_custom_value(&key, value).await?;
+        }
+
+        Ok(Self { adapter, memory, inbound_group_sessions })
+    }
+
+    /// The inbound group sessions of this store, to give to
+    /// [`delete_inbound_group_session`].
+    pub(crate) fn inbound_group_sessions(&self) -> InboundGroupSessions {
+        self.inbound_group_sessions.clone()
+    }
+
+    /// Write a set of changes through to the adapter.
+    async fn persist_changes(&self, changes: &Changes) -> Result<()> {
+        let adapter = &self.adapter;
+
+        if let Some(identity) = &changes.private_identity {
+            put_record(
+                adapter,
+                collections::CORE,
+                core_keys::PRIVATE_IDENTITY,
+                &identity.pickle().await,
+            )
+            .await?;
+        }
+
+        if let Some(version) = &changes.backup_version {
+            put_record(adapter, collections::CORE, core_keys::BACKUP_VERSION, version).await?;
+        }
+
+        if let Some(key) = &changes.backup_decryption_key {
+            put_record(
+                adapter,
+                collections::CORE,
+                core_keys::BACKUP_DECRYPTION_KEY,
+                &key.to_base64(),
+            )
+            .await?;
+        }
+
+        if let Some(key) = &changes.dehydrated_device_pickle_key {
+            put_record(
+                adapter,
+                collections::CORE,
+                core_keys::DEHYDRATED_DEVICE_PICKLE_KEY,
+                &key.to_base64(),
+            )
+            .await?;
+        }
+
+        if let Some(token) = &changes.next_batch_token {
+            put_record(adapter, collections::CORE, core_keys::NEXT_BATCH_TOKEN, token).await?;
+        }
+
+        for session in &changes.sessions {
+            let key = format!("{}|{}", session.sender_key().to_base64(), session.session_id());
+            put_record(adapter, collections::SESSIONS, &key, &session.pickle().await).await?;
+        }
+
+        for hash in &changes.message_hashes {
+            let key = format!("{}|{}", hash.sender_key, hash.hash);
+            put_record(adapter, collections::MESSAGE_HASHES, &key, hash).await?;
+        }
+
+        for session in &changes.outbound_group_sessions {
+            put_record(
+                adapter,
+                collections::OUTBOUND_GROUP_SESSIONS,
+                session.room_id().as_str(),
+                &session.pickle().await,
+            )
+            .await?;
+        }
+
+        for request in &changes.key_requests {
+            put_record(adapter, collections::GOSSIP_REQUESTS, request.request_id.as_str(), request)
+                .await?;
+        }
+
+        for identity in changes
+            .identities
+            .new
+            .iter()
+            .chain(&changes.identities.changed)
+            .chain(&changes.identities.unchanged)
+        {
+            put_record(adapter, collections::IDENTITIES, identity.user_id().as_str(), identity)
+                .await?;
+        }
+
+        for device in changes.devices.new.iter().chain(&changes.devices.changed) {
+            let key = device_key(device.user_id(), device.device_id());
+            put_record(adapter, collections::DEVICES, &key, device).await?;
+        }
+
+        for device in &changes.devices.deleted {
+            let key = device_key(device.user_id(), device.device_id());
+            delete_record(adapter, collections::DEVICES, &key).await?;
+        }
+
+        for (room_id, entries) in &changes.withheld_session_info {
+            for (session_id, entry) in entries {
+                let key = format!("{room_id}|{session_id}");
+                put_record(adapter, collections::WITHHELD_SESSIONS, &key, entry).await?;
+            }
+        }
+
+        for (room_id, settings) in &changes.room_settings {
+            put_record(adapter, collections::ROOM_SETTINGS, room_id.as_str(), settings).await?;
+        }
+
+        for secret in &changes.secrets {
+            // The inbox may hold several values for the same secret name, so each one
+            // gets a unique key.
+            let key = format!("{}|{}", secret.secret_name, TransactionId::new());
+            put_record(adapter, collections::SECRETS_INBOX, &key, secret).await?;
+        }
+
+        for bundle in &changes.received_room_key_bundles {
+            let key = format!("{}|{}", bundle.bundle_data.room_id, bundle.sender_user);
+            put_record(adapter, collections::RECEIVED_ROOM_KEY_BUNDLES, &key, bundle).await?;
+        }
+
+        for room_id in &changes.room_key_backups_fully_downloaded {
+            put_record(
+                adapter,
+                collections::ROOM_KEY_BACKUPS_FULLY_DOWNLOADED,
+                room_id.as_str(),
+                &true,
+            )
+            .await?;
+        }
+
+        for (room_id, details) in &changes.rooms_pending_key_bundle {
+            match details {
+                Some(details) => {
+                    put_record(
+                        adapter,
+                        collections::ROOMS_PENDING_KEY_BUNDLE,
+                        room_id.as_str(),
+                        details,
+                    )
+                    .await?
+                }
+                None => {
+                    delete_record(adapter, collections::ROOMS_PENDING_KEY_BUNDLE, room_id.as_str())
+                        .await?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl CryptoStore for CustomStore {
+    type Error = CryptoStoreError;
+
+    async fn load_account(&self) -> Result<Option<Account>> {
+        self.memory.load_account().await
+    }
+
+    async fn load_identity(&self) -> Result<Option<PrivateCrossSigningIdentity>> {
+        self.memory.load_identity().await
+    }
+
+    async fn save_pending_changes(&self, changes: PendingChanges) -> Result<()> {
+        if let Some(account) = &changes.account {
+            put_record(&self.adapter, collections::CORE, core_keys::ACCOUNT, &account.pickle())
+                .await?;
+        }
+
+        self.memory.save_pending_changes(changes).await
+    }
+
+    async fn save_changes(&self, mut changes: Changes) -> Result<()> {
+        self.persist_changes(&changes).await?;
+
+        for session in std::mem::take(&mut changes.inbound_group_sessions) {
+            self.inbound_group_sessions.save(&self.adapter, &session, None).await?;
+        }
+
+        self.memory.save_changes(changes).await
+    }
+
+    async fn save_inbound_group_sessions(
+        &self,
+        sessions: Vec<InboundGroupSession>,
+        backed_up_to_version: Option<&str>,
+    ) -> Result<()> {
+        for session in &sessions {
+            self.inbound_group_sessions.save(&self.adapter, session, backed_up_to_version).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_sessions(&self, sender_key: &str) -> Result<Option<Vec<Session>>> {
+        self.memory.get_sessions(sender_key).await
+    }
+
+    async fn get_inbound_group_session(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<InboundGroupSession>> {
+        self.inbound_group_sessions.get(room_id, session_id)
+    }
+
+    async fn get_withheld_info(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<RoomKeyWithheldEntry>> {
+        self.memory.get_withheld_info(room_id, session_id).await
+    }
+
+    async fn get_withheld_sessions_by_room_id(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RoomKeyWithheldEntry>> {
+        self.memory.get_withheld_sessions_by_room_id(room_id).await
+    }
+
+    async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>> {
+        self.inbound_group_sessions.filter(usize::MAX, |_, _| true)
+    }
+
+    async fn inbound_group_session_counts(
+        &self,
+        backup_version: Option<&str>,
+    ) -> Result<RoomKeyCounts> {
+        let sessions = self.inbound_group_sessions.0.borrow();
+        let backed_up = match backup_version {
+            Some(version) => sessions
+                .values()
+                .filter(|stored| stored.backed_up_to.as_deref() == Some(version))
+                .count(),
+            None => 0,
+        };
+
+        Ok(RoomKeyCounts { total: sessions.len(), backed_up })
+    }
+
+    async fn get_inbound_group_sessions_by_room_id(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.inbound_group_sessions
+            .filter(usize::MAX, |session_room_id, _| session_room_id == room_id)
+    }
+
+    async fn get_inbound_group_sessions_for_device_batch(
+        &self,
+        sender_key: Curve25519PublicKey,
+        sender_data_type: SenderDataType,
+        after_session_id: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>> {
+        let mut sessions: Vec<_> = self
+            .inbound_group_sessions
+            .filter(usize::MAX, |_, _| true)?
+            .into_iter()
+            .filter(|session| {
+                session.sender_key() == sender_key
+                    && session.sender_data.to_type() == sender_data_type
+                    && after_session_id
+                        .as_deref()
+                        .map_or(true, |after_session_id| session.session_id() > after_session_id)
+            })
+            .collect();
+
+        sessions.sort_by(|a, b| a.session_id().cmp(b.session_id()));
+        sessions.truncate(limit);
+
+        Ok(sessions)
+    }
+
+    async fn inbound_group_sessions_for_backup(
+        &self,
+        backup_version: &str,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.inbound_group_sessions
+            .filter(limit, |_, stored| stored.backed_up_to.as_deref() != Some(backup_version))
+    }
+
+    async fn mark_inbound_group_sessions_as_backed_up(
+        &self,
+        backup_version: &str,
+        room_and_session_ids: &[(&RoomId, &str)],
+    ) -> Result<()> {
+        for (room_id, session_id) in room_and_session_ids {
+            self.inbound_group_sessions
+                .set_backed_up_to(&self.adapter, room_id, session_id, Some(backup_version))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn reset_backup_state(&self) -> Result<()> {
+        let backed_up: Vec<_> = self
+            .inbound_group_sessions
+            .0
+            .borrow()
+            .iter()
+            .filter(|(_, stored)| stored.backed_up_to.is_some())
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for (room_id, session_id) in backed_up {
+            self.inbound_group_sessions
+                .set_backed_up_to(&self.adapter, &room_id, &session_id, None)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn load_backup_keys(&self) -> Result<BackupKeys> {
+        self.memory.load_backup_keys().await
+    }
+
+    async fn load_dehydrated_device_pickle_key(&self) -> Result<Option<DehydratedDeviceKey>> {
+        self.memory.load_dehydrated_device_pickle_key().await
+    }
+
+    async fn delete_dehydrated_device_pickle_key(&self) -> Result<()> {
+        delete_record(&self.adapter, collections::CORE, core_keys::DEHYDRATED_DEVICE_PICKLE_KEY)
+            .await?;
+        self.memory.delete_dehydrated_device_pickle_key().await
+    }
+
+    async fn get_outbound_group_session(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<OutboundGroupSession>> {
+        self.memory.get_outbound_group_session(room_id).await
+    }
+
+    async fn load_tracked_users(&self) -> Result<Vec<TrackedUser>> {
+        self.memory.load_tracked_users().await
+    }
+
+    async fn save_tracked_users(&self, users: &[(&UserId, bool)]) -> Result<()> {
+        for (user_id, dirty) in users {
+            put_record(&self.adapter, collections::TRACKED_USERS, user_id.as_str(), dirty).await?;
+        }
+
+        self.memory.save_tracked_users(users).await
+    }
+
+    async fn get_device(
+        &self,
+        user_id: &UserId,
+        device_id: &DeviceId,
+    ) -> Result<Option<DeviceData>> {
+        self.memory.get_device(user_id, device_id).await
+    }
+
+    async fn get_user_devices(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<OwnedDeviceId, DeviceData>> {
+        self.memory.get_user_devices(user_id).await
+    }
+
+    async fn get_user_identity(&self, user_id: &UserId) -> Result<Option<UserIdentityData>> {
+        self.memory.get_user_identity(user_id).await
+    }
+
+    async fn is_message_known(&self, message_hash: &OlmMessageHash) -> Result<bool> {
+        self.memory.is_message_known(message_hash).await
+    }
+
+    async fn get_outgoing_secret_requests(
+        &self,
+        request_id: &TransactionId,
+    ) -> Result<Option<GossipRequest>> {
+        self.memory.get_outgoing_secret_requests(request_id).await
+    }
+
+    async fn get_secret_request_by_info(
+        &self,
+        secret_info: &SecretInfo,
+    ) -> Result<Option<GossipRequest>> {
+        self.memory.get_secret_request_by_info(secret_info).await
+    }
+
+    async fn get_unsent_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        self.memory.get_unsent_secret_requests().await
+    }
+
+    async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
+        delete_record(&self.adapter, collections::GOSSIP_REQUESTS, request_id.as_str()).await?;
+        self.memory.delete_outgoing_secret_requests(request_id).await
+    }
+
+    async fn get_secrets_from_inbox(
+        &self,
+        secret_name: &SecretName,
+    ) -> Result<Vec<SecretsInboxItem>> {
+        self.memory.get_secrets_from_inbox(secret_name).await
+    }
+
+    async fn delete_secrets_from_inbox(&self, secret_name: &SecretName) -> Result<()> {
+        let prefix = format!("{secret_name}|");
+        for (key, _) in iterate_raw(&self.adapter, collections::SECRETS_INBOX).await? {
+            if key.starts_with(&prefix) {
+                delete_record(&self.adapter, collections::SECRETS_INBOX, &key).await?;
+            }
+        }
+
+        self.memory.delete_secrets_from_inbox(secret_name).await
+    }
+
+    async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
+        self.memory.get_room_settings(room_id).await
+    }
+
+    async fn get_received_room_key_bundle_data(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<StoredRoomKeyBundleData>> {
+        self.memory.get_received_room_key_bundle_data(room_id, user_id).await
+    }
+
+    async fn has_downloaded_all_room_keys(&self, room_id: &RoomId) -> Result<bool> {
+        self.memory.has_downloaded_all_room_keys(room_id).await
+    }
+
+    async fn get_pending_key_bundle_details_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<RoomPendingKeyBundleDetails>> {
+        self.memory.get_pending_key_bundle_details_for_room(room_id).await
+    }
+
+    async fn get_all_rooms_pending_key_bundles(&self) -> Result<Vec<RoomPendingKeyBundleDetails>> {
+        self.memory.get_all_rooms_pending_key_bundles().await
+    }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.memory.get_custom_value(key).await
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        put_raw(&self.adapter, collections::CUSTOM_VALUES, key, &value).await?;
+        self.memory.set_custom_value(key, value).await
+    }
+
+    async fn remove_custom_value(&self, key: &str) -> Result<()> {
+        delete_record(&self.adapter, collections::CUSTOM_VALUES, key).await?;
+        self.memory.remove_custom_value(key).await
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<Option<CrossProcessLockGeneration>> {
+        // Taking a lease has to be atomic, which the adapter can't provide, so
+        // leases are only shared between the users of this store rather than
+        // written through to the adapter. This is documented on
+        // `CryptoStoreAdapter` and `StoreHandle.createLock`.
+        self.memory.try_take_leased_lock(lease_duration_ms, key, holder).await
+    }
+
+    async fn next_batch_token(&self) -> Result<Option<String>> {
+        self.memory.next_batch_token().await
+    }
+
+    async fn clear_caches(&self) {
+        self.memory.clear_caches().await
+    }
+}
+
+/// Remove every record held by the adapter.
+///
+/// Returns the names of the collections which contained records.
+pub(crate) async fn wipe(adapter: &CryptoStoreAdapter) -> Result<Vec<String>> {
+    let mut removed = Vec::new();
+
+    for collection in collections::ALL.iter().chain([&collections::QUARANTINE]) {
+        let records = iterate_raw(adapter, collection).await?;
+        if records.is_empty() {
+            continue;
+        }
+
+        for (key, _) in records {
+            delete_record(adapter, collection, &key).await?;
+        }
+
+        removed.push(collection.to_string());
+    }
+
+    Ok(removed)
+}
+
+/// Check that a record of the given collection can be deserialized.
+pub(crate) fn check_record(collection: &str, key: &str, value: &[u8]) -> Result<(), String> {
+    fn parse<T: DeserializeOwned>(value: &[u8]) -> Result<T, String> {
+        serde_json::from_slice(value).map_err(|e| e.to_string())
+    }
+
+    match collection {
+        collections::CORE => match key {
+            core_keys::ACCOUNT => Account::from_pickle(parse::<PickledAccount>(value)?)
+                .map(drop)
+                .map_err(|e| e.to_string()),
+            core_keys::PRIVATE_IDENTITY => parse::<PickledCrossSigningIdentity>(value).map(drop),
+            core_keys::BACKUP_DECRYPTION_KEY => {
+                BackupDecryptionKey::from_base64(&parse::<String>(value)?)
+                    .map(drop)
+                    .map_err(|e| e.to_string())
+            }
+            core_keys::DEHYDRATED_DEVICE_PICKLE_KEY => {
+                DehydratedDeviceKey::from_base64(&parse::<String>(value)?)
+                    .map(drop)
+                    .map_err(|e| e.to_string())
+            }
+            _ => parse::<String>(value).map(drop),
+        },
+        collections::SESSIONS => parse::<PickledSession>(value).map(drop),
+        collections::MESSAGE_HASHES => parse::<OlmMessageHash>(value).map(drop),
+        collections::INBOUND_GROUP_SESSIONS => {
+            InboundGroupSession::from_pickle(parse::<InboundGroupSessionRecord>(value)?.pickle)
+                .map(drop)
+                .map_err(|e| e.to_string())
+        }
+        collections::OUTBOUND_GROUP_SESSIONS => {
+            parse::<PickledOutboundGroupSession>(value).map(drop)
+        }
+        collections::GOSSIP_REQUESTS => parse::<GossipRequest>(value).map(drop),
+        collections::IDENTITIES => parse::<UserIdentityData>(value).map(drop),
+        collections::DEVICES => parse::<DeviceData>(value).map(drop),
+        collections::TRACKED_USERS => {
+            parse_user_id(key).map_err(|e| e.to_string())?;
+            parse::<bool>(value).map(drop)
+        }
+        collections::WITHHELD_SESSIONS => parse::<RoomKeyWithheldEntry>(value).map(drop),
+        collections::ROOM_SETTINGS => {
+            parse_room_id(key).map_err(|e| e.to_string())?;
+            parse::<RoomSettings>(value).map(drop)
+        }
+        collections::SECRETS_INBOX => parse::<SecretsInboxItem>(value).map(drop),
+        collections::RECEIVED_ROOM_KEY_BUNDLES => parse::<StoredRoomKeyBundleData>(value).map(drop),
+        collections::ROOM_KEY_BACKUPS_FULLY_DOWNLOADED => {
+            parse_room_id(key).map_err(|e| e.to_string())?;
+            parse::<bool>(value).map(drop)
+        }
+        collections::ROOMS_PENDING_KEY_BUNDLE => {
+            parse_room_id(key).map_err(|e| e.to_string())?;
+            parse::<RoomPendingKeyBundleDetails>(value).map(drop)
+        }
+        // Custom values are opaque.
+        _ => Ok(()),
+    }
+}
+
+/// Move a record to the [`collections::QUARANTINE`] collection.
+pub(crate) async fn quarantine(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+    key: &str,
+    value: &[u8],
+) -> Result<()> {
+    put_raw(adapter, collections::QUARANTINE, &format!("{collection}|{key}"), value).await?;
+    delete_record(adapter, collection, key).await
+}
+
+/// Remove an inbound group session from the adapter, and from the
+/// `inbound_group_sessions` of the store it belongs to.
+///
+/// Other stores opened on the same adapter still hold the session in memory,
+/// until they are reopened.
+pub(crate) async fn delete_inbound_group_session(
+    adapter: &CryptoStoreAdapter,
+    inbound_group_sessions: &InboundGroupSessions,
+    room_id: &RoomId,
+    session_id: &str,
+) -> Result<()> {
+    delete_record(
+        adapter,
+        collections::INBOUND_GROUP_SESSIONS,
+        &inbound_group_session_key(room_id, session_id),
+    )
+    .await?;
+
+    inbound_group_sessions.0.borrow_mut().remove(&(room_id.to_owned(), session_id.to_owned()));
+
+    Ok(())
+}
+
+/// The key of a device record.
+pub(crate) fn device_key(user_id: &UserId, device_id: &DeviceId) -> String {
+    format!("{user_id}|{device_id}")
+}
+
+/// The key of an inbound group session record.
+pub(crate) fn inbound_group_session_key(room_id: &RoomId, session_id: &str) -> String {
+    format!("{room_id}|{session_id}")
+}
+
+fn parse_room_id(room_id: &str) -> Result<OwnedRoomId> {
+    room_id.try_into().map_err(|e| CryptoStoreError::backend(AdapterError(format!("{e}"))))
+}
+
+fn parse_user_id(user_id: &str) -> Result<matrix_sdk_common::ruma::OwnedUserId> {
+    user_id.try_into().map_err(|e| CryptoStoreError::backend(AdapterError(format!("{e}"))))
+}
+
+/// Read a raw record from the adapter.
+pub(crate) async fn get_raw(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+    key: &str,
+) -> Result<Option<Vec<u8>>> {
+    let value = JsFuture::from(adapter.get_js(collection, key).map_err(AdapterError::from_js)?)
+        .await
+        .map_err(AdapterError::from_js)?;
+
+    if value.is_undefined() || value.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(value.dyn_into::<Uint8Array>().map_err(AdapterError::from_js)?.to_vec()))
+    }
+}
+
+/// Write a raw record to the adapter.
+pub(crate) async fn put_raw(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+    key: &str,
+    value: &[u8],
+) -> Result<()> {
+    let value = Uint8Array::from(value);
+    JsFuture::from(adapter.put_js(collection, key, value).map_err(AdapterError::from_js)?)
+        .await
+        .map_err(AdapterError::from_js)?;
+    Ok(())
+}
+
+/// Remove a record from the adapter.
+pub(crate) async fn delete_record(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+    key: &str,
+) -> Result<()> {
+    JsFuture::from(adapter.delete_js(collection, key).map_err(AdapterError::from_js)?)
+        .await
+        .map_err(AdapterError::from_js)?;
+    Ok(())
+}
+
+/// Read every raw record of a collection from the adapter.
+pub(crate) async fn iterate_raw(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let entries = JsFuture::from(adapter.iterate_js(collection).map_err(AdapterError::from_js)?)
+        .await
+        .map_err(AdapterError::from_js)?;
+
+    let entries: Array = entries.dyn_into().map_err(AdapterError::from_js)?;
+
+    entries
+        .iter()
+        .map(|entry| {
+            let entry: Array = entry.dyn_into().map_err(AdapterError::from_js)?;
+            let key = entry.get(0).as_string().ok_or_else(|| {
+                CryptoStoreError::backend(AdapterError(format!(
+                    "Non-string key in collection `{collection}`"
+                )))
+            })?;
+            let value: Uint8Array = entry.get(1).dyn_into().map_err(AdapterError::from_js)?;
+            Ok((key, value.to_vec()))
+        })
+        .collect()
+}
+
+async fn get_record<T: DeserializeOwned>(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+    key: &str,
+) -> Result<Option<T>> {
+    get_raw(adapter, collection, key)
+        .await?
+        .map(|value| serde_json::from_slice(&value))
+        .transpose()
+        .map_err(CryptoStoreError::from)
+}
+
+async fn put_record<T: Serialize + ?Sized>(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+    key: &str,
+    value: &T,
+) -> Result<()> {
+    put_raw(adapter, collection, key, &serde_json::to_vec(value)?).await
+}
+
+async fn iterate_records<T: DeserializeOwned>(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+) -> Result<Vec<(String, T)>> {
+    iterate_raw(adapter, collection)
+        .await?
+        .into_iter()
+        .map(|(key, value)| Ok((key, serde_json::from_slice(&value)?)))
+        .collect()
+}
+
+async fn iterate_values<T: DeserializeOwned>(
+    adapter: &CryptoStoreAdapter,
+    collection: &str,
+) -> Result<Vec<T>> {
+    Ok(iterate_records(adapter, collection).await?.into_iter().map(|(_, value)| value).collect())
+}
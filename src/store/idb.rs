@@ -0,0 +1,407 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Low-level access to the IndexedDB databases created by
+//! `matrix_sdk_indexeddb`, for the maintenance operations which the store
+//! itself doesn't offer.
+
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use js_sys::{Array, ArrayBuffer, Function, JsString, Object, Promise, Reflect};
+use matrix_sdk_common::ruma::{OwnedRoomId, RoomId};
+use matrix_sdk_crypto::vodozemac::base64_encode;
+use matrix_sdk_store_encryption::StoreCipher;
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    IdbDatabase, IdbFactory, IdbOpenDbRequest, IdbRequest, IdbTransaction, IdbTransactionMode,
+};
+
+use super::rekey::{StoreSecret, StoredCipher};
+
+/// The suffix of the database holding the crypto store data.
+pub(crate) const CRYPTO_DB_SUFFIX: &str = "matrix-sdk-crypto";
+
+/// The suffix of the database holding the crypto store metadata, including
+/// the encrypted store cipher.
+pub(crate) const META_DB_SUFFIX: &str = "matrix-sdk-crypto-meta";
+
+/// The object store of the meta database holding the store cipher.
+pub(crate) const META_CORE_OBJECT_STORE: &str = "matrix-sdk-crypto";
+
+/// The key of the store cipher in [`META_CORE_OBJECT_STORE`].
+pub(crate) const STORE_CIPHER_KEY: &str = "store_cipher";
+
+/// The object store of the crypto database holding the Olm sessions.
+pub(crate) const SESSIONS_OBJECT_STORE: &str = "session";
+
+/// The object store of the crypto database holding the withheld-key records.
+pub(crate) const WITHHELD_SESSIONS_OBJECT_STORE: &str = "direct_withheld_info";
+
+/// The object store of the crypto database holding the inbound group sessions.
+pub(crate) const INBOUND_GROUP_SESSIONS_OBJECT_STORE: &str = "inbound_group_sessions3";
+
+/// The separator between the parts of a compound key, in
+/// `matrix_sdk_indexeddb`.
+const KEY_SEPARATOR: &str = "\u{001D}";
+
+/// The names of the databases used by the store with the given name.
+pub(crate) fn database_names(store_name: &str) -> [String; 2] {
+    [format!("{store_name}::{CRYPTO_DB_SUFFIX}"), meta_database_name(store_name)]
+}
+
+/// The name of the meta database of the store with the given name.
+pub(crate) fn meta_database_name(store_name: &str) -> String {
+    format!("{store_name}::{META_DB_SUFFIX}")
+}
+
+/// Get the global `IDBFactory`.
+pub(crate) fn factory() -> Result<IdbFactory, JsError> {
+    let factory = Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))
+        .map_err(|_| JsError::new("IndexedDB is not available"))?;
+
+    if factory.is_undefined() || factory.is_null() {
+        return Err(JsError::new("IndexedDB is not available"));
+    }
+
+    Ok(factory.unchecked_into())
+}
+
+/// Wait for an `IDBRequest` to complete, returning its result.
+///
+/// For the requests opening or deleting a database, the `blocked` event, sent
+/// when other connections to the database are still open, is reported as an
+/// error rather than waited out.
+pub(crate) async fn request_result(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        if let Some(open_request) = request.dyn_ref::<IdbOpenDbRequest>() {
+            let reject_on_blocked = reject.clone();
+            let on_blocked = Closure::once_into_js(move || {
+                let error = js_sys::Error::new(
+                    "The database is still open elsewhere: close every `StoreHandle` and \
+                    `OlmMachine` using it first",
+                );
+                let _ = reject_on_blocked.call1(&JsValue::NULL, &error);
+            });
+            open_request.set_onblocked(Some(on_blocked.unchecked_ref()));
+        }
+
+        let request_for_success = request.clone();
+        let on_success = Closure::once_into_js(move || {
+            let _ =
+                resolve.call1(&JsValue::NULL, &request_for_success.result().unwrap_or_default());
+        });
+
+        let request_for_error = request.clone();
+        let on_error = Closure::once_into_js(move || {
+            let error = request_for_error.error().ok().flatten().map(JsValue::from);
+            let _ = reject.call1(&JsValue::NULL, &error.unwrap_or_default());
+        });
+
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+
+    JsFuture::from(promise).await
+}
+
+/// Wait for an `IDBTransaction` to be committed.
+pub(crate) async fn transaction_complete(transaction: &IdbTransaction) -> Result<(), JsValue> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let on_complete = Closure::once_into_js(move || {
+            let _ = resolve.call0(&JsValue::NULL);
+        });
+
+        let reject_on_abort = reject.clone();
+        let on_error = Closure::once_into_js(move || {
+            let _ = reject.call1(&JsValue::NULL, &JsValue::from_str("The transaction failed"));
+        });
+        let on_abort = Closure::once_into_js(move || {
+            let _ = reject_on_abort
+                .call1(&JsValue::NULL, &JsValue::from_str("The transaction was aborted"));
+        });
+
+        transaction.set_oncomplete(Some(on_complete.unchecked_ref()));
+        transaction.set_onerror(Some(on_error.unchecked_ref()));
+        transaction.set_onabort(Some(on_abort.unchecked_ref()));
+    });
+
+    JsFuture::from(promise).await.map(|_| ())
+}
+
+/// Open an existing database, without upgrading it.
+///
+/// Returns `None` if there is no database with this name.
+pub(crate) async fn open_existing(name: &str) -> Result<Option<IdbDatabase>, JsError> {
+    let request = factory()?
+        .open(name)
+        .map_err(|_| JsError::new(&format!("Failed to open the database `{name}`")))?;
+
+    // If the database doesn't exist, opening it creates it at version 1; abort
+    // the upgrade so that we don't leave an empty database behind.
+    let not_found = Rc::new(Cell::new(false));
+    let request_for_upgrade = request.clone();
+    let not_found_for_upgrade = not_found.clone();
+    let on_upgrade_needed = Closure::once_into_js(move || {
+        not_found_for_upgrade.set(true);
+        if let Some(transaction) = request_for_upgrade.transaction() {
+            let _ = transaction.abort();
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade_needed.unchecked_ref()));
+
+    match request_result(&request).await {
+        Ok(database) => Ok(Some(database.unchecked_into())),
+        // The creation of the database has been aborted above.
+        Err(_) if not_found.get() => Ok(None),
+        Err(error) => Err(JsError::new(&format!(
+            "Failed to open the database `{name}`: {}",
+            error_message(&error)
+        ))),
+    }
+}
+
+/// Get a readable message from a `DOMException`, or any other error.
+fn error_message(error: &JsValue) -> String {
+    Reflect::get(error, &JsValue::from_str("message"))
+        .ok()
+        .and_then(|message| message.as_string())
+        .unwrap_or_else(|| format!("{error:?}"))
+}
+
+/// Read a single value from an object store.
+pub(crate) async fn get_value(
+    database: &IdbDatabase,
+    object_store: &str,
+    key: &str,
+) -> Result<JsValue, JsError> {
+    let transaction = database
+        .transaction_with_str(object_store)
+        .map_err(|_| JsError::new(&format!("Missing object store `{object_store}`")))?;
+    let request = transaction
+        .object_store(object_store)
+        .and_then(|store| store.get(&JsValue::from_str(key)))
+        .map_err(|_| JsError::new(&format!("Failed to read `{key}`")))?;
+
+    request_result(&request).await.map_err(|_| JsError::new(&format!("Failed to read `{key}`")))
+}
+
+/// Write values into an object store in a single transaction, and wait for
+/// it to be committed.
+pub(crate) async fn put_values(
+    database: &IdbDatabase,
+    object_store: &str,
+    values: &[(&str, &JsValue)],
+) -> Result<(), JsError> {
+    let transaction = database
+        .transaction_with_str_and_mode(object_store, IdbTransactionMode::Readwrite)
+        .map_err(|_| JsError::new(&format!("Missing object store `{object_store}`")))?;
+    let store = transaction
+        .object_store(object_store)
+        .map_err(|_| JsError::new(&format!("Missing object store `{object_store}`")))?;
+
+    for (key, value) in values {
+        store
+            .put_with_key(value, &JsValue::from_str(key))
+            .map_err(|_| JsError::new(&format!("Failed to write `{key}`")))?;
+    }
+
+    transaction_complete(&transaction)
+        .await
+        .map_err(|_| JsError::new(&format!("Failed to write to `{object_store}`")))
+}
+
+/// Delete the databases used by the store with the given name.
+///
+/// If another connection to one of the databases is still open, an error is
+/// returned; the database is then deleted once that connection is closed.
+///
+/// Returns the names of the databases which existed and have been deleted.
+pub(crate) async fn delete_store_databases(store_name: &str) -> Result<Vec<String>, JsError> {
+    let factory = factory()?;
+    let mut removed = Vec::new();
+
+    for name in database_names(store_name) {
+        match open_existing(&name).await? {
+            Some(database) => database.close(),
+            None => continue,
+        }
+
+        let request = factory
+            .delete_database(&name)
+            .map_err(|_| JsError::new(&format!("Failed to delete the database `{name}`")))?;
+        request_result(&request).await.map_err(|error| {
+            JsError::new(&format!(
+                "Failed to delete the database `{name}`: {}",
+                error_message(&error)
+            ))
+        })?;
+
+        removed.push(name);
+    }
+
+    Ok(removed)
+}
+
+/// Read the store cipher of the store with the given name, with the secret it
+/// was opened with.
+pub(crate) async fn store_cipher(
+    store_name: &str,
+    secret: &StoreSecret,
+) -> Result<StoreCipher, JsError> {
+    let database = open_existing(&meta_database_name(store_name))
+        .await?
+        .ok_or_else(|| JsError::new(&format!("There is no crypto store named `{store_name}`")))?;
+    let stored = get_value(&database, META_CORE_OBJECT_STORE, STORE_CIPHER_KEY).await;
+    database.close();
+
+    let stored = StoredCipher::from_js(stored?)?
+        .ok_or_else(|| JsError::new("The crypto store is not encrypted"))?;
+
+    secret
+        .import_cipher(&stored.exported)
+        .ok_or_else(|| JsError::new("The secret doesn't match the crypto store"))
+}
+
+/// Delete the given inbound group sessions, by room ID, from the store with
+/// the given name, in a single transaction.
+///
+/// `cipher` is the store cipher of the store, if it is encrypted.
+pub(crate) async fn delete_inbound_group_sessions(
+    store_name: &str,
+    cipher: Option<&StoreCipher>,
+    sessions: &BTreeMap<OwnedRoomId, BTreeSet<String>>,
+) -> Result<(), JsError> {
+    let name = format!("{store_name}::{CRYPTO_DB_SUFFIX}");
+    let database = open_existing(&name)
+        .await?
+        .ok_or_else(|| JsError::new(&format!("There is no crypto store named `{store_name}`")))?;
+
+    let result = async {
+        let error = || JsError::new("Failed to delete the inbound group sessions");
+        let transaction = database
+            .transaction_with_str_and_mode(
+                INBOUND_GROUP_SESSIONS_OBJECT_STORE,
+                IdbTransactionMode::Readwrite,
+            )
+            .map_err(|_| error())?;
+        let object_store =
+            transaction.object_store(INBOUND_GROUP_SESSIONS_OBJECT_STORE).map_err(|_| error())?;
+
+        for (room_id, session_ids) in sessions {
+            for session_id in session_ids {
+                let key = inbound_group_session_key(cipher, room_id, session_id);
+                object_store.delete(&JsValue::from(key)).map_err(|_| error())?;
+            }
+        }
+
+        transaction_complete(&transaction).await.map_err(|_| error())
+    }
+    .await;
+    database.close();
+
+    result
+}
+
+/// The key of an inbound group session in
+/// [`INBOUND_GROUP_SESSIONS_OBJECT_STORE`].
+///
+/// This follows the encoding of the keys of `matrix_sdk_indexeddb`: each part
+/// is percent-encoded, then hashed with the store cipher if there is one.
+fn inbound_group_session_key(
+    cipher: Option<&StoreCipher>,
+    room_id: &RoomId,
+    session_id: &str,
+) -> String {
+    let encode = |part: &str| {
+        let encoded = percent_encode(part);
+        match cipher {
+            Some(cipher) => base64_encode(
+                cipher.hash_key(INBOUND_GROUP_SESSIONS_OBJECT_STORE, encoded.as_bytes()),
+            ),
+            None => encoded,
+        }
+    };
+
+    [encode(room_id.as_str()), KEY_SEPARATOR.to_owned(), encode(session_id)].concat()
+}
+
+/// Percent-encode the control characters and the non-ASCII bytes of a key
+/// part, as `matrix_sdk_indexeddb` does.
+fn percent_encode(part: &str) -> String {
+    let mut encoded = String::with_capacity(part.len());
+    for byte in part.bytes() {
+        if byte.is_ascii_control() || !byte.is_ascii() {
+            encoded.push_str(&format!("%{byte:02X}"));
+        } else {
+            encoded.push(char::from(byte));
+        }
+    }
+    encoded
+}
+
+/// Read all the values of an object store.
+pub(crate) async fn get_all_values(
+    database: &IdbDatabase,
+    object_store: &str,
+) -> Result<Array, JsError> {
+    let request = database
+        .transaction_with_str(object_store)
+        .and_then(|transaction| transaction.object_store(object_store))
+        .and_then(|store| store.get_all())
+        .map_err(|_| JsError::new(&format!("Failed to read the object store `{object_store}`")))?;
+
+    let values = request_result(&request)
+        .await
+        .map_err(|_| JsError::new(&format!("Failed to read the object store `{object_store}`")))?;
+
+    Ok(values.unchecked_into())
+}
+
+/// Estimate the number of bytes taken by a value stored in IndexedDB.
+///
+/// Binary data is counted by its length, strings as two bytes per UTF-16 code
+/// unit, and other primitives as 8 bytes.
+pub(crate) fn approximate_size(value: &JsValue) -> usize {
+    if value.is_undefined() || value.is_null() {
+        0
+    } else if let Some(string) = value.dyn_ref::<JsString>() {
+        string.length() as usize * 2
+    } else if value.as_f64().is_some() || value.as_bool().is_some() {
+        8
+    } else if let Some(buffer) = value.dyn_ref::<ArrayBuffer>() {
+        buffer.byte_length() as usize
+    } else if ArrayBuffer::is_view(value) {
+        Reflect::get(value, &JsValue::from_str("byteLength"))
+            .ok()
+            .and_then(|length| length.as_f64())
+            .unwrap_or_default() as usize
+    } else if let Some(array) = value.dyn_ref::<Array>() {
+        array.iter().map(|item| approximate_size(&item)).sum()
+    } else if value.is_object() {
+        Object::entries(value.unchecked_ref())
+            .iter()
+            .map(|entry| {
+                let entry: Array = entry.unchecked_into();
+                approximate_size(&entry.get(0)) + approximate_size(&entry.get(1))
+            })
+            .sum()
+    } else {
+        0
+    }
+}
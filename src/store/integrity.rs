@@ -0,0 +1,260 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Integrity checks of the content of a crypto store.
+
+use std::collections::BTreeSet;
+
+use js_sys::{Array, Object};
+use matrix_sdk_crypto::store::DynCryptoStore;
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use super::{
+    custom::{self, CryptoStoreAdapter},
+    INBOX_SECRET_NAMES,
+};
+
+/// The TypeScript type for the options of the integrity checks.
+#[wasm_bindgen(typescript_custom_section)]
+const INTEGRITY_CHECK_OPTIONS_TYPE: &str = r#"
+/**
+ * Options for {@link StoreHandle.verifyIntegrity} and
+ * {@link StoreHandle.verifyCustomIntegrity}.
+ */
+interface IntegrityOptions {
+    /**
+     * Whether unreadable records should be moved to a quarantine collection.
+     * Only supported for stores opened with {@link StoreHandle.openCustom}.
+     * Defaults to `false`.
+     */
+    repair?: boolean;
+}"#;
+
+/// The options of the integrity checks.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct IntegrityOptions {
+    #[serde(default)]
+    pub(crate) repair: bool,
+}
+
+impl IntegrityOptions {
+    pub(crate) fn from_js(value: Option<Object>) -> Result<Self, JsError> {
+        match value {
+            Some(value) => Ok(serde_wasm_bindgen::from_value(value.into())?),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// The result of an integrity check of a crypto store.
+#[derive(Clone, Debug, Default)]
+#[wasm_bindgen]
+pub struct IntegrityReport {
+    /// The number of records, or groups of records, which were checked.
+    #[wasm_bindgen(readonly)]
+    pub checked: usize,
+
+    /// The number of unreadable records which were moved to the quarantine
+    /// collection.
+    #[wasm_bindgen(readonly)]
+    pub quarantined: usize,
+
+    problems: Vec<IntegrityProblem>,
+}
+
+#[wasm_bindgen]
+impl IntegrityReport {
+    /// Whether no problem was found.
+    #[wasm_bindgen(getter, js_name = "isHealthy")]
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// The problems which were found.
+    #[wasm_bindgen(unchecked_return_type = "IntegrityProblem[]")]
+    pub fn problems(&self) -> Array {
+        self.problems.iter().cloned().map(JsValue::from).collect()
+    }
+}
+
+impl IntegrityReport {
+    /// Add the results of another check to this report.
+    pub(crate) fn merge(&mut self, other: IntegrityReport) {
+        self.checked += other.checked;
+        self.quarantined += other.quarantined;
+        self.problems.extend(other.problems);
+    }
+
+    fn check<T, E: std::fmt::Display>(
+        &mut self,
+        collection: &str,
+        key: Option<&str>,
+        result: Result<T, E>,
+    ) -> Option<T> {
+        self.checked += 1;
+
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.problems.push(IntegrityProblem {
+                    collection: collection.to_owned(),
+                    key: key.map(ToOwned::to_owned),
+                    error: e.to_string(),
+                    quarantined: false,
+                });
+                None
+            }
+        }
+    }
+}
+
+/// A problem found by an integrity check.
+#[derive(Clone, Debug)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct IntegrityProblem {
+    /// The collection holding the unreadable data.
+    #[wasm_bindgen(readonly)]
+    pub collection: String,
+
+    /// The key of the unreadable record, or of the group of records which
+    /// could not be read, if known.
+    #[wasm_bindgen(readonly)]
+    pub key: Option<String>,
+
+    /// A description of the error.
+    #[wasm_bindgen(readonly)]
+    pub error: String,
+
+    /// Whether the record was moved to the quarantine collection.
+    #[wasm_bindgen(readonly)]
+    pub quarantined: bool,
+}
+
+/// Check that the content of a store can be loaded, through the
+/// `CryptoStore` interface.
+///
+/// Some data can only be loaded as a whole, so problems are reported per
+/// group of records: for example, the devices of one user, or all the
+/// inbound group sessions.
+pub(crate) async fn verify_store(store: &DynCryptoStore) -> IntegrityReport {
+    let mut report = IntegrityReport::default();
+
+    report.check("account", None, store.load_account().await);
+    report.check("private_identity", None, store.load_identity().await);
+    report.check("backup_keys", None, store.load_backup_keys().await);
+    report.check(
+        "dehydrated_device_pickle_key",
+        None,
+        store.load_dehydrated_device_pickle_key().await,
+    );
+    report.check("next_batch_token", None, store.next_batch_token().await);
+    report.check("gossip_requests", None, store.get_unsent_secret_requests().await);
+
+    for secret_name in INBOX_SECRET_NAMES {
+        report.check(
+            "secrets_inbox",
+            Some(secret_name.as_str()),
+            store.get_secrets_from_inbox(secret_name).await,
+        );
+    }
+
+    let tracked_users =
+        report.check("tracked_users", None, store.load_tracked_users().await).unwrap_or_default();
+
+    for tracked_user in &tracked_users {
+        let user_id = tracked_user.user_id.as_str();
+
+        report.check(
+            "identities",
+            Some(user_id),
+            store.get_user_identity(&tracked_user.user_id).await,
+        );
+
+        let devices = report
+            .check("devices", Some(user_id), store.get_user_devices(&tracked_user.user_id).await)
+            .unwrap_or_default();
+
+        for device in devices.values() {
+            if let Some(sender_key) = device.curve25519_key() {
+                let sender_key = sender_key.to_base64();
+                report.check("sessions", Some(&sender_key), store.get_sessions(&sender_key).await);
+            }
+        }
+    }
+
+    let inbound_group_sessions = report
+        .check("inbound_group_sessions", None, store.get_inbound_group_sessions().await)
+        .unwrap_or_default();
+
+    let rooms: BTreeSet<_> =
+        inbound_group_sessions.iter().map(|session| session.room_id().to_owned()).collect();
+
+    for room_id in &rooms {
+        let key = Some(room_id.as_str());
+
+        report.check(
+            "outbound_group_sessions",
+            key,
+            store.get_outbound_group_session(room_id).await,
+        );
+        report.check("room_settings", key, store.get_room_settings(room_id).await);
+        report.check(
+            "withheld_sessions",
+            key,
+            store.get_withheld_sessions_by_room_id(room_id).await,
+        );
+    }
+
+    report
+}
+
+/// Check that every record held by a custom store adapter can be read,
+/// optionally moving the unreadable ones to the quarantine collection.
+pub(crate) async fn verify_adapter(
+    adapter: &CryptoStoreAdapter,
+    options: &IntegrityOptions,
+) -> Result<IntegrityReport, JsError> {
+    let mut report = IntegrityReport::default();
+
+    for collection in custom::collections::ALL {
+        let records = match custom::iterate_raw(adapter, collection).await {
+            Ok(records) => records,
+            Err(e) => {
+                report.check::<(), _>(collection, None, Err(e));
+                continue;
+            }
+        };
+
+        for (key, value) in records {
+            if report
+                .check(collection, Some(&key), custom::check_record(collection, &key, &value))
+                .is_some()
+            {
+                continue;
+            }
+
+            if options.repair {
+                custom::quarantine(adapter, collection, &key, &value).await?;
+
+                report.quarantined += 1;
+                if let Some(problem) = report.problems.last_mut() {
+                    problem.quarantined = true;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
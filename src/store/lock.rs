@@ -0,0 +1,310 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A lease lock over a crypto store, shared between JavaScript contexts.
+
+use std::{cell::RefCell, rc::Rc, sync::Arc, time::Duration};
+
+use js_sys::{Date, Function, Promise};
+use matrix_sdk_common::{cross_process_lock::CrossProcessLockGeneration, sleep::sleep};
+use matrix_sdk_crypto::store::DynCryptoStore;
+use tracing::warn;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{future::future_to_promise, machine::promise_result_to_future};
+
+/// The key under which the lease is stored in the crypto store.
+const LOCK_KEY: &str = "crypto_store_lock";
+
+/// The default duration of a lease, in milliseconds.
+pub(crate) const DEFAULT_LEASE_DURATION_MS: u32 = 10_000;
+
+#[derive(Default)]
+struct LockState {
+    /// The generation of the lease we hold, if any.
+    generation: Option<CrossProcessLockGeneration>,
+
+    /// When the lease we hold expires unless renewed, in milliseconds since
+    /// the Unix epoch.
+    expires_at_ms: f64,
+
+    /// Incremented whenever the automatic renewal must stop.
+    renewal_epoch: u64,
+
+    /// The callback to call when the lease is lost.
+    on_lost: Option<Function>,
+}
+
+/// A lease lock over a crypto store, so that only one JavaScript context (a
+/// browser tab, a worker, …) at a time uses it.
+///
+/// The lease is stored in the crypto store itself, so contexts opening the
+/// same IndexedDB-based store share it. Once acquired, it is renewed
+/// automatically until it is released, or lost to another holder.
+///
+/// Instances are created with {@link StoreHandle.createLock}. Freeing an
+/// instance releases the lock.
+#[wasm_bindgen]
+pub struct StoreLock {
+    store: Arc<DynCryptoStore>,
+    holder: String,
+    lease_duration_ms: u32,
+    state: Rc<RefCell<LockState>>,
+}
+
+impl StoreLock {
+    pub(crate) fn new(store: Arc<DynCryptoStore>, holder: String, lease_duration_ms: u32) -> Self {
+        Self { store, holder, lease_duration_ms, state: Default::default() }
+    }
+}
+
+#[wasm_bindgen]
+impl StoreLock {
+    /// The name identifying this holder of the lock.
+    #[wasm_bindgen(getter)]
+    pub fn holder(&self) -> String {
+        self.holder.clone()
+    }
+
+    /// Whether we currently hold the lock.
+    ///
+    /// This is `false` once our lease has expired, even if the lost callback
+    /// hasn't been called yet, for example because this context was
+    /// suspended.
+    #[wasm_bindgen(getter, js_name = "isHeld")]
+    pub fn is_held(&self) -> bool {
+        let state = self.state.borrow();
+        state.generation.is_some() && Date::now() < state.expires_at_ms
+    }
+
+    /// Try to acquire the lock.
+    ///
+    /// Once acquired, the lease is renewed automatically.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for `true` if the lock is now held by us, or `false` if
+    /// another holder has it.
+    #[wasm_bindgen(unchecked_return_type = "Promise<boolean>")]
+    pub fn acquire(&self) -> Promise {
+        let store = self.store.clone();
+        let holder = self.holder.clone();
+        let lease_duration_ms = self.lease_duration_ms;
+        let state = self.state.clone();
+
+        future_to_promise(async move {
+            let epoch = {
+                let state = state.borrow();
+                if state.generation.is_some() {
+                    return Ok(true);
+                }
+                state.renewal_epoch
+            };
+
+            let requested_at = Date::now();
+            let Some(generation) =
+                store.try_take_leased_lock(lease_duration_ms, LOCK_KEY, &holder).await?
+            else {
+                return Ok(false);
+            };
+
+            if state.borrow().renewal_epoch != epoch {
+                // The lock has been released, or freed, in the meantime.
+                release_lease(&store, &holder).await;
+                return Ok(false);
+            }
+
+            let epoch = {
+                let mut state = state.borrow_mut();
+                state.generation = Some(generation);
+                state.expires_at_ms = requested_at + f64::from(lease_duration_ms);
+                state.renewal_epoch += 1;
+                state.renewal_epoch
+            };
+
+            spawn_local(renew_periodically(store, holder, lease_duration_ms, state, epoch));
+
+            Ok(true)
+        })
+    }
+
+    /// Renew the lease now, rather than waiting for the automatic renewal.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for `true` if the lock is still held by us. If it has been
+    /// lost, the callback registered with {@link registerLostCallback} is
+    /// called.
+    #[wasm_bindgen(unchecked_return_type = "Promise<boolean>")]
+    pub fn renew(&self) -> Promise {
+        let store = self.store.clone();
+        let holder = self.holder.clone();
+        let lease_duration_ms = self.lease_duration_ms;
+        let state = self.state.clone();
+
+        future_to_promise(async move {
+            if state.borrow().generation.is_none() {
+                return Ok(false);
+            }
+
+            Ok(renew_once(&store, &holder, lease_duration_ms, &state).await)
+        })
+    }
+
+    /// Release the lock, if we hold it, so that another holder can acquire
+    /// it straight away.
+    #[wasm_bindgen(unchecked_return_type = "Promise<void>")]
+    pub fn release(&self) -> Promise {
+        let store = self.store.clone();
+        let holder = self.holder.clone();
+        let state = self.state.clone();
+
+        future_to_promise(async move {
+            let held = {
+                let mut state = state.borrow_mut();
+                state.renewal_epoch += 1;
+                state.generation.take().is_some()
+            };
+
+            if held {
+                // A lease of zero milliseconds expires immediately.
+                store.try_take_leased_lock(0, LOCK_KEY, &holder).await?;
+            }
+
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Register a callback which will be called if the lock is lost, because
+    /// another holder took it over.
+    ///
+    /// Another holder can only take the lock over once our lease has expired,
+    /// for example because this context was suspended for too long. When this
+    /// happens, the state of the store may have been changed by the other
+    /// holder: any `OlmMachine` using it should be recreated before the lock
+    /// is acquired again.
+    ///
+    /// `callback` should be a function that takes no argument and returns a
+    /// Promise.
+    #[wasm_bindgen(js_name = "registerLostCallback")]
+    pub fn register_lost_callback(
+        &self,
+        #[wasm_bindgen(unchecked_param_type = "() => Promise<void>")] callback: Function,
+    ) {
+        self.state.borrow_mut().on_lost = Some(callback);
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        // Stop the automatic renewal, and give the lease up.
+        let held = {
+            let mut state = self.state.borrow_mut();
+            state.renewal_epoch += 1;
+            state.generation.take().is_some()
+        };
+
+        if held {
+            let store = self.store.clone();
+            let holder = self.holder.clone();
+            spawn_local(async move { release_lease(&store, &holder).await });
+        }
+    }
+}
+
+/// Renew the lease every half lease duration, until the renewal epoch changes
+/// or the lease is lost.
+async fn renew_periodically(
+    store: Arc<DynCryptoStore>,
+    holder: String,
+    lease_duration_ms: u32,
+    state: Rc<RefCell<LockState>>,
+    epoch: u64,
+) {
+    let period = Duration::from_millis((lease_duration_ms / 2).into());
+
+    loop {
+        sleep(period).await;
+
+        if state.borrow().renewal_epoch != epoch {
+            break;
+        }
+
+        if !renew_once(&store, &holder, lease_duration_ms, &state).await {
+            break;
+        }
+    }
+}
+
+/// Extend our lease, returning whether we still hold the lock.
+async fn renew_once(
+    store: &DynCryptoStore,
+    holder: &str,
+    lease_duration_ms: u32,
+    state: &Rc<RefCell<LockState>>,
+) -> bool {
+    let previous = state.borrow().generation;
+    let requested_at = Date::now();
+
+    match store.try_take_leased_lock(lease_duration_ms, LOCK_KEY, holder).await {
+        Ok(Some(generation)) if Some(generation) == previous => {
+            state.borrow_mut().expires_at_ms = requested_at + f64::from(lease_duration_ms);
+            true
+        }
+
+        Ok(Some(_)) => {
+            // Another holder had the lock in the meantime, so whatever we
+            // cached about the store may be stale: give the lock up.
+            release_lease(store, holder).await;
+            on_lost(state).await;
+            false
+        }
+
+        Ok(None) => {
+            on_lost(state).await;
+            false
+        }
+
+        Err(e) => {
+            warn!("Failed to renew the store lock: {e:?}");
+            on_lost(state).await;
+            false
+        }
+    }
+}
+
+/// Give our lease up, so that another holder can take the lock straight away.
+async fn release_lease(store: &DynCryptoStore, holder: &str) {
+    // A lease of zero milliseconds expires immediately.
+    if let Err(e) = store.try_take_leased_lock(0, LOCK_KEY, holder).await {
+        warn!("Failed to release the store lock: {e:?}");
+    }
+}
+
+/// Mark the lock as lost, and call the registered callback.
+async fn on_lost(state: &Rc<RefCell<LockState>>) {
+    let callback = {
+        let mut state = state.borrow_mut();
+        state.generation = None;
+        state.renewal_epoch += 1;
+        state.on_lost.clone()
+    };
+
+    if let Some(callback) = callback {
+        if let Err(e) = promise_result_to_future(callback.call0(&JsValue::NULL)).await {
+            warn!("Error calling the store lock lost callback: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,780 @@
+//! Store types.
+
+mod custom;
+mod idb;
+mod integrity;
+mod lock;
+mod rekey;
+mod session_cache;
+pub(crate) mod session_times;
+mod snapshot;
+mod stats;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use js_sys::{Array, Object, Promise, Uint8Array};
+use matrix_sdk_common::ruma::{events::secret::request::SecretName, OwnedRoomId};
+use matrix_sdk_crypto::{
+    store::{DynCryptoStore, IntoCryptoStore, MemoryStore},
+    types::BackupSecrets,
+};
+use tracing::{dispatcher, instrument::WithSubscriber};
+use wasm_bindgen::prelude::*;
+use zeroize::{Zeroize, Zeroizing};
+
+use self::custom::{CryptoStoreAdapter, CustomStore};
+pub use self::{
+    integrity::{IntegrityProblem, IntegrityReport},
+    lock::StoreLock,
+    stats::{CollectionStats, StoreStats},
+};
+pub(crate) use self::{
+    session_cache::{CachingStore, InboundGroupSessionCache},
+    stats::store_stats,
+};
+use crate::{
+    encryption::EncryptionAlgorithm,
+    future::future_to_promise,
+    identifiers::{RoomId, UserId},
+    impl_from_to_inner,
+    tracing::{logger_to_dispatcher, JsLogger},
+    vodozemac::Curve25519PublicKey,
+};
+
+/// The secrets which can be found in the secrets inbox.
+pub(crate) const INBOX_SECRET_NAMES: &[SecretName] = &[
+    SecretName::CrossSigningMasterKey,
+    SecretName::CrossSigningSelfSigningKey,
+    SecretName::CrossSigningUserSigningKey,
+    SecretName::RecoveryKey,
+    SecretName::RoomKeyBackup,
+];
+
+/// A struct containing an open connection to a CryptoStore.
+///
+/// Opening the CryptoStore can take some time, due to the PBKDF calculation
+/// involved, so if multiple operations are being done on the same store, it is
+/// more efficient to open it once.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct StoreHandle {
+    pub(crate) store: Arc<DynCryptoStore>,
+    pub(crate) backend: StoreBackend,
+}
+
+/// Where the data of a [`StoreHandle`] is kept.
+#[derive(Clone, Debug)]
+pub(crate) enum StoreBackend {
+    /// A memory store, which is lost once dropped.
+    Memory,
+
+    /// An IndexedDB-based store, with the given name.
+    ///
+    /// The secret it was opened with is kept to read its store cipher, when
+    /// exporting a snapshot.
+    IndexedDb { store_name: String, secret: Option<rekey::StoreSecret> },
+
+    /// A store persisted by a JavaScript adapter.
+    ///
+    /// The inbound group sessions of the store are shared with it, so that
+    /// they can be deleted.
+    Custom { adapter: CryptoStoreAdapter, inbound_group_sessions: custom::InboundGroupSessions },
+}
+
+impl StoreBackend {
+    /// Remove all the data persisted for this store.
+    ///
+    /// Returns the names of the IndexedDB databases, or of the adapter
+    /// collections, which have been removed.
+    pub(crate) async fn wipe(&self) -> Result<Vec<String>, JsError> {
+        Ok(match self {
+            Self::Memory => Vec::new(),
+            Self::IndexedDb { store_name, .. } => idb::delete_store_databases(store_name).await?,
+            Self::Custom { adapter, .. } => custom::wipe(adapter).await?,
+        })
+    }
+
+    /// Delete the given inbound group sessions, by room ID.
+    ///
+    /// Memory stores don't support this, as the sessions can't be removed from
+    /// them.
+    pub(crate) async fn delete_inbound_group_sessions(
+        &self,
+        sessions: &BTreeMap<OwnedRoomId, BTreeSet<String>>,
+    ) -> Result<(), JsError> {
+        match self {
+            Self::Memory => {
+                return Err(JsError::new(
+                    "Inbound group sessions can't be deleted from memory stores",
+                ))
+            }
+            Self::IndexedDb { store_name, secret } => {
+                let cipher = match secret {
+                    Some(secret) => Some(idb::store_cipher(store_name, secret).await?),
+                    None => None,
+                };
+                idb::delete_inbound_group_sessions(store_name, cipher.as_ref(), sessions).await?;
+            }
+            Self::Custom { adapter, inbound_group_sessions } => {
+                for (room_id, session_ids) in sessions {
+                    for session_id in session_ids {
+                        custom::delete_inbound_group_session(
+                            adapter,
+                            inbound_group_sessions,
+                            room_id,
+                            session_id,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl StoreHandle {
+    /// Open a crypto store.
+    ///
+    /// The created store will be based on IndexedDB if a `store_name` is
+    /// provided; otherwise it will be based on a memory store and once the
+    /// objects is dropped, the keys will be lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_name` - The name that should be used to open the IndexedDB
+    ///   based database. If this isn't provided, a memory-only store will be
+    ///   used. *Note* the memory-only store will lose your E2EE keys when the
+    ///   `StoreHandle` gets dropped.
+    ///
+    /// * `store_passphrase` - The passphrase that should be used to encrypt the
+    ///   store, for IndexedDB-based stores
+    ///
+    /// * `logger` - An optional logger instance to use for writing log messages
+    ///   during the open operation. An instance of `JsLogger`.
+    #[wasm_bindgen(js_name = "open", unchecked_return_type = "Promise<StoreHandle>")]
+    pub fn open_for_js(
+        store_name: Option<String>,
+        store_passphrase: Option<String>,
+        #[wasm_bindgen(unchecked_optional_param_type = "JsLogger")] logger: Option<JsLogger>,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&logger_to_dispatcher(logger));
+        future_to_promise(async move {
+            StoreHandle::open(store_name, store_passphrase).with_current_subscriber().await
+        })
+    }
+
+    pub(crate) async fn open(
+        store_name: Option<String>,
+        store_passphrase: Option<String>,
+    ) -> Result<StoreHandle, JsError> {
+        let (store, backend) = match store_name {
+            Some(store_name) => {
+                let secret = store_passphrase.clone().map(rekey::StoreSecret::passphrase);
+
+                (
+                    Self::open_indexeddb(&store_name, store_passphrase).await?,
+                    StoreBackend::IndexedDb { store_name, secret },
+                )
+            }
+
+            None => {
+                if store_passphrase.is_some() {
+                    return Err(JsError::new(
+                        "The `store_passphrase` has been set, but it has an effect only if \
+                        `store_name` is set, which is not; please provide one",
+                    ));
+                }
+
+                (MemoryStore::new().into_crypto_store(), StoreBackend::Memory)
+            }
+        };
+
+        Ok(Self { store, backend })
+    }
+
+    async fn open_indexeddb(
+        store_name: &str,
+        store_passphrase: Option<String>,
+    ) -> Result<Arc<DynCryptoStore>, matrix_sdk_indexeddb::IndexeddbCryptoStoreError> {
+        let store = match store_passphrase {
+            Some(mut store_passphrase) => {
+                use zeroize::Zeroize;
+
+                let store = matrix_sdk_indexeddb::IndexeddbCryptoStore::open_with_passphrase(
+                    store_name,
+                    &store_passphrase,
+                )
+                .await?;
+
+                store_passphrase.zeroize();
+                store
+            }
+
+            None => matrix_sdk_indexeddb::IndexeddbCryptoStore::open_with_name(store_name).await?,
+        };
+
+        Ok(store.into_crypto_store())
+    }
+
+    /// Open a crypto store based on IndexedDB, using the given key for
+    /// encryption.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_name` - The name that should be used to open the IndexedDB
+    ///   based database.
+    ///
+    /// * `store_key` - The key that should be used to encrypt the store, for
+    ///   IndexedDB-based stores. Must be a 32-byte array.
+    ///
+    /// * `logger` - An optional logger instance to use for writing log messages
+    ///   during the open operation. An instance of `JsLogger`.
+    #[wasm_bindgen(js_name = "openWithKey", unchecked_return_type = "Promise<StoreHandle>")]
+    pub fn open_with_key(
+        store_name: String,
+        mut store_key: Vec<u8>,
+        #[wasm_bindgen(unchecked_optional_param_type = "JsLogger")] logger: Option<JsLogger>,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&logger_to_dispatcher(logger));
+        future_to_promise(async move {
+            let store_key_array: Zeroizing<[u8; 32]> = Zeroizing::new(
+                store_key
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| JsError::new("Expected a key of length 32"))?,
+            );
+            store_key.zeroize();
+
+            let store = matrix_sdk_indexeddb::IndexeddbCryptoStore::open_with_key(
+                &store_name,
+                &store_key_array,
+            )
+            .await?;
+
+            Ok(Self {
+                store: store.into_crypto_store(),
+                backend: StoreBackend::IndexedDb {
+                    store_name,
+                    secret: Some(rekey::StoreSecret::key(&store_key_array)?),
+                },
+            })
+        })
+    }
+
+    /// Open a crypto store whose persistence is implemented in JavaScript.
+    ///
+    /// All the records held by the adapter are loaded when the store is
+    /// opened, and every subsequent change is written through to it. The
+    /// adapter is responsible for encrypting the records at rest, if needed.
+    ///
+    /// Leases taken on the store, for example by {@link StoreLock}, are not
+    /// written to the adapter, so they don't exclude other JavaScript contexts
+    /// opening a store on the same underlying storage.
+    ///
+    /// # Arguments
+    ///
+    /// * `adapter` - A `CryptoStoreAdapter` providing async `get`, `put`,
+    ///   `delete` and `iterate` methods over named collections.
+    ///
+    /// * `logger` - An optional logger instance to use for writing log messages
+    ///   during the open operation. An instance of `JsLogger`.
+    #[wasm_bindgen(js_name = "openCustom", unchecked_return_type = "Promise<StoreHandle>")]
+    pub fn open_custom(
+        adapter: CryptoStoreAdapter,
+        #[wasm_bindgen(unchecked_optional_param_type = "JsLogger")] logger: Option<JsLogger>,
+    ) -> Promise {
+        let _guard = dispatcher::set_default(&logger_to_dispatcher(logger));
+        future_to_promise(async move {
+            let store = CustomStore::open(adapter.clone()).with_current_subscriber().await?;
+            let inbound_group_sessions = store.inbound_group_sessions();
+
+            Ok(Self {
+                store: store.into_crypto_store(),
+                backend: StoreBackend::Custom { adapter, inbound_group_sessions },
+            })
+        })
+    }
+
+    /// Export the whole content of this store as a single blob, encrypted
+    /// with the given passphrase.
+    ///
+    /// The snapshot holds every record of the underlying storage: the IndexedDB
+    /// databases of stores opened with {@link StoreHandle.open} or
+    /// {@link StoreHandle.openWithKey}, or the adapter collections of stores
+    /// opened with {@link StoreHandle.openCustom}. Memory stores cannot be
+    /// exported.
+    ///
+    /// Each IndexedDB database is read in a single transaction, so the
+    /// snapshot is consistent even while the store is in use. Adapters have no
+    /// transactions, so custom stores should not be written to while they are
+    /// exported. The snapshot is built in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase that should be used to encrypt the
+    ///   snapshot.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for a `Uint8Array` which can be given to
+    /// {@link StoreHandle.importSnapshot} or
+    /// {@link StoreHandle.importCustomSnapshot}, depending on the kind of
+    /// this store.
+    #[wasm_bindgen(js_name = "exportSnapshot", unchecked_return_type = "Promise<Uint8Array>")]
+    pub fn export_snapshot(&self, mut passphrase: String) -> Promise {
+        let backend = self.backend.clone();
+        future_to_promise(async move {
+            let snapshot = snapshot::export_snapshot(&backend, &passphrase).await;
+            passphrase.zeroize();
+
+            Ok(Uint8Array::from(snapshot?.as_slice()))
+        })
+    }
+
+    /// Rebuild an IndexedDB-based crypto store from a snapshot made by
+    /// {@link StoreHandle.exportSnapshot}.
+    ///
+    /// The snapshot is imported into a new IndexedDB-based store, which is
+    /// returned. It fails if a store with the given name already exists; if
+    /// the import fails, nothing is left behind.
+    ///
+    /// The records are copied without being decrypted, so the new store must
+    /// be encrypted if, and only if, the exported one was.
+    ///
+    /// # Arguments
+    ///
+    /// * `blob` - The snapshot, as returned by `exportSnapshot`.
+    ///
+    /// * `passphrase` - The passphrase that was used to encrypt the snapshot.
+    ///
+    /// * `target_store_name` - The name of the IndexedDB-based store to create.
+    ///
+    /// * `target_store_passphrase` - The passphrase that should be used to
+    ///   encrypt the new store, if any.
+    #[wasm_bindgen(js_name = "importSnapshot", unchecked_return_type = "Promise<StoreHandle>")]
+    pub fn import_snapshot(
+        blob: Uint8Array,
+        mut passphrase: String,
+        target_store_name: String,
+        target_store_passphrase: Option<String>,
+    ) -> Promise {
+        let blob = blob.to_vec();
+        future_to_promise(async move {
+            let target_store_passphrase = target_store_passphrase.map(Zeroizing::new);
+            let result = snapshot::import_into_indexeddb(
+                &blob,
+                &passphrase,
+                &target_store_name,
+                target_store_passphrase.as_deref().map(String::as_str),
+            )
+            .await;
+            passphrase.zeroize();
+
+            result
+        })
+    }
+
+    /// Rebuild a crypto store whose persistence is implemented in JavaScript
+    /// from a snapshot made by {@link StoreHandle.exportSnapshot} on a store
+    /// opened with {@link StoreHandle.openCustom}.
+    ///
+    /// The adapter must not hold any record; if the import fails, the records
+    /// written to it are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `blob` - The snapshot, as returned by `exportSnapshot`.
+    ///
+    /// * `passphrase` - The passphrase that was used to encrypt the snapshot.
+    ///
+    /// * `adapter` - The `CryptoStoreAdapter` to write the records to.
+    #[wasm_bindgen(
+        js_name = "importCustomSnapshot",
+        unchecked_return_type = "Promise<StoreHandle>"
+    )]
+    pub fn import_custom_snapshot(
+        blob: Uint8Array,
+        mut passphrase: String,
+        adapter: CryptoStoreAdapter,
+    ) -> Promise {
+        let blob = blob.to_vec();
+        future_to_promise(async move {
+            let result = snapshot::import_into_custom(&blob, &passphrase, adapter).await;
+            passphrase.zeroize();
+
+            result
+        })
+    }
+
+    /// Change the passphrase or key protecting an IndexedDB-based crypto
+    /// store.
+    ///
+    /// The records of the store are encrypted with a random store cipher,
+    /// and only that cipher is encrypted with the passphrase or key. Rekeying
+    /// re-encrypts the cipher, not the records: anyone who obtained the
+    /// cipher with the old secret can still decrypt them.
+    ///
+    /// The store must not be open while it is being rekeyed. The operation
+    /// is atomic: if it is interrupted, the store can still be opened with
+    /// `old_secret`, and `rekey` can safely be called again. Calling it again
+    /// with the same secrets after it succeeded does nothing, while a wrong
+    /// `old_secret` is still rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_name` - The name of the store, as given to {@link
+    ///   StoreHandle.open} or {@link StoreHandle.openWithKey}.
+    ///
+    /// * `old_secret` - The current passphrase (a string), or key (a 32-byte
+    ///   `Uint8Array`) of the store.
+    ///
+    /// * `new_secret` - The new passphrase or key of the store.
+    #[wasm_bindgen(unchecked_return_type = "Promise<void>")]
+    pub fn rekey(
+        store_name: String,
+        #[wasm_bindgen(unchecked_param_type = "string | Uint8Array")] old_secret: JsValue,
+        #[wasm_bindgen(unchecked_param_type = "string | Uint8Array")] new_secret: JsValue,
+    ) -> Promise {
+        future_to_promise(async move {
+            let old_secret = rekey::StoreSecret::from_js(old_secret)?;
+            let new_secret = rekey::StoreSecret::from_js(new_secret)?;
+
+            rekey::rekey(&store_name, old_secret, new_secret).await?;
+
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    /// Delete the IndexedDB databases used by the crypto store with the given
+    /// name.
+    ///
+    /// Any `StoreHandle` or `OlmMachine` using the store must be freed
+    /// first. Otherwise, the returned `Promise` is rejected, and the
+    /// databases are only deleted once every connection to them has been
+    /// closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_name` - The name of the store, as given to {@link
+    ///   StoreHandle.open} or {@link StoreHandle.openWithKey}.
+    ///
+    /// # Returns
+    ///
+    /// A `Promise` for the names of the databases which have been deleted.
+    /// It is empty if there was no such store.
+    #[wasm_bindgen(unchecked_return_type = "Promise<string[]>")]
+    pub fn delete(store_name: String) -> Promise {
+        future_to_promise(async move {
+            let removed = idb::delete_store_databases(&store_name).await?;
+            Ok(removed.into_iter().map(JsValue::from).collect::<Array>())
+        })
+    }
+
+    /// Collect statistics about the content of this store: the number of
+    /// records of each kind, and the approximate size of each collection of
+    /// the underlying storage.
+    ///
+    /// This reads the whole store, so can take a while on large stores.
+    #[wasm_bindgen(unchecked_return_type = "Promise<StoreStats>")]
+    pub fn stats(&self) -> Promise {
+        let store = self.store.clone();
+        let backend = self.backend.clone();
+        future_to_promise(async move { store_stats(&store, &backend).await })
+    }
+
+    /// Create a lease lock over this store, so that only one JavaScript
+    /// context (a browser tab, a worker, …) at a time uses it.
+    ///
+    /// The lock is not acquired yet: call {@link StoreLock.acquire}.
+    ///
+    /// For IndexedDB-based stores, the lease is shared by every context
+    /// opening the same store. For stores opened with
+    /// {@link StoreHandle.openCustom}, it is only shared by the users of this
+    /// `StoreHandle`.
+    ///
+    /// # Arguments
+    ///
+    /// * `holder` - A name identifying this context, unique among the contexts
+    ///   which may use the store.
+    ///
+    /// * `lease_duration_ms` - How long the lease lasts unless renewed, in
+    ///   milliseconds. Defaults to 10 seconds.
+    #[wasm_bindgen(js_name = "createLock")]
+    pub fn create_lock(&self, holder: String, lease_duration_ms: Option<u32>) -> StoreLock {
+        StoreLock::new(
+            self.store.clone(),
+            holder,
+            lease_duration_ms.unwrap_or(lock::DEFAULT_LEASE_DURATION_MS),
+        )
+    }
+
+    /// Check that the content of this store can be read.
+    ///
+    /// Every kind of record is loaded through the store. For IndexedDB-based
+    /// and memory stores, some kinds of records can only be loaded as a
+    /// whole, so problems are reported per group of records, and cannot be
+    /// repaired: the check is rejected if `repair` is set. For stores opened
+    /// with {@link StoreHandle.openCustom}, every record of the adapter is
+    /// also checked individually; see
+    /// {@link StoreHandle.verifyCustomIntegrity}.
+    ///
+    /// Since this needs the store to be open, it cannot help with a store
+    /// which fails to open, except for custom stores.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - An optional {@link IntegrityOptions} object.
+    #[wasm_bindgen(js_name = "verifyIntegrity", unchecked_return_type = "Promise<IntegrityReport>")]
+    pub fn verify_integrity(
+        &self,
+        #[wasm_bindgen(unchecked_optional_param_type = "IntegrityOptions")] options: Option<Object>,
+    ) -> Promise {
+        let store = self.store.clone();
+        let backend = self.backend.clone();
+
+        future_to_promise(async move {
+            let options = integrity::IntegrityOptions::from_js(options)?;
+
+            if options.repair && !matches!(backend, StoreBackend::Custom { .. }) {
+                return Err(JsError::new(
+                    "Only the stores opened with `StoreHandle.openCustom` can be repaired",
+                ));
+            }
+
+            let mut report = integrity::verify_store(&store).await;
+
+            if let StoreBackend::Custom { adapter, .. } = &backend {
+                report.merge(integrity::verify_adapter(adapter, &options).await?);
+            }
+
+            Ok(report)
+        })
+    }
+
+    /// Check that every record held by a custom store adapter can be read.
+    ///
+    /// Unlike {@link StoreHandle.verifyIntegrity}, this doesn't need the
+    /// store to be opened first, so it can be used when
+    /// {@link StoreHandle.openCustom} fails. With `repair` set, the
+    /// unreadable records are moved to the `quarantine` collection of the
+    /// adapter, after which the store can be opened.
+    ///
+    /// # Arguments
+    ///
+    /// * `adapter` - The `CryptoStoreAdapter` to check.
+    ///
+    /// * `options` - An optional {@link IntegrityOptions} object.
+    #[wasm_bindgen(
+        js_name = "verifyCustomIntegrity",
+        unchecked_return_type = "Promise<IntegrityReport>"
+    )]
+    pub fn verify_custom_integrity(
+        adapter: CryptoStoreAdapter,
+        #[wasm_bindgen(unchecked_optional_param_type = "IntegrityOptions")] options: Option<Object>,
+    ) -> Promise {
+        future_to_promise(async move {
+            let options = integrity::IntegrityOptions::from_js(options)?;
+            integrity::verify_adapter(&adapter, &options).await
+        })
+    }
+}
+
+impl IntoCryptoStore for StoreHandle {
+    fn into_crypto_store(self) -> Arc<DynCryptoStore> {
+        self.store.clone()
+    }
+}
+
+/// A struct containing private cross signing keys that can be backed
+/// up or uploaded to the secret store.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct CrossSigningKeyExport {
+    pub(crate) inner: matrix_sdk_crypto::store::types::CrossSigningKeyExport,
+}
+
+impl_from_to_inner!(matrix_sdk_crypto::store::types::CrossSigningKeyExport => CrossSigningKeyExport);
+
+#[wasm_bindgen]
+impl CrossSigningKeyExport {
+    /// The seed of the master key encoded as unpadded base64.
+    #[wasm_bindgen(getter, js_name = "masterKey")]
+    pub fn master_key(&self) -> Option<String> {
+        self.inner.master_key.clone()
+    }
+
+    /// The seed of the self signing key encoded as unpadded base64.
+    #[wasm_bindgen(getter, js_name = "self_signing_key")]
+    pub fn self_signing_key(&self) -> Option<String> {
+        self.inner.self_signing_key.clone()
+    }
+
+    /// The seed of the user signing key encoded as unpadded base64.
+    #[wasm_bindgen(getter, js_name = "userSigningKey")]
+    pub fn user_signing_key(&self) -> Option<String> {
+        self.inner.user_signing_key.clone()
+    }
+}
+
+/// Information on a room key that has been received or imported.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct RoomKeyInfo {
+    pub(crate) inner: matrix_sdk_crypto::store::types::RoomKeyInfo,
+}
+
+impl_from_to_inner!(matrix_sdk_crypto::store::types::RoomKeyInfo => RoomKeyInfo);
+
+#[wasm_bindgen]
+impl RoomKeyInfo {
+    /// The {@link EncryptionAlgorithm} that this key is used for. Will be one
+    /// of the `m.megolm.*` algorithms.
+    #[wasm_bindgen(getter)]
+    pub fn algorithm(&self) -> EncryptionAlgorithm {
+        self.inner.algorithm.clone().into()
+    }
+
+    /// The room where the key is used.
+    #[wasm_bindgen(getter, js_name = "roomId")]
+    pub fn room_id(&self) -> RoomId {
+        self.inner.room_id.clone().into()
+    }
+
+    /// The Curve25519 key of the device which initiated the session originally.
+    #[wasm_bindgen(getter, js_name = "senderKey")]
+    pub fn sender_key(&self) -> Curve25519PublicKey {
+        self.inner.sender_key.into()
+    }
+
+    /// The ID of the session that the key is for.
+    #[wasm_bindgen(getter, js_name = "sessionId")]
+    pub fn session_id(&self) -> String {
+        self.inner.session_id.clone()
+    }
+}
+
+/// Information on a received `m.room_key.withheld` event.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct RoomKeyWithheldInfo {
+    pub(crate) inner: matrix_sdk_crypto::store::types::RoomKeyWithheldInfo,
+}
+
+impl_from_to_inner!(matrix_sdk_crypto::store::types::RoomKeyWithheldInfo => RoomKeyWithheldInfo);
+
+#[wasm_bindgen]
+impl RoomKeyWithheldInfo {
+    /// The User ID of the sender of the withheld information.
+    ///
+    /// This may be the sender of an `m.room_key.withheld` event, or the sender
+    /// of a shared room key bundle under MSC4268.
+    #[wasm_bindgen(getter)]
+    pub fn sender(&self) -> UserId {
+        self.inner.withheld_event.sender.to_owned().into()
+    }
+
+    /// The encryption algorithm of the session that is being withheld.
+    ///
+    /// This may be from an `m.room_key.withheld` event, or from a shared room
+    /// key bundle under MSC4268.
+    #[wasm_bindgen(getter)]
+    pub fn algorithm(&self) -> EncryptionAlgorithm {
+        self.inner.withheld_event.content.algorithm().into()
+    }
+
+    /// The `code` indicating why the key was withheld, such as `m.unverified`.
+    ///
+    /// This may be from an `m.room_key.withheld` event (such as
+    /// `m.unverified`), or from a shared room key bundle under MSC4268.
+    #[wasm_bindgen(getter, js_name = "withheldCode")]
+    pub fn withheld_code(&self) -> String {
+        self.inner.withheld_event.content.withheld_code().as_str().to_owned()
+    }
+
+    /// The room ID of the session that is being withheld.
+    #[wasm_bindgen(getter, js_name = "roomId")]
+    pub fn room_id(&self) -> RoomId {
+        self.inner.room_id.to_owned().into()
+    }
+
+    /// The session ID of the session that is being withheld.
+    #[wasm_bindgen(getter, js_name = "sessionId")]
+    pub fn session_id(&self) -> String {
+        self.inner.session_id.to_owned()
+    }
+}
+
+/// Struct containing the bundle of secrets to fully activate a new device for
+/// end-to-end encryption.
+#[derive(Debug)]
+#[wasm_bindgen]
+pub struct SecretsBundle {
+    pub(super) inner: matrix_sdk_crypto::types::SecretsBundle,
+}
+
+/// The backup-specific parts of a secrets bundle.
+#[derive(Debug)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct BackupSecretsBundle {
+    /// The backup decryption key, encoded as unpadded base64.
+    pub key: String,
+    /// The backup version which this backup decryption key is used with.
+    pub backup_version: String,
+}
+
+#[wasm_bindgen]
+impl SecretsBundle {
+    /// The seed of the master key encoded as unpadded base64.
+    #[wasm_bindgen(getter, js_name = "masterKey")]
+    pub fn master_key(&self) -> String {
+        self.inner.cross_signing.master_key.clone()
+    }
+
+    /// The seed of the self signing key encoded as unpadded base64.
+    #[wasm_bindgen(getter, js_name = "selfSigningKey")]
+    pub fn self_signing_key(&self) -> String {
+        self.inner.cross_signing.self_signing_key.clone()
+    }
+
+    /// The seed of the user signing key encoded as unpadded base64.
+    #[wasm_bindgen(getter, js_name = "userSigningKey")]
+    pub fn user_signing_key(&self) -> String {
+        self.inner.cross_signing.user_signing_key.clone()
+    }
+
+    /// The bundle of the backup decryption key and backup version if any.
+    #[wasm_bindgen(getter, js_name = "backupBundle")]
+    pub fn backup_bundle(&self) -> Option<BackupSecretsBundle> {
+        if let Some(BackupSecrets::MegolmBackupV1Curve25519AesSha2(backup)) = &self.inner.backup {
+            Some(BackupSecretsBundle {
+                key: backup.key.to_base64(),
+                backup_version: backup.backup_version.clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Serialize the [`SecretsBundle`] to a JSON object.
+    #[wasm_bindgen(unchecked_return_type = "unknown")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.inner)?)
+    }
+
+    /// Deserialize the [`SecretsBundle`] from a JSON object.
+    pub fn from_json(
+        #[wasm_bindgen(unchecked_param_type = "unknown")] json: JsValue,
+    ) -> Result<SecretsBundle, JsError> {
+        let bundle = serde_wasm_bindgen::from_value(json)?;
+
+        Ok(Self { inner: bundle })
+    }
+}
+
+impl_from_to_inner!(matrix_sdk_crypto::types::SecretsBundle => SecretsBundle);
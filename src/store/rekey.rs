@@ -0,0 +1,217 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Changing the secret protecting an IndexedDB-based crypto store.
+
+use hkdf::Hkdf;
+use js_sys::Uint8Array;
+use matrix_sdk_store_encryption::StoreCipher;
+use sha2::Sha256;
+use wasm_bindgen::{prelude::*, JsCast};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::idb;
+
+type Result<T, E = JsError> = std::result::Result<T, E>;
+
+/// The HKDF info used by `matrix_sdk_indexeddb` to derive the key protecting
+/// the store cipher from a store key given to `openWithKey`.
+const STORE_KEY_HKDF_INFO: &[u8] = b"CRYPTOSTORE_CIPHER";
+
+/// The key of the record, in the meta database of a store, holding a hash of
+/// the secret the store was last rekeyed from.
+const REKEYED_FROM_KEY: &str = "rekeyed_from";
+
+/// A passphrase or a key protecting a store.
+#[derive(Clone)]
+pub(crate) enum StoreSecret {
+    Passphrase(Zeroizing<String>),
+    Key(Zeroizing<[u8; 32]>),
+}
+
+impl std::fmt::Debug for StoreSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passphrase(_) => f.write_str("StoreSecret::Passphrase(<redacted>)"),
+            Self::Key(_) => f.write_str("StoreSecret::Key(<redacted>)"),
+        }
+    }
+}
+
+impl StoreSecret {
+    /// Get a secret from a JavaScript string (a passphrase) or `Uint8Array` (a
+    /// 32-byte key).
+    pub(crate) fn from_js(value: JsValue) -> Result<Self> {
+        if let Some(passphrase) = value.as_string() {
+            return Ok(Self::passphrase(passphrase));
+        }
+
+        let mut bytes = value
+            .dyn_into::<Uint8Array>()
+            .map_err(|_| JsError::new("Expected a passphrase string or a key as a Uint8Array"))?
+            .to_vec();
+        let key: Result<[u8; 32]> =
+            bytes.as_slice().try_into().map_err(|_| JsError::new("Expected a key of length 32"));
+        bytes.zeroize();
+
+        Self::key(&Zeroizing::new(key?))
+    }
+
+    /// Get the secret of a store opened with a passphrase.
+    pub(crate) fn passphrase(passphrase: String) -> Self {
+        Self::Passphrase(Zeroizing::new(passphrase))
+    }
+
+    /// Get the secret of a store opened with a 32-byte key.
+    pub(crate) fn key(key: &[u8; 32]) -> Result<Self> {
+        // Derive the key the same way `matrix_sdk_indexeddb` does.
+        let mut derived = Zeroizing::new([0u8; 32]);
+        Hkdf::<Sha256>::new(None, key.as_slice())
+            .expand(STORE_KEY_HKDF_INFO, derived.as_mut_slice())
+            .map_err(|_| JsError::new("Failed to derive the store key"))?;
+
+        Ok(Self::Key(derived))
+    }
+
+    pub(crate) fn import_cipher(&self, exported: &[u8]) -> Option<StoreCipher> {
+        match self {
+            Self::Passphrase(passphrase) => StoreCipher::import(passphrase, exported).ok(),
+            Self::Key(key) => StoreCipher::import_with_key(key, exported).ok(),
+        }
+    }
+
+    /// Hash this secret with the MAC key of `cipher`, so that it can be
+    /// recognised later by anyone who can open the cipher, without being
+    /// revealed.
+    fn hash(&self, cipher: &StoreCipher) -> [u8; 32] {
+        let secret = match self {
+            Self::Passphrase(passphrase) => passphrase.as_bytes(),
+            Self::Key(key) => key.as_slice(),
+        };
+
+        cipher.hash_key(REKEYED_FROM_KEY, secret)
+    }
+
+    pub(crate) fn export_cipher(&self, cipher: &StoreCipher) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Passphrase(passphrase) => cipher.export(passphrase)?,
+            Self::Key(key) => cipher.export_with_key(key)?,
+        })
+    }
+}
+
+/// The exported store cipher, as stored in the meta database of a store.
+pub(crate) struct StoredCipher {
+    pub(crate) exported: Zeroizing<Vec<u8>>,
+    /// Whether the cipher is stored as a `Uint8Array`, rather than as an array
+    /// of numbers.
+    is_typed_array: bool,
+}
+
+impl StoredCipher {
+    /// Read the store cipher from the value of its record, if there is one.
+    pub(crate) fn from_js(stored: JsValue) -> Result<Option<Self>> {
+        if stored.is_undefined() {
+            return Ok(None);
+        }
+
+        let is_typed_array = stored.is_instance_of::<Uint8Array>();
+        let exported = if is_typed_array {
+            Zeroizing::new(stored.unchecked_into::<Uint8Array>().to_vec())
+        } else {
+            Zeroizing::new(serde_wasm_bindgen::from_value::<Vec<u8>>(stored)?)
+        };
+
+        Ok(Some(Self { exported, is_typed_array }))
+    }
+
+    /// Get the value of the record for another exported cipher, keeping the
+    /// shape in which this one was stored.
+    pub(crate) fn replacement(&self, exported: &[u8]) -> Result<JsValue> {
+        Ok(if self.is_typed_array {
+            Uint8Array::from(exported).into()
+        } else {
+            serde_wasm_bindgen::to_value(exported)?
+        })
+    }
+}
+
+/// Re-protect the store cipher of the given store with `new_secret`.
+///
+/// The records of an IndexedDB-based store are encrypted with a random store
+/// cipher, which is itself the only thing encrypted with the store passphrase
+/// or key. Changing the secret hence only requires rewriting the cipher, in a
+/// single IndexedDB transaction: if the operation is interrupted, the store
+/// can still be opened with the old secret, and the operation can be retried.
+///
+/// Along with the cipher, a hash of `old_secret` keyed by the cipher is
+/// stored, so that retrying after a successful rekey is a no-op only if it is
+/// given the same old secret.
+pub(crate) async fn rekey(
+    store_name: &str,
+    old_secret: StoreSecret,
+    new_secret: StoreSecret,
+) -> Result<()> {
+    let database = idb::open_existing(&idb::meta_database_name(store_name))
+        .await?
+        .ok_or_else(|| JsError::new(&format!("There is no crypto store named `{store_name}`")))?;
+
+    let result = rekey_database(&database, &old_secret, &new_secret).await;
+    database.close();
+
+    result
+}
+
+async fn rekey_database(
+    database: &web_sys::IdbDatabase,
+    old_secret: &StoreSecret,
+    new_secret: &StoreSecret,
+) -> Result<()> {
+    let stored =
+        idb::get_value(database, idb::META_CORE_OBJECT_STORE, idb::STORE_CIPHER_KEY).await?;
+    let stored = StoredCipher::from_js(stored)?
+        .ok_or_else(|| JsError::new("The crypto store is not encrypted"))?;
+
+    // A previous attempt may already have completed, in which case the old
+    // secret can only be checked against the hash stored along the cipher,
+    // unless both secrets are the same.
+    if let Some(cipher) = new_secret.import_cipher(&stored.exported) {
+        let rekeyed_from =
+            idb::get_value(database, idb::META_CORE_OBJECT_STORE, REKEYED_FROM_KEY).await?;
+        let rekeyed_from = rekeyed_from.dyn_into::<Uint8Array>().ok().map(|hash| hash.to_vec());
+
+        return if rekeyed_from.as_deref() == Some(old_secret.hash(&cipher).as_slice())
+            || old_secret.import_cipher(&stored.exported).is_some()
+        {
+            Ok(())
+        } else {
+            Err(JsError::new("The old secret doesn't match the crypto store"))
+        };
+    }
+
+    let cipher = old_secret
+        .import_cipher(&stored.exported)
+        .ok_or_else(|| JsError::new("The old secret doesn't match the crypto store"))?;
+
+    let reexported = Zeroizing::new(new_secret.export_cipher(&cipher)?);
+    let value = stored.replacement(&reexported)?;
+    let rekeyed_from = Uint8Array::from(old_secret.hash(&cipher).as_slice()).into();
+
+    idb::put_values(
+        database,
+        idb::META_CORE_OBJECT_STORE,
+        &[(idb::STORE_CIPHER_KEY, &value), (REKEYED_FROM_KEY, &rekeyed_from)],
+    )
+    .await
+}
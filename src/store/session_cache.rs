@@ -0,0 +1,409 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A crypto store keeping the inbound group sessions it reads while events are
+//! being decrypted in a batch, so that each session is only read once.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+
+use async_trait::async_trait;
+use matrix_sdk_common::{
+    cross_process_lock::CrossProcessLockGeneration,
+    ruma::{
+        events::secret::request::SecretName, DeviceId, OwnedDeviceId, OwnedRoomId, RoomId,
+        TransactionId, UserId,
+    },
+};
+use matrix_sdk_crypto::{
+    olm::{
+        Account, InboundGroupSession, OlmMessageHash, OutboundGroupSession,
+        PrivateCrossSigningIdentity, SenderDataType, Session,
+    },
+    store::{
+        types::{
+            BackupKeys, Changes, DehydratedDeviceKey, PendingChanges, RoomKeyCounts,
+            RoomKeyWithheldEntry, RoomPendingKeyBundleDetails, RoomSettings, SecretsInboxItem,
+            StoredRoomKeyBundleData, TrackedUser,
+        },
+        CryptoStore, CryptoStoreError, DynCryptoStore,
+    },
+    vodozemac::Curve25519PublicKey,
+    DeviceData, GossipRequest, SecretInfo, UserIdentityData,
+};
+
+type Result<T, E = CryptoStoreError> = std::result::Result<T, E>;
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// The number of batches in progress.
+    batches: usize,
+
+    /// Incremented each time the sessions are forgotten, so that a session
+    /// read before a write isn't kept after it.
+    generation: u64,
+
+    /// The inbound group sessions read during the batches in progress, or
+    /// `None` for the ones which were missing, by room and session ID.
+    sessions: HashMap<(OwnedRoomId, String), Option<InboundGroupSession>>,
+}
+
+/// The inbound group sessions read from a [`CachingStore`] while a batch of
+/// events is being decrypted.
+///
+/// Sessions are only kept while a batch is in progress, and are forgotten once
+/// any of them is written to the store.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InboundGroupSessionCache(Rc<RefCell<CacheState>>);
+
+impl InboundGroupSessionCache {
+    /// Keep the inbound group sessions read from the store until the returned
+    /// guard is dropped.
+    pub(crate) fn batch(&self) -> CacheBatch {
+        self.0.borrow_mut().batches += 1;
+        CacheBatch(self.clone())
+    }
+
+    /// Forget the sessions read so far, for example because some of them
+    /// have been deleted from the store.
+    pub(crate) fn clear(&self) {
+        let mut state = self.0.borrow_mut();
+        state.sessions.clear();
+        state.generation += 1;
+    }
+
+    fn get(&self, room_id: &RoomId, session_id: &str) -> Option<Option<InboundGroupSession>> {
+        self.0.borrow().sessions.get(&(room_id.to_owned(), session_id.to_owned())).cloned()
+    }
+
+    fn generation(&self) -> u64 {
+        self.0.borrow().generation
+    }
+
+    fn insert(
+        &self,
+        generation: u64,
+        room_id: &RoomId,
+        session_id: &str,
+        session: Option<InboundGroupSession>,
+    ) {
+        let mut state = self.0.borrow_mut();
+        if state.batches > 0 && state.generation == generation {
+            state.sessions.insert((room_id.to_owned(), session_id.to_owned()), session);
+        }
+    }
+}
+
+/// A batch started with [`InboundGroupSessionCache::batch`].
+#[derive(Debug)]
+pub(crate) struct CacheBatch(InboundGroupSessionCache);
+
+impl Drop for CacheBatch {
+    fn drop(&mut self) {
+        let mut state = self.0 .0.borrow_mut();
+        state.batches -= 1;
+        if state.batches == 0 {
+            state.sessions.clear();
+            state.generation += 1;
+        }
+    }
+}
+
+/// A crypto store reading the inbound group sessions through an
+/// [`InboundGroupSessionCache`], and forwarding everything else to another
+/// store.
+#[derive(Debug)]
+pub(crate) struct CachingStore {
+    inner: Arc<DynCryptoStore>,
+    cache: InboundGroupSessionCache,
+}
+
+impl CachingStore {
+    pub(crate) fn new(inner: Arc<DynCryptoStore>, cache: InboundGroupSessionCache) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait(?Send)]
+impl CryptoStore for CachingStore {
+    type Error = CryptoStoreError;
+
+    async fn load_account(&self) -> Result<Option<Account>> {
+        self.inner.load_account().await
+    }
+
+    async fn load_identity(&self) -> Result<Option<PrivateCrossSigningIdentity>> {
+        self.inner.load_identity().await
+    }
+
+    async fn save_pending_changes(&self, changes: PendingChanges) -> Result<()> {
+        self.inner.save_pending_changes(changes).await
+    }
+
+    async fn save_changes(&self, changes: Changes) -> Result<()> {
+        let result = self.inner.save_changes(changes).await;
+        self.cache.clear();
+
+        result
+    }
+
+    async fn save_inbound_group_sessions(
+        &self,
+        sessions: Vec<InboundGroupSession>,
+        backed_up_to_version: Option<&str>,
+    ) -> Result<()> {
+        let result = self.inner.save_inbound_group_sessions(sessions, backed_up_to_version).await;
+        self.cache.clear();
+
+        result
+    }
+
+    async fn get_sessions(&self, sender_key: &str) -> Result<Option<Vec<Session>>> {
+        self.inner.get_sessions(sender_key).await
+    }
+
+    async fn get_inbound_group_session(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<InboundGroupSession>> {
+        if let Some(session) = self.cache.get(room_id, session_id) {
+            return Ok(session);
+        }
+
+        let generation = self.cache.generation();
+        let session = self.inner.get_inbound_group_session(room_id, session_id).await?;
+        self.cache.insert(generation, room_id, session_id, session.clone());
+
+        Ok(session)
+    }
+
+    async fn get_withheld_info(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> Result<Option<RoomKeyWithheldEntry>> {
+        self.inner.get_withheld_info(room_id, session_id).await
+    }
+
+    async fn get_withheld_sessions_by_room_id(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RoomKeyWithheldEntry>> {
+        self.inner.get_withheld_sessions_by_room_id(room_id).await
+    }
+
+    async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>> {
+        self.inner.get_inbound_group_sessions().await
+    }
+
+    async fn inbound_group_session_counts(
+        &self,
+        backup_version: Option<&str>,
+    ) -> Result<RoomKeyCounts> {
+        self.inner.inbound_group_session_counts(backup_version).await
+    }
+
+    async fn get_inbound_group_sessions_by_room_id(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.inner.get_inbound_group_sessions_by_room_id(room_id).await
+    }
+
+    async fn get_inbound_group_sessions_for_device_batch(
+        &self,
+        sender_key: Curve25519PublicKey,
+        sender_data_type: SenderDataType,
+        after_session_id: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.inner
+            .get_inbound_group_sessions_for_device_batch(
+                sender_key,
+                sender_data_type,
+                after_session_id,
+                limit,
+            )
+            .await
+    }
+
+    async fn inbound_group_sessions_for_backup(
+        &self,
+        backup_version: &str,
+        limit: usize,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.inner.inbound_group_sessions_for_backup(backup_version, limit).await
+    }
+
+    async fn mark_inbound_group_sessions_as_backed_up(
+        &self,
+        backup_version: &str,
+        room_and_session_ids: &[(&RoomId, &str)],
+    ) -> Result<()> {
+        let result = self
+            .inner
+            .mark_inbound_group_sessions_as_backed_up(backup_version, room_and_session_ids)
+            .await;
+        self.cache.clear();
+
+        result
+    }
+
+    async fn reset_backup_state(&self) -> Result<()> {
+        let result = self.inner.reset_backup_state().await;
+        self.cache.clear();
+
+        result
+    }
+
+    async fn load_backup_keys(&self) -> Result<BackupKeys> {
+        self.inner.load_backup_keys().await
+    }
+
+    async fn load_dehydrated_device_pickle_key(&self) -> Result<Option<DehydratedDeviceKey>> {
+        self.inner.load_dehydrated_device_pickle_key().await
+    }
+
+    async fn delete_dehydrated_device_pickle_key(&self) -> Result<()> {
+        self.inner.delete_dehydrated_device_pickle_key().await
+    }
+
+    async fn get_outbound_group_session(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<OutboundGroupSession>> {
+        self.inner.get_outbound_group_session(room_id).await
+    }
+
+    async fn load_tracked_users(&self) -> Result<Vec<TrackedUser>> {
+        self.inner.load_tracked_users().await
+    }
+
+    async fn save_tracked_users(&self, users: &[(&UserId, bool)]) -> Result<()> {
+        self.inner.save_tracked_users(users).await
+    }
+
+    async fn get_device(
+        &self,
+        user_id: &UserId,
+        device_id: &DeviceId,
+    ) -> Result<Option<DeviceData>> {
+        self.inner.get_device(user_id, device_id).await
+    }
+
+    async fn get_user_devices(
+        &self,
+        user_id: &UserId,
+    ) -> Result<HashMap<OwnedDeviceId, DeviceData>> {
+        self.inner.get_user_devices(user_id).await
+    }
+
+    async fn get_user_identity(&self, user_id: &UserId) -> Result<Option<UserIdentityData>> {
+        self.inner.get_user_identity(user_id).await
+    }
+
+    async fn is_message_known(&self, message_hash: &OlmMessageHash) -> Result<bool> {
+        self.inner.is_message_known(message_hash).await
+    }
+
+    async fn get_outgoing_secret_requests(
+        &self,
+        request_id: &TransactionId,
+    ) -> Result<Option<GossipRequest>> {
+        self.inner.get_outgoing_secret_requests(request_id).await
+    }
+
+    async fn get_secret_request_by_info(
+        &self,
+        secret_info: &SecretInfo,
+    ) -> Result<Option<GossipRequest>> {
+        self.inner.get_secret_request_by_info(secret_info).await
+    }
+
+    async fn get_unsent_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        self.inner.get_unsent_secret_requests().await
+    }
+
+    async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
+        self.inner.delete_outgoing_secret_requests(request_id).await
+    }
+
+    async fn get_secrets_from_inbox(
+        &self,
+        secret_name: &SecretName,
+    ) -> Result<Vec<SecretsInboxItem>> {
+        self.inner.get_secrets_from_inbox(secret_name).await
+    }
+
+    async fn delete_secrets_from_inbox(&self, secret_name: &SecretName) -> Result<()> {
+        self.inner.delete_secrets_from_inbox(secret_name).await
+    }
+
+    async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
+        self.inner.get_room_settings(room_id).await
+    }
+
+    async fn get_received_room_key_bundle_data(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<Option<StoredRoomKeyBundleData>> {
+        self.inner.get_received_room_key_bundle_data(room_id, user_id).await
+    }
+
+    async fn has_downloaded_all_room_keys(&self, room_id: &RoomId) -> Result<bool> {
+        self.inner.has_downloaded_all_room_keys(room_id).await
+    }
+
+    async fn get_pending_key_bundle_details_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<RoomPendingKeyBundleDetails>> {
+        self.inner.get_pending_key_bundle_details_for_room(room_id).await
+    }
+
+    async fn get_all_rooms_pending_key_bundles(&self) -> Result<Vec<RoomPendingKeyBundleDetails>> {
+        self.inner.get_all_rooms_pending_key_bundles().await
+    }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.get_custom_value(key).await
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.inner.set_custom_value(key, value).await
+    }
+
+    async fn remove_custom_value(&self, key: &str) -> Result<()> {
+        self.inner.remove_custom_value(key).await
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<Option<CrossProcessLockGeneration>> {
+        self.inner.try_take_leased_lock(lease_duration_ms, key, holder).await
+    }
+
+    async fn next_batch_token(&self) -> Result<Option<String>> {
+        self.inner.next_batch_token().await
+    }
+
+    async fn clear_caches(&self) {
+        self.inner.clear_caches().await;
+        self.cache.clear();
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The time at which each inbound group session was first stored.
+//!
+//! Inbound group sessions don't record when they were created or imported, so
+//! the `OlmMachine` keeps track of it in the custom values of the store, as
+//! room keys are received: one value per session, so that recording the time
+//! of a new session doesn't depend on the number of sessions already stored.
+//!
+//! The writes are serialised by the `OlmMachine` with the pruning of the
+//! sessions, so that the time of a deleted session is never recorded again.
+
+use matrix_sdk_common::ruma::{MilliSecondsSinceUnixEpoch, RoomId};
+use matrix_sdk_crypto::store::{CryptoStoreError, DynCryptoStore};
+
+fn key(room_id: &RoomId, session_id: &str) -> String {
+    format!("inbound_group_session_time|{room_id}|{session_id}")
+}
+
+/// Get the time at which the given session was first stored, in milliseconds
+/// since the Unix epoch, if it is known.
+pub(crate) async fn get(
+    store: &DynCryptoStore,
+    room_id: &RoomId,
+    session_id: &str,
+) -> Result<Option<u64>, CryptoStoreError> {
+    Ok(match store.get_custom_value(&key(room_id, session_id)).await? {
+        Some(value) => Some(serde_json::from_slice(&value)?),
+        None => None,
+    })
+}
+
+/// Record the current time as the time at which the given sessions of a room
+/// were first stored, unless a time has already been recorded.
+pub(crate) async fn record(
+    store: &DynCryptoStore,
+    room_id: &RoomId,
+    session_ids: impl IntoIterator<Item = &str>,
+) -> Result<(), CryptoStoreError> {
+    let now = u64::from(MilliSecondsSinceUnixEpoch::now().get());
+
+    for session_id in session_ids {
+        let key = key(room_id, session_id);
+        if store.get_custom_value(&key).await?.is_none() {
+            store.set_custom_value(&key, serde_json::to_vec(&now)?).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Forget the times at which the given sessions of a room were stored.
+pub(crate) async fn remove(
+    store: &DynCryptoStore,
+    room_id: &RoomId,
+    session_ids: impl IntoIterator<Item = &str>,
+) -> Result<(), CryptoStoreError> {
+    for session_id in session_ids {
+        store.remove_custom_value(&key(room_id, session_id)).await?;
+    }
+
+    Ok(())
+}
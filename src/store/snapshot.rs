@@ -0,0 +1,620 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Passphrase-encrypted snapshots of a whole crypto store.
+//!
+//! A snapshot holds the raw records of the storage backing the store, so that
+//! every collection is exported, including the ones which cannot be listed
+//! through the `CryptoStore` trait. As a consequence, a snapshot can only be
+//! imported into a store of the same kind as the one it was made from.
+
+use std::collections::BTreeMap;
+
+use js_sys::{Array, ArrayBuffer, Map, Object, Reflect, Uint8Array};
+use matrix_sdk_crypto::store::IntoCryptoStore;
+use matrix_sdk_store_encryption::{EncryptedValueBase64, StoreCipher};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{IdbDatabase, IdbTransaction, IdbTransactionMode};
+use zeroize::Zeroizing;
+
+use super::{
+    custom::{self, CryptoStoreAdapter, CustomStore},
+    idb,
+    rekey::{StoreSecret, StoredCipher},
+    StoreBackend, StoreHandle,
+};
+
+type Result<T, E = JsError> = std::result::Result<T, E>;
+
+/// The version of the snapshot format produced by [`export_snapshot`].
+const SNAPSHOT_VERSION: u8 = 2;
+
+/// The outer, unencrypted, envelope of a snapshot.
+///
+/// Byte strings are encoded as base64 throughout, rather than as JSON arrays
+/// of numbers.
+#[derive(Serialize, Deserialize)]
+struct SnapshotEnvelope {
+    version: u8,
+    /// The [`StoreCipher`] used to encrypt `data`, itself encrypted with the
+    /// snapshot passphrase.
+    ///
+    /// For encrypted IndexedDB-based stores, this is the cipher of the store,
+    /// with which its records are encrypted.
+    #[serde(with = "base64_bytes")]
+    cipher: Vec<u8>,
+    /// The encrypted [`StoreSnapshot`].
+    data: EncryptedValueBase64,
+}
+
+/// Serialise byte strings as base64 strings.
+mod base64_bytes {
+    use matrix_sdk_crypto::vodozemac::{base64_decode, base64_encode};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64_encode(bytes))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        base64_decode(String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// A byte string, serialised as base64.
+#[derive(Serialize, Deserialize)]
+struct Bytes(#[serde(with = "base64_bytes")] Vec<u8>);
+
+/// The content of a crypto store, as stored in a snapshot.
+#[derive(Serialize, Deserialize)]
+enum StoreSnapshot {
+    /// The databases of an IndexedDB-based store, in the order of
+    /// [`idb::database_names`].
+    IndexedDb { encrypted: bool, databases: Vec<DatabaseSnapshot> },
+
+    /// The records of each collection of a store opened with
+    /// `StoreHandle.openCustom`.
+    Custom { collections: BTreeMap<String, Vec<(String, Bytes)>> },
+}
+
+/// The content of an IndexedDB database.
+#[derive(Serialize, Deserialize)]
+struct DatabaseSnapshot {
+    version: f64,
+    /// The keys and values of the records of each object store.
+    object_stores: BTreeMap<String, Vec<(RawValue, RawValue)>>,
+}
+
+/// A value read from IndexedDB.
+#[derive(Serialize, Deserialize)]
+enum RawValue {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    /// A `Uint8Array`.
+    Bytes(Bytes),
+    /// An `ArrayBuffer`.
+    Buffer(Bytes),
+    Array(Vec<RawValue>),
+    Map(Vec<(RawValue, RawValue)>),
+    Object(Vec<(String, RawValue)>),
+}
+
+impl RawValue {
+    fn from_js(value: &JsValue) -> Result<Self> {
+        Ok(if value.is_undefined() {
+            Self::Undefined
+        } else if value.is_null() {
+            Self::Null
+        } else if let Some(value) = value.as_bool() {
+            Self::Bool(value)
+        } else if let Some(value) = value.as_f64() {
+            Self::Number(value)
+        } else if let Some(value) = value.as_string() {
+            Self::String(value)
+        } else if let Some(bytes) = value.dyn_ref::<Uint8Array>() {
+            Self::Bytes(Bytes(bytes.to_vec()))
+        } else if let Some(buffer) = value.dyn_ref::<ArrayBuffer>() {
+            Self::Buffer(Bytes(Uint8Array::new(buffer).to_vec()))
+        } else if let Some(array) = value.dyn_ref::<Array>() {
+            Self::Array(array.iter().map(|item| Self::from_js(&item)).collect::<Result<_>>()?)
+        } else if let Some(map) = value.dyn_ref::<Map>() {
+            let mut entries = Vec::new();
+            map.for_each(&mut |value, key| entries.push((key, value)));
+
+            Self::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| Ok((Self::from_js(key)?, Self::from_js(value)?)))
+                    .collect::<Result<_>>()?,
+            )
+        } else if is_plain_object(value) {
+            Self::Object(
+                Object::entries(value.unchecked_ref())
+                    .iter()
+                    .map(|entry| {
+                        let entry: Array = entry.unchecked_into();
+                        let key = entry.get(0).as_string().unwrap_or_default();
+                        Ok((key, Self::from_js(&entry.get(1))?))
+                    })
+                    .collect::<Result<_>>()?,
+            )
+        } else {
+            return Err(JsError::new(&format!("Unsupported value in the store: {value:?}")));
+        })
+    }
+
+    fn to_js(&self) -> Result<JsValue> {
+        Ok(match self {
+            Self::Undefined => JsValue::UNDEFINED,
+            Self::Null => JsValue::NULL,
+            Self::Bool(value) => JsValue::from_bool(*value),
+            Self::Number(value) => JsValue::from_f64(*value),
+            Self::String(value) => JsValue::from_str(value),
+            Self::Bytes(Bytes(bytes)) => Uint8Array::from(bytes.as_slice()).into(),
+            Self::Buffer(Bytes(bytes)) => Uint8Array::from(bytes.as_slice()).buffer().into(),
+            Self::Array(items) => items.iter().map(Self::to_js).collect::<Result<Array>>()?.into(),
+            Self::Map(entries) => {
+                let map = Map::new();
+                for (key, value) in entries {
+                    map.set(&key.to_js()?, &value.to_js()?);
+                }
+                map.into()
+            }
+            Self::Object(entries) => {
+                let object = Object::new();
+                for (key, value) in entries {
+                    Reflect::set(&object, &JsValue::from_str(key), &value.to_js()?)
+                        .map_err(|_| JsError::new("Failed to rebuild a value of the store"))?;
+                }
+                object.into()
+            }
+        })
+    }
+}
+
+/// Whether the value is an object created by an object literal, or by
+/// `Object.create(null)`.
+fn is_plain_object(value: &JsValue) -> bool {
+    if !value.is_object() {
+        return false;
+    }
+
+    let prototype = JsValue::from(Object::get_prototype_of(value));
+    prototype.is_null() || prototype == JsValue::from(Object::get_prototype_of(&Object::new()))
+}
+
+/// Read the whole content of the storage behind a store, and encrypt it with
+/// `passphrase`.
+pub(crate) async fn export_snapshot(backend: &StoreBackend, passphrase: &str) -> Result<Vec<u8>> {
+    let (cipher, snapshot) = match backend {
+        StoreBackend::Memory => {
+            return Err(JsError::new("Snapshots cannot be exported from memory stores"));
+        }
+        StoreBackend::IndexedDb { store_name, secret } => {
+            export_indexeddb(store_name, secret.as_ref()).await?
+        }
+        StoreBackend::Custom { adapter, .. } => {
+            (StoreCipher::new()?, export_custom(adapter).await?)
+        }
+    };
+
+    let data = serde_json::to_vec(&snapshot)?;
+    drop(snapshot);
+
+    let envelope = SnapshotEnvelope {
+        version: SNAPSHOT_VERSION,
+        cipher: cipher.export(passphrase)?,
+        data: cipher.encrypt_value_base64_data(data)?,
+    };
+
+    Ok(serde_json::to_vec(&envelope)?)
+}
+
+/// Read every database of an IndexedDB-based store.
+///
+/// The records are copied as they are stored, so the store cipher is returned
+/// along with them, if the store is encrypted.
+///
+/// Each database is read in a single transaction. Transactions can't span
+/// databases, but the meta database only holds the store cipher, which doesn't
+/// change during the lifetime of a store, so the snapshot is consistent.
+async fn export_indexeddb(
+    store_name: &str,
+    secret: Option<&StoreSecret>,
+) -> Result<(StoreCipher, StoreSnapshot)> {
+    let meta_database_name = idb::meta_database_name(store_name);
+    let mut databases = Vec::new();
+    let mut stored_cipher = None;
+
+    for name in idb::database_names(store_name) {
+        let database = idb::open_existing(&name)
+            .await?
+            .ok_or_else(|| JsError::new(&format!("The database `{name}` does not exist")))?;
+        let result = read_database(&database).await;
+        database.close();
+        let mut snapshot = result?;
+
+        // The store cipher is exported on its own, protected by the snapshot
+        // passphrase rather than by the secret of the store.
+        if name == meta_database_name {
+            if let Some(records) = snapshot.object_stores.get_mut(idb::META_CORE_OBJECT_STORE) {
+                if let Some(index) = records.iter().position(
+                    |(key, _)| matches!(key, RawValue::String(key) if key.as_str() == idb::STORE_CIPHER_KEY),
+                ) {
+                    stored_cipher = StoredCipher::from_js(records.remove(index).1.to_js()?)?;
+                }
+            }
+        }
+
+        databases.push(snapshot);
+    }
+
+    let (cipher, encrypted) = match (stored_cipher, secret) {
+        (Some(stored), Some(secret)) => (
+            secret
+                .import_cipher(&stored.exported)
+                .ok_or_else(|| JsError::new("The secret doesn't match the crypto store"))?,
+            true,
+        ),
+        (Some(_), None) => {
+            return Err(JsError::new("The crypto store is encrypted, but its secret is unknown"));
+        }
+        (None, _) => (StoreCipher::new()?, false),
+    };
+
+    Ok((cipher, StoreSnapshot::IndexedDb { encrypted, databases }))
+}
+
+/// Read every object store of a database, in a single transaction.
+async fn read_database(database: &IdbDatabase) -> Result<DatabaseSnapshot> {
+    let read_error =
+        |_: JsValue| JsError::new(&format!("Failed to read the database `{}`", database.name()));
+
+    let names = object_store_names(database);
+    let mut object_stores = BTreeMap::new();
+
+    if !names.is_empty() {
+        let transaction = database
+            .transaction_with_str_sequence(&names.iter().map(JsValue::from).collect::<Array>())
+            .map_err(read_error)?;
+
+        // Issue every request before waiting for any, so that the transaction
+        // stays active.
+        let requests = names
+            .iter()
+            .map(|name| {
+                let object_store = transaction.object_store(name)?;
+                Ok((name, object_store.get_all_keys()?, object_store.get_all()?))
+            })
+            .collect::<Result<Vec<_>, JsValue>>()
+            .map_err(read_error)?;
+
+        for (name, keys, values) in requests {
+            let keys: Array =
+                idb::request_result(&keys).await.map_err(read_error)?.unchecked_into();
+            let values: Array =
+                idb::request_result(&values).await.map_err(read_error)?.unchecked_into();
+
+            let records = keys
+                .iter()
+                .zip(values.iter())
+                .map(|(key, value)| Ok((RawValue::from_js(&key)?, RawValue::from_js(&value)?)))
+                .collect::<Result<_>>()?;
+
+            object_stores.insert(name.clone(), records);
+        }
+    }
+
+    Ok(DatabaseSnapshot { version: database.version(), object_stores })
+}
+
+/// Read every collection of a custom store adapter.
+///
+/// The records moved to the quarantine by an integrity check are not
+/// exported.
+async fn export_custom(adapter: &CryptoStoreAdapter) -> Result<StoreSnapshot> {
+    let mut collections = BTreeMap::new();
+
+    for name in custom::collections::ALL {
+        let records = custom::iterate_raw(adapter, name).await?;
+        collections.insert(
+            name.to_string(),
+            records.into_iter().map(|(key, value)| (key, Bytes(value))).collect(),
+        );
+    }
+
+    Ok(StoreSnapshot::Custom { collections })
+}
+
+/// Decrypt a snapshot produced by [`export_snapshot`].
+fn open_snapshot(blob: &[u8], passphrase: &str) -> Result<(StoreCipher, StoreSnapshot)> {
+    let envelope: SnapshotEnvelope =
+        serde_json::from_slice(blob).map_err(|_| JsError::new("The snapshot is malformed"))?;
+
+    if envelope.version != SNAPSHOT_VERSION {
+        return Err(JsError::new(&format!(
+            "Unsupported snapshot version {}, expected {SNAPSHOT_VERSION}",
+            envelope.version
+        )));
+    }
+
+    let cipher = StoreCipher::import(passphrase, &envelope.cipher)
+        .map_err(|_| JsError::new("Failed to decrypt the snapshot: wrong passphrase?"))?;
+    let data = Zeroizing::new(cipher.decrypt_value_base64_data(envelope.data)?);
+    let snapshot =
+        serde_json::from_slice(&data).map_err(|_| JsError::new("The snapshot is malformed"))?;
+
+    Ok((cipher, snapshot))
+}
+
+/// Create a new IndexedDB-based store from a snapshot of another one.
+///
+/// The snapshot must have been made from an IndexedDB-based store, and the new
+/// store is encrypted if, and only if, that one was. Fails if there is already
+/// a store with the given name; if the import fails, the new store is
+/// deleted.
+pub(crate) async fn import_into_indexeddb(
+    blob: &[u8],
+    passphrase: &str,
+    store_name: &str,
+    store_passphrase: Option<&str>,
+) -> Result<StoreHandle> {
+    let (cipher, snapshot) = open_snapshot(blob, passphrase)?;
+
+    let StoreSnapshot::IndexedDb { encrypted, databases } = snapshot else {
+        return Err(JsError::new(
+            "The snapshot was not exported from an IndexedDB-based store, it can only be \
+            imported with `StoreHandle.importCustomSnapshot`",
+        ));
+    };
+
+    match (encrypted, store_passphrase) {
+        (true, None) => {
+            return Err(JsError::new(
+                "The snapshot was exported from an encrypted store, a passphrase is needed \
+                for the new store",
+            ));
+        }
+        (false, Some(_)) => {
+            return Err(JsError::new(
+                "The snapshot was exported from an unencrypted store, it cannot be imported \
+                into an encrypted one",
+            ));
+        }
+        _ => {}
+    }
+
+    if databases.len() != idb::database_names(store_name).len() {
+        return Err(JsError::new("The snapshot is malformed"));
+    }
+
+    for name in idb::database_names(store_name) {
+        if let Some(database) = idb::open_existing(&name).await? {
+            database.close();
+            return Err(JsError::new(&format!("The crypto store `{store_name}` already exists")));
+        }
+    }
+
+    let result = restore_indexeddb(&cipher, databases, store_name, store_passphrase).await;
+
+    if result.is_err() {
+        // Don't leave a half-written store behind. The import error is more
+        // useful than a failure to clean up, so the latter is ignored.
+        let _ = idb::delete_store_databases(store_name).await;
+    }
+
+    result
+}
+
+/// Create the databases of a new IndexedDB-based store, and fill them with
+/// the records of a snapshot.
+async fn restore_indexeddb(
+    cipher: &StoreCipher,
+    databases: Vec<DatabaseSnapshot>,
+    store_name: &str,
+    store_passphrase: Option<&str>,
+) -> Result<StoreHandle> {
+    // Let the store create its databases, then replace their content.
+    drop(StoreHandle::open(Some(store_name.to_owned()), store_passphrase.map(Into::into)).await?);
+
+    let meta_database_name = idb::meta_database_name(store_name);
+    for (name, snapshot) in idb::database_names(store_name).into_iter().zip(databases) {
+        let database = idb::open_existing(&name)
+            .await?
+            .ok_or_else(|| JsError::new(&format!("The database `{name}` does not exist")))?;
+
+        let cipher = match store_passphrase {
+            Some(store_passphrase) if name == meta_database_name => {
+                Some((cipher, StoreSecret::passphrase(store_passphrase.to_owned())))
+            }
+            _ => None,
+        };
+
+        let result = restore_database(&database, snapshot, cipher).await;
+        database.close();
+        result?;
+    }
+
+    StoreHandle::open(Some(store_name.to_owned()), store_passphrase.map(Into::into)).await
+}
+
+/// Replace the content of a database, just created by the store, with the
+/// records of a snapshot.
+///
+/// For the meta database of an encrypted store, `cipher` is the cipher of the
+/// snapshot records, which replaces the one of the new store, protected by the
+/// secret of the new store.
+async fn restore_database(
+    database: &IdbDatabase,
+    mut snapshot: DatabaseSnapshot,
+    cipher: Option<(&StoreCipher, StoreSecret)>,
+) -> Result<()> {
+    let names = object_store_names(database);
+
+    if database.version() != snapshot.version
+        || snapshot.object_stores.keys().any(|name| !names.contains(name))
+    {
+        return Err(JsError::new(
+            "The snapshot was exported from a store with a different database schema",
+        ));
+    }
+
+    let cipher_record = match cipher {
+        Some((cipher, secret)) => {
+            let stored =
+                idb::get_value(database, idb::META_CORE_OBJECT_STORE, idb::STORE_CIPHER_KEY)
+                    .await?;
+            let stored = StoredCipher::from_js(stored)?
+                .ok_or_else(|| JsError::new("The new crypto store is not encrypted"))?;
+
+            Some(stored.replacement(&Zeroizing::new(secret.export_cipher(cipher)?))?)
+        }
+        None => None,
+    };
+
+    let transaction = database
+        .transaction_with_str_sequence_and_mode(
+            &names.iter().map(JsValue::from).collect::<Array>(),
+            IdbTransactionMode::Readwrite,
+        )
+        .map_err(|_| write_error(database))?;
+
+    let result = write_records(&transaction, &names, &mut snapshot, cipher_record);
+    if result.is_err() {
+        let _ = transaction.abort();
+    }
+    result?;
+
+    idb::transaction_complete(&transaction).await.map_err(|_| write_error(database))
+}
+
+/// Queue the writes of a snapshot into the object stores of a transaction.
+fn write_records(
+    transaction: &IdbTransaction,
+    names: &[String],
+    snapshot: &mut DatabaseSnapshot,
+    cipher_record: Option<JsValue>,
+) -> Result<()> {
+    let database = transaction.db();
+
+    for name in names {
+        let object_store = transaction.object_store(name).map_err(|_| write_error(&database))?;
+        object_store.clear().map_err(|_| write_error(&database))?;
+
+        // Object stores with a key path take the key from the value itself.
+        let in_line_keys = !object_store.key_path().map_err(|_| write_error(&database))?.is_null();
+
+        for (key, value) in snapshot.object_stores.remove(name).unwrap_or_default() {
+            let value = value.to_js()?;
+            if in_line_keys {
+                object_store.put(&value)
+            } else {
+                object_store.put_with_key(&value, &key.to_js()?)
+            }
+            .map_err(|_| write_error(&database))?;
+        }
+    }
+
+    if let Some(cipher_record) = cipher_record {
+        transaction
+            .object_store(idb::META_CORE_OBJECT_STORE)
+            .and_then(|object_store| {
+                object_store.put_with_key(&cipher_record, &JsValue::from_str(idb::STORE_CIPHER_KEY))
+            })
+            .map_err(|_| write_error(&database))?;
+    }
+
+    Ok(())
+}
+
+fn write_error(database: &IdbDatabase) -> JsError {
+    JsError::new(&format!("Failed to write the snapshot into the database `{}`", database.name()))
+}
+
+/// The names of the object stores of a database.
+fn object_store_names(database: &IdbDatabase) -> Vec<String> {
+    let names = database.object_store_names();
+    (0..names.length()).filter_map(|index| names.get(index)).collect()
+}
+
+/// Fill an empty custom store adapter from a snapshot of another one, and open
+/// a store on it.
+///
+/// The snapshot must have been made from a store opened with
+/// `StoreHandle.openCustom`. Fails if the adapter holds any record; if the
+/// import fails, the records written to the adapter are removed.
+pub(crate) async fn import_into_custom(
+    blob: &[u8],
+    passphrase: &str,
+    adapter: CryptoStoreAdapter,
+) -> Result<StoreHandle> {
+    let (_, snapshot) = open_snapshot(blob, passphrase)?;
+
+    let StoreSnapshot::Custom { collections } = snapshot else {
+        return Err(JsError::new(
+            "The snapshot was not exported from a custom store, it can only be imported with \
+            `StoreHandle.importSnapshot`",
+        ));
+    };
+
+    if let Some(name) =
+        collections.keys().find(|name| !custom::collections::ALL.contains(&name.as_str()))
+    {
+        return Err(JsError::new(&format!("Unknown collection `{name}` in the snapshot")));
+    }
+
+    for name in custom::collections::ALL.iter().chain([&custom::collections::QUARANTINE]) {
+        if !custom::iterate_raw(&adapter, name).await?.is_empty() {
+            return Err(JsError::new(&format!(
+                "The adapter is not empty: the collection `{name}` holds records"
+            )));
+        }
+    }
+
+    let result = restore_custom(collections, adapter.clone()).await;
+
+    if result.is_err() {
+        // As above, don't leave a half-written store behind.
+        let _ = custom::wipe(&adapter).await;
+    }
+
+    result
+}
+
+/// Write the records of a snapshot to a custom store adapter, and open a store
+/// on it.
+async fn restore_custom(
+    collections: BTreeMap<String, Vec<(String, Bytes)>>,
+    adapter: CryptoStoreAdapter,
+) -> Result<StoreHandle> {
+    for (name, records) in &collections {
+        for (key, Bytes(value)) in records {
+            custom::put_raw(&adapter, name, key, value).await?;
+        }
+    }
+
+    let store = CustomStore::open(adapter.clone()).await?;
+    let inbound_group_sessions = store.inbound_group_sessions();
+
+    Ok(StoreHandle {
+        store: store.into_crypto_store(),
+        backend: StoreBackend::Custom { adapter, inbound_group_sessions },
+    })
+}
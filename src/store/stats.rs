@@ -0,0 +1,256 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics about the content of a crypto store.
+
+use std::collections::BTreeMap;
+
+use js_sys::{Array, JsString, Map};
+use matrix_sdk_common::ruma::OwnedRoomId;
+use matrix_sdk_crypto::store::DynCryptoStore;
+use wasm_bindgen::prelude::*;
+
+use super::{custom, idb, StoreBackend, INBOX_SECRET_NAMES};
+
+/// Statistics about the content of a crypto store, as returned by
+/// {@link StoreHandle.stats} and {@link OlmMachine.storeStats}.
+#[derive(Clone, Debug)]
+#[wasm_bindgen]
+pub struct StoreStats {
+    /// The number of Olm sessions.
+    ///
+    /// For memory stores, only the sessions with the devices of tracked users
+    /// are counted.
+    #[wasm_bindgen(readonly, js_name = "olmSessions")]
+    pub olm_sessions: usize,
+
+    /// The total number of inbound group sessions.
+    #[wasm_bindgen(readonly, js_name = "inboundGroupSessions")]
+    pub inbound_group_sessions: usize,
+
+    /// The number of devices of tracked users.
+    #[wasm_bindgen(readonly)]
+    pub devices: usize,
+
+    /// The number of cross-signing identities of tracked users.
+    #[wasm_bindgen(readonly, js_name = "userIdentities")]
+    pub user_identities: usize,
+
+    /// The number of tracked users.
+    #[wasm_bindgen(readonly, js_name = "trackedUsers")]
+    pub tracked_users: usize,
+
+    /// The number of secrets received but not yet processed.
+    #[wasm_bindgen(readonly, js_name = "pendingSecrets")]
+    pub pending_secrets: usize,
+
+    /// The number of outgoing secret requests not yet sent.
+    #[wasm_bindgen(readonly, js_name = "unsentSecretRequests")]
+    pub unsent_secret_requests: usize,
+
+    /// The number of withheld-key records.
+    ///
+    /// For memory stores, only the records of the rooms we have inbound group
+    /// sessions for are counted.
+    #[wasm_bindgen(readonly, js_name = "withheldSessions")]
+    pub withheld_sessions: usize,
+
+    per_room: BTreeMap<OwnedRoomId, usize>,
+    collections: Vec<CollectionStats>,
+}
+
+#[wasm_bindgen]
+impl StoreStats {
+    /// The number of inbound group sessions of each room.
+    #[wasm_bindgen(
+        js_name = "inboundGroupSessionsPerRoom",
+        unchecked_return_type = "Map<string, number>"
+    )]
+    pub fn inbound_group_sessions_per_room(&self) -> Map {
+        let map = Map::new();
+
+        for (room_id, count) in &self.per_room {
+            map.set(&JsString::from(room_id.as_str()), &JsValue::from(*count));
+        }
+
+        map
+    }
+
+    /// Statistics about each collection of the underlying storage.
+    ///
+    /// For IndexedDB-based stores, there is one entry per object store; for
+    /// stores opened with {@link StoreHandle.openCustom}, one entry per
+    /// adapter collection. It is empty for memory stores.
+    #[wasm_bindgen(unchecked_return_type = "CollectionStats[]")]
+    pub fn collections(&self) -> Array {
+        self.collections.iter().cloned().map(JsValue::from).collect()
+    }
+}
+
+/// Statistics about one collection of the storage backing a crypto store.
+#[derive(Clone, Debug)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct CollectionStats {
+    /// The name of the collection.
+    #[wasm_bindgen(readonly)]
+    pub name: String,
+
+    /// The number of records in the collection.
+    #[wasm_bindgen(readonly)]
+    pub records: usize,
+
+    /// The approximate size of the records, in bytes.
+    #[wasm_bindgen(readonly, js_name = "approximateBytes")]
+    pub approximate_bytes: usize,
+}
+
+/// Collect statistics about the given store.
+pub(crate) async fn store_stats(
+    store: &DynCryptoStore,
+    backend: &StoreBackend,
+) -> Result<StoreStats, JsError> {
+    let collections = match backend {
+        StoreBackend::Memory => Vec::new(),
+        StoreBackend::IndexedDb { store_name, .. } => indexeddb_collections(store_name).await?,
+        StoreBackend::Custom { adapter, .. } => {
+            let mut collections = Vec::new();
+            for name in custom::collections::ALL {
+                let records = custom::iterate_raw(adapter, name).await?;
+                collections.push(CollectionStats {
+                    name: name.to_string(),
+                    records: records.len(),
+                    approximate_bytes: records
+                        .iter()
+                        .map(|(key, value)| key.len() + value.len())
+                        .sum(),
+                });
+            }
+            collections
+        }
+    };
+
+    // Olm sessions and withheld-key records can't all be listed through the
+    // store, so they are counted from the underlying storage when possible.
+    let record_count = |indexeddb_object_store: &str, custom_collection: &str| {
+        let name = match backend {
+            StoreBackend::Memory => return None,
+            StoreBackend::IndexedDb { store_name, .. } => {
+                let [crypto_database_name, _] = idb::database_names(store_name);
+                format!("{crypto_database_name}/{indexeddb_object_store}")
+            }
+            StoreBackend::Custom { .. } => custom_collection.to_owned(),
+        };
+
+        Some(collections.iter().find(|collection| collection.name == name).map_or(0, |c| c.records))
+    };
+    let stored_olm_sessions =
+        record_count(idb::SESSIONS_OBJECT_STORE, custom::collections::SESSIONS);
+    let stored_withheld_sessions =
+        record_count(idb::WITHHELD_SESSIONS_OBJECT_STORE, custom::collections::WITHHELD_SESSIONS);
+
+    let mut olm_sessions = 0;
+    let mut devices = 0;
+    let mut user_identities = 0;
+
+    let tracked_users = store.load_tracked_users().await?;
+    for tracked_user in &tracked_users {
+        if store.get_user_identity(&tracked_user.user_id).await?.is_some() {
+            user_identities += 1;
+        }
+
+        for device in store.get_user_devices(&tracked_user.user_id).await?.into_values() {
+            devices += 1;
+
+            if stored_olm_sessions.is_none() {
+                if let Some(sender_key) = device.curve25519_key() {
+                    olm_sessions +=
+                        store.get_sessions(&sender_key.to_base64()).await?.map_or(0, |s| s.len());
+                }
+            }
+        }
+    }
+
+    let mut per_room = BTreeMap::new();
+    let inbound_group_sessions = store.get_inbound_group_sessions().await?;
+    for session in &inbound_group_sessions {
+        *per_room.entry(session.room_id().to_owned()).or_default() += 1;
+    }
+
+    let withheld_sessions = match stored_withheld_sessions {
+        Some(count) => count,
+        None => {
+            let mut count = 0;
+            for room_id in per_room.keys() {
+                count += store.get_withheld_sessions_by_room_id(room_id).await?.len();
+            }
+            count
+        }
+    };
+
+    let mut pending_secrets = 0;
+    for secret_name in INBOX_SECRET_NAMES {
+        pending_secrets += store.get_secrets_from_inbox(secret_name).await?.len();
+    }
+
+    Ok(StoreStats {
+        olm_sessions: stored_olm_sessions.unwrap_or(olm_sessions),
+        inbound_group_sessions: inbound_group_sessions.len(),
+        devices,
+        user_identities,
+        tracked_users: tracked_users.len(),
+        pending_secrets,
+        unsent_secret_requests: store.get_unsent_secret_requests().await?.len(),
+        withheld_sessions,
+        per_room,
+        collections,
+    })
+}
+
+/// Collect statistics about each object store of the databases of an
+/// IndexedDB-based store.
+async fn indexeddb_collections(store_name: &str) -> Result<Vec<CollectionStats>, JsError> {
+    let mut collections = Vec::new();
+
+    for database_name in idb::database_names(store_name) {
+        let Some(database) = idb::open_existing(&database_name).await? else {
+            continue;
+        };
+
+        let object_store_names = database.object_store_names();
+        for index in 0..object_store_names.length() {
+            let Some(object_store) = object_store_names.get(index) else {
+                continue;
+            };
+
+            let values = idb::get_all_values(&database, &object_store).await;
+            let values = match values {
+                Ok(values) => values,
+                Err(e) => {
+                    database.close();
+                    return Err(e);
+                }
+            };
+
+            collections.push(CollectionStats {
+                name: format!("{database_name}/{object_store}"),
+                records: values.length() as usize,
+                approximate_bytes: values.iter().map(|value| idb::approximate_size(&value)).sum(),
+            });
+        }
+
+        database.close();
+    }
+
+    Ok(collections)
+}
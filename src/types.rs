@@ -6,19 +6,21 @@ use std::{
 };
 
 use js_sys::{Array, JsString, Map, Set};
-use matrix_sdk_common::ruma::OwnedRoomId;
+use matrix_sdk_common::ruma::{serde::Raw, OwnedRoomId};
 use matrix_sdk_crypto::{
     backups::{
         SignatureState as InnerSignatureState, SignatureVerification as InnerSignatureVerification,
     },
+    olm::{EncryptionSettings, OutboundGroupSession, ShareInfo},
     MediaEncryptionInfo,
 };
+use serde::{Deserialize, Serialize};
 use tracing::warn;
 use wasm_bindgen::prelude::*;
 
 use crate::{
     encryption::EncryptionAlgorithm,
-    identifiers::{DeviceKeyId, RoomId, UserId},
+    identifiers::{DeviceId, DeviceKeyId, RoomId, UserId},
     impl_from_to_inner,
     responses::ToDeviceEncryptionInfo,
     vodozemac::Ed25519Signature,
@@ -542,6 +544,45 @@ impl InvalidToDeviceEvent {
     }
 }
 
+/// Convert a plain JavaScript object into raw JSON, for the methods which
+/// accept objects rather than JSON strings.
+///
+/// The object is read directly from JavaScript, without going through a JSON
+/// string there; it is only serialized once in Rust, since matrix-sdk-crypto
+/// works on raw JSON.
+pub(crate) fn js_object_to_raw<T>(object: JsValue) -> Result<Raw<T>, ObjectToRawError> {
+    let value = js_object_to_json(object)?;
+    Ok(Raw::from_json(serde_json::value::to_raw_value(&value)?))
+}
+
+/// Convert a plain JavaScript object into a JSON value, the same way as
+/// [`js_object_to_raw`], for the matrix-sdk-crypto methods which take parsed
+/// JSON.
+pub(crate) fn js_object_to_json(object: JsValue) -> Result<serde_json::Value, ObjectToRawError> {
+    Ok(serde_wasm_bindgen::from_value(object)?)
+}
+
+/// The error returned by [`js_object_to_raw`].
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ObjectToRawError {
+    /// The object could not be read from JavaScript.
+    #[error(transparent)]
+    Read(#[from] serde_wasm_bindgen::Error),
+
+    /// The object could not be serialized to JSON.
+    #[error(transparent)]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// Convert raw JSON from matrix-sdk-crypto into a plain JavaScript object, for
+/// the methods which return objects rather than JSON strings.
+///
+/// The object is built directly, without handing a JSON string to JavaScript.
+pub(crate) fn json_to_js_object(json: &str) -> Result<JsValue, JsError> {
+    let value: serde_json::Value = serde_json::from_str(json)?;
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
 /// Convert an `ProcessedToDeviceEvent` into a `JsValue`, ready to return to
 /// JavaScript.
 ///
@@ -679,3 +720,347 @@ impl From<matrix_sdk_crypto::store::types::RoomPendingKeyBundleDetails>
         Self { inner: value }
     }
 }
+
+/// The TypeScript type for the policy given to
+/// {@link OlmMachine.pruneInboundGroupSessions}.
+#[wasm_bindgen(typescript_custom_section)]
+const PRUNE_POLICY_TYPE: &str = r#"
+/**
+ * Which inbound group sessions {@link OlmMachine.pruneInboundGroupSessions}
+ * should delete.
+ *
+ * Only sessions which have been backed up are ever deleted. The other filters
+ * further restrict which sessions are deleted.
+ */
+interface PrunePolicy {
+    /** Only delete sessions of these rooms. */
+    roomIds?: string[];
+
+    /**
+     * Only delete sessions which were stored more than this many milliseconds
+     * ago. Sessions whose storage time is unknown are kept.
+     */
+    olderThanMs?: number;
+
+    /** Only report which sessions would be deleted, without deleting them. */
+    dryRun?: boolean;
+}"#;
+
+/// The policy given to {@link OlmMachine.pruneInboundGroupSessions}.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PrunePolicy {
+    #[serde(default)]
+    pub(crate) room_ids: Option<BTreeSet<OwnedRoomId>>,
+    #[serde(default)]
+    pub(crate) older_than_ms: Option<f64>,
+    #[serde(default)]
+    pub(crate) dry_run: bool,
+}
+
+/// The result of a call to {@link OlmMachine.pruneInboundGroupSessions}.
+#[derive(Clone, Debug)]
+#[wasm_bindgen]
+pub struct PruneResult {
+    /// Whether this was a dry run, in which case no session was deleted.
+    #[wasm_bindgen(readonly, js_name = "dryRun")]
+    pub dry_run: bool,
+
+    /// The number of sessions which were, or would have been, deleted.
+    #[wasm_bindgen(readonly)]
+    pub count: usize,
+
+    pub(crate) sessions: BTreeMap<OwnedRoomId, BTreeSet<String>>,
+}
+
+#[wasm_bindgen]
+impl PruneResult {
+    /// The sessions which were, or would have been, deleted.
+    ///
+    /// A Map from room ID to a Set of session IDs.
+    #[wasm_bindgen(unchecked_return_type = "Map<string, Set<string>>")]
+    pub fn sessions(&self) -> Map {
+        let map = Map::new();
+
+        for (room_id, sessions) in &self.sessions {
+            let s: Array = sessions.iter().map(|s| JsString::from(s.as_str())).collect();
+            map.set(&JsString::from(room_id.as_str()), &Set::new(&s));
+        }
+
+        map
+    }
+}
+
+/// The result of a call to {@link OlmMachine.encryptToDeviceEventForUsers}.
+#[derive(Debug)]
+#[wasm_bindgen]
+pub struct EncryptToDeviceResult {
+    pub(crate) requests: Array,
+    pub(crate) skipped: Vec<SkippedDevice>,
+}
+
+#[wasm_bindgen]
+impl EncryptToDeviceResult {
+    /// The requests which should be sent out to deliver the encrypted event.
+    ///
+    /// They don't need to be passed to {@link OlmMachine.markRequestAsSent}.
+    #[wasm_bindgen(unchecked_return_type = "ToDeviceRequest[]")]
+    pub fn requests(&self) -> Array {
+        self.requests.clone()
+    }
+
+    /// The devices for which the event was not encrypted.
+    #[wasm_bindgen(unchecked_return_type = "SkippedDevice[]")]
+    pub fn skipped(&self) -> Array {
+        self.skipped.iter().cloned().map(JsValue::from).collect()
+    }
+}
+
+/// A device for which {@link OlmMachine.encryptToDeviceEventForUsers} didn't
+/// encrypt the event.
+#[derive(Clone, Debug)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct SkippedDevice {
+    /// The owner of the device.
+    #[wasm_bindgen(readonly, js_name = "userId")]
+    pub user_id: UserId,
+
+    /// The ID of the device.
+    #[wasm_bindgen(readonly, js_name = "deviceId")]
+    pub device_id: DeviceId,
+
+    /// Why the device was skipped, as a withheld code.
+    ///
+    /// Example: "m.no_olm" if we have no Olm session with the device, or
+    /// "m.unverified" if it is excluded by the {@link CollectStrategy}.
+    #[wasm_bindgen(readonly)]
+    pub reason: String,
+}
+
+/// The state of the current outbound group session of a room, as returned by
+/// {@link OlmMachine.getOutboundGroupSessionInfo}.
+#[derive(Clone, Debug)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct OutboundGroupSessionInfo {
+    /// The ID of the session.
+    #[wasm_bindgen(readonly, js_name = "sessionId")]
+    pub session_id: String,
+
+    /// The time at which the session was created, in milliseconds since the
+    /// Unix epoch.
+    #[wasm_bindgen(readonly, js_name = "creationTimeMs")]
+    pub creation_time_ms: f64,
+
+    /// The number of messages encrypted with the session so far.
+    #[wasm_bindgen(readonly, js_name = "messageCount")]
+    pub message_count: f64,
+
+    /// Whether the session has been shared with the recipients.
+    #[wasm_bindgen(readonly)]
+    pub shared: bool,
+
+    /// Whether the session has been invalidated, for example because a member
+    /// left the room.
+    #[wasm_bindgen(readonly)]
+    pub invalidated: bool,
+
+    /// Whether the session will be replaced by a new one the next time the
+    /// room key is shared with the {@link EncryptionSettings} given to
+    /// {@link OlmMachine.getOutboundGroupSessionInfo}: because it has been
+    /// invalidated, it reached the rotation period of the settings it was
+    /// created with, or the algorithm or history visibility changed.
+    #[wasm_bindgen(readonly, js_name = "rotationDue")]
+    pub rotation_due: bool,
+
+    pub(crate) shared_with: BTreeMap<String, BTreeMap<String, u32>>,
+    pub(crate) withheld_from: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[wasm_bindgen]
+impl OutboundGroupSessionInfo {
+    /// The devices the session has been shared with.
+    ///
+    /// A Map from user ID to a Map from device ID to the first message index
+    /// the device can decrypt.
+    #[wasm_bindgen(
+        js_name = "sharedWith",
+        unchecked_return_type = "Map<string, Map<string, number>>"
+    )]
+    pub fn shared_with(&self) -> Map {
+        nested_map(&self.shared_with, |index| JsValue::from(*index))
+    }
+
+    /// The devices the session has been withheld from.
+    ///
+    /// A Map from user ID to a Map from device ID to the withheld code, for
+    /// example "m.unverified".
+    #[wasm_bindgen(
+        js_name = "withheldFrom",
+        unchecked_return_type = "Map<string, Map<string, string>>"
+    )]
+    pub fn withheld_from(&self) -> Map {
+        nested_map(&self.withheld_from, |code| JsValue::from(code.as_str()))
+    }
+}
+
+/// Convert a map of maps keyed by user and device ID into a JavaScript `Map`
+/// of `Map`s.
+fn nested_map<T>(
+    map: &BTreeMap<String, BTreeMap<String, T>>,
+    to_js: impl Fn(&T) -> JsValue,
+) -> Map {
+    let outer = Map::new();
+
+    for (user_id, devices) in map {
+        let inner = Map::new();
+        for (device_id, value) in devices {
+            inner.set(&JsString::from(device_id.as_str()), &to_js(value));
+        }
+        outer.set(&JsString::from(user_id.as_str()), &inner);
+    }
+
+    outer
+}
+
+impl OutboundGroupSessionInfo {
+    /// Describe the given session, and whether it would be rotated when
+    /// sharing the room key with the given settings.
+    pub(crate) async fn new(session: &OutboundGroupSession, settings: &EncryptionSettings) -> Self {
+        // The creation time, message count and recipients have no accessors,
+        // so they are read from a pickle of the session.
+        let pickle = session.pickle().await;
+        let invalidated = session.invalidated();
+
+        let creation_time_ms = f64::from(pickle.creation_time.get()) * 1000.0;
+        let now_ms = f64::from(matrix_sdk_common::ruma::MilliSecondsSinceUnixEpoch::now().get());
+        let expired = pickle.message_count >= pickle.settings.rotation_period_msgs
+            || now_ms - creation_time_ms >= pickle.settings.rotation_period.as_millis() as f64;
+        let settings_changed = pickle.settings.algorithm != settings.algorithm
+            || pickle.settings.history_visibility != settings.history_visibility;
+        let rotation_due = invalidated || expired || settings_changed;
+
+        let mut shared_with = BTreeMap::<_, BTreeMap<_, _>>::new();
+        let mut withheld_from = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for (user_id, devices) in pickle.shared_with_set {
+            for (device_id, share_info) in devices {
+                match share_info {
+                    ShareInfo::Shared(shared) => {
+                        shared_with
+                            .entry(user_id.to_string())
+                            .or_default()
+                            .insert(device_id.to_string(), shared.message_index);
+                    }
+                    ShareInfo::Withheld(code) => {
+                        withheld_from
+                            .entry(user_id.to_string())
+                            .or_default()
+                            .insert(device_id.to_string(), code.as_str().to_owned());
+                    }
+                }
+            }
+        }
+
+        Self {
+            session_id: session.session_id().to_owned(),
+            creation_time_ms,
+            message_count: pickle.message_count as f64,
+            shared: session.shared(),
+            invalidated,
+            rotation_due,
+            shared_with,
+            withheld_from,
+        }
+    }
+}
+
+/// Why {@link OlmMachine.planRoomKeyShare} expects a device not to receive the
+/// room key.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomKeyWithheldReason {
+    /// The device is excluded by the {@link CollectStrategy}, because it isn't
+    /// verified or not signed by its owner, or because it is a dehydrated
+    /// device which its owner didn't sign. The key would be withheld with the
+    /// "m.unverified" code.
+    Unverified,
+
+    /// The device has been blacklisted. The key would be withheld with the
+    /// "m.blacklisted" code.
+    Blacklisted,
+
+    /// We have no Olm session with the device. The key would be withheld with
+    /// the "m.no_olm" code, unless a session is established with
+    /// {@link OlmMachine.getMissingSessions} first.
+    NoOlmSession,
+
+    /// The owner of the device was verified, but has since changed their
+    /// identity, or the device is not signed by them. The
+    /// {@link CollectStrategy} makes {@link OlmMachine.shareRoomKey} fail
+    /// until the problem is resolved.
+    VerificationViolation,
+}
+
+/// The result of a call to {@link OlmMachine.planRoomKeyShare}.
+#[derive(Debug)]
+#[wasm_bindgen]
+pub struct RoomKeySharePlan {
+    /// Whether {@link OlmMachine.shareRoomKey} would fail, because some
+    /// devices are withheld with
+    /// {@link RoomKeyWithheldReason.VerificationViolation}, or because the
+    /// {@link CollectStrategy} is identity-based and our own device isn't
+    /// verified by our cross-signing identity.
+    #[wasm_bindgen(readonly, js_name = "wouldFail")]
+    pub would_fail: bool,
+
+    pub(crate) recipients: BTreeMap<String, BTreeSet<String>>,
+    pub(crate) withheld: BTreeMap<String, BTreeMap<String, RoomKeyWithheldReason>>,
+}
+
+#[wasm_bindgen]
+impl RoomKeySharePlan {
+    /// The devices which would receive the room key.
+    ///
+    /// A Map from user ID to the IDs of their devices.
+    #[wasm_bindgen(unchecked_return_type = "Map<string, string[]>")]
+    pub fn recipients(&self) -> Map {
+        let map = Map::new();
+
+        for (user_id, devices) in &self.recipients {
+            let devices: Array = devices.iter().map(|d| JsString::from(d.as_str())).collect();
+            map.set(&JsString::from(user_id.as_str()), &devices);
+        }
+
+        map
+    }
+
+    /// The devices which would not receive the room key.
+    ///
+    /// A Map from user ID to a Map from device ID to the reason.
+    #[wasm_bindgen(unchecked_return_type = "Map<string, Map<string, RoomKeyWithheldReason>>")]
+    pub fn withheld(&self) -> Map {
+        nested_map(&self.withheld, |reason| JsValue::from(*reason))
+    }
+}
+
+impl RoomKeySharePlan {
+    pub(crate) fn new() -> Self {
+        Self { would_fail: false, recipients: BTreeMap::new(), withheld: BTreeMap::new() }
+    }
+
+    pub(crate) fn add_recipient(&mut self, user_id: &str, device_id: &str) {
+        self.recipients.entry(user_id.to_owned()).or_default().insert(device_id.to_owned());
+    }
+
+    pub(crate) fn add_withheld(
+        &mut self,
+        user_id: &str,
+        device_id: &str,
+        reason: RoomKeyWithheldReason,
+    ) {
+        if reason == RoomKeyWithheldReason::VerificationViolation {
+            self.would_fail = true;
+        }
+
+        self.withheld.entry(user_id.to_owned()).or_default().insert(device_id.to_owned(), reason);
+    }
+}
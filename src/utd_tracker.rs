@@ -0,0 +1,308 @@
+// Copyright 2026 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tracking of the room events which couldn't be decrypted because their room
+//! key was missing, so that they can be decrypted again once it arrives.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::{Rc, Weak},
+};
+
+use futures_util::{pin_mut, StreamExt};
+use js_sys::{Array, Function};
+use matrix_sdk_common::ruma::{serde::Raw, OwnedRoomId, RoomId};
+use matrix_sdk_crypto::{types::events::room::encrypted::EncryptedEvent, DecryptionSettings};
+use serde::Deserialize;
+use tracing::{debug, warn, Dispatch};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::DecryptionErrorCode,
+    future::BackgroundTask,
+    identifiers,
+    machine::{promise_result_to_future, OlmMachine},
+};
+
+/// The maximum number of events tracked at once. Once it is reached, the
+/// oldest tracked event is forgotten to make room for each new one.
+const MAX_TRACKED_EVENTS: usize = 1000;
+
+/// The part of the content of an encrypted room event which identifies its
+/// Megolm session.
+#[derive(Deserialize)]
+struct EncryptedContentSessionId {
+    session_id: Option<String>,
+}
+
+/// Get the ID of the Megolm session of an encrypted room event, if any.
+pub(crate) fn session_id(event: &Raw<EncryptedEvent>) -> Option<String> {
+    event
+        .get_field::<EncryptedContentSessionId>("content")
+        .ok()
+        .flatten()
+        .and_then(|content| content.session_id)
+}
+
+#[derive(Debug)]
+struct TrackedEvent {
+    /// The position of the event in the order in which events were tracked.
+    sequence: u64,
+    event: Raw<EncryptedEvent>,
+    decryption_settings: DecryptionSettings,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    /// The tracked events, by room and session ID.
+    events: HashMap<(OwnedRoomId, String), Vec<TrackedEvent>>,
+
+    /// The room and session ID of the tracked events, in the order in which
+    /// they were tracked.
+    order: BTreeMap<u64, (OwnedRoomId, String)>,
+
+    /// The sequence number of the next tracked event.
+    next_sequence: u64,
+
+    /// The task decrypting the tracked events, if a callback has been
+    /// registered.
+    task: Option<BackgroundTask>,
+}
+
+impl TrackerState {
+    /// Forget the event which has been tracked for the longest time.
+    fn evict_oldest(&mut self) {
+        let Some((sequence, key)) = self.order.pop_first() else { return };
+        debug!(room_id = ?key.0, session_id = key.1, "Too many undecryptable events, forgetting the oldest one");
+
+        if let Some(events) = self.events.get_mut(&key) {
+            events.retain(|tracked| tracked.sequence != sequence);
+            if events.is_empty() {
+                self.events.remove(&key);
+            }
+        }
+    }
+}
+
+/// Remembers the room events which couldn't be decrypted because their room
+/// key was missing, and decrypts them again once a matching room key is
+/// received.
+///
+/// Events are only tracked once a callback has been registered with
+/// [`UtdTracker::start`]. The task decrypting the events is stopped with
+/// [`UtdTracker::stop`], or once the tracker is dropped, that is once the
+/// `OlmMachine` is closed or freed.
+#[derive(Debug, Default)]
+pub(crate) struct UtdTracker {
+    state: RefCell<TrackerState>,
+}
+
+impl UtdTracker {
+    /// Remember an event which failed to decrypt with the given error code,
+    /// if it may be decrypted once a new room key arrives.
+    pub(crate) fn track(
+        &self,
+        room_id: &RoomId,
+        event: &Raw<EncryptedEvent>,
+        decryption_settings: &DecryptionSettings,
+        code: DecryptionErrorCode,
+    ) {
+        if !matches!(
+            code,
+            DecryptionErrorCode::MissingRoomKey | DecryptionErrorCode::UnknownMessageIndex
+        ) {
+            return;
+        }
+
+        let Some(session_id) = session_id(event) else { return };
+        let mut state = self.state.borrow_mut();
+
+        if state.task.is_none() {
+            return;
+        }
+
+        let key = (room_id.to_owned(), session_id);
+        if state.events.get(&key).is_some_and(|events| {
+            events.iter().any(|tracked| tracked.event.json().get() == event.json().get())
+        }) {
+            return;
+        }
+
+        if state.order.len() >= MAX_TRACKED_EVENTS {
+            state.evict_oldest();
+        }
+
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        state.order.insert(sequence, key.clone());
+        state.events.entry(key).or_default().push(TrackedEvent {
+            sequence,
+            event: event.clone(),
+            decryption_settings: decryption_settings.clone(),
+        });
+    }
+
+    /// Forget, and return, the events of the given session.
+    fn take(&self, room_id: &RoomId, session_id: &str) -> Vec<TrackedEvent> {
+        let mut state = self.state.borrow_mut();
+        let events =
+            state.events.remove(&(room_id.to_owned(), session_id.to_owned())).unwrap_or_default();
+        for tracked in &events {
+            state.order.remove(&tracked.sequence);
+        }
+
+        events
+    }
+
+    /// Start decrypting the tracked events when their room key arrives,
+    /// passing them to `callback`.
+    ///
+    /// Replaces any previously registered callback.
+    pub(crate) fn start(
+        self: &Rc<Self>,
+        machine: matrix_sdk_crypto::OlmMachine,
+        callback: Function,
+        tracing_subscriber: &Dispatch,
+    ) {
+        let task = BackgroundTask::spawn(
+            decrypt_on_new_room_keys(machine, Rc::downgrade(self), callback),
+            tracing_subscriber,
+        );
+
+        // Replacing the previous task, if any, aborts it.
+        self.state.borrow_mut().task = Some(task);
+    }
+
+    /// Stop decrypting the tracked events, and wait until the task doing it
+    /// has stopped.
+    pub(crate) async fn stop(&self) {
+        let task = self.state.borrow_mut().task.take();
+
+        if let Some(task) = task {
+            task.abort().await;
+        }
+    }
+}
+
+/// Decrypt the tracked events of each room key as it is received, whether
+/// from a sync, a forwarded key, a backup or a room key bundle.
+async fn decrypt_on_new_room_keys(
+    machine: matrix_sdk_crypto::OlmMachine,
+    tracker: Weak<UtdTracker>,
+    callback: Function,
+) {
+    let stream = machine.store().room_keys_received_stream();
+    pin_mut!(stream);
+
+    while let Some(item) = stream.next().await {
+        let room_keys = match item {
+            Ok(room_keys) => room_keys,
+            Err(e) => {
+                warn!("Error reading room_keys_received_stream {e:?}");
+                continue;
+            }
+        };
+
+        for room_key in room_keys {
+            let Some(events) = tracker
+                .upgrade()
+                .map(|tracker| tracker.take(&room_key.room_id, &room_key.session_id))
+            else {
+                return;
+            };
+
+            let decrypted = Array::new();
+            for tracked in events {
+                match OlmMachine::decrypt_room_event_helper(
+                    &machine,
+                    &tracked.event,
+                    &room_key.room_id,
+                    &tracked.decryption_settings,
+                )
+                .await
+                {
+                    Ok(event) => {
+                        decrypted.push(&event.into());
+                    }
+                    Err(error) => {
+                        // The new key may still not be able to decrypt the event, for example
+                        // if it was received at a later index: keep waiting for a better one.
+                        if let Some(tracker) = tracker.upgrade() {
+                            tracker.track(
+                                &room_key.room_id,
+                                &tracked.event,
+                                &tracked.decryption_settings,
+                                error.code,
+                            );
+                        }
+                    }
+                }
+            }
+
+            if decrypted.length() == 0 {
+                continue;
+            }
+
+            let room_id = identifiers::RoomId::from(room_key.room_id.clone());
+            if let Err(e) = promise_result_to_future(callback.call2(
+                &JsValue::NULL,
+                &room_id.into(),
+                &decrypted,
+            ))
+            .await
+            {
+                warn!("Error calling the events decrypted callback: {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use matrix_sdk_common::ruma::{room_id, serde::Raw};
+    use matrix_sdk_crypto::{DecryptionSettings, TrustRequirement};
+    use wasm_bindgen_test::wasm_bindgen_test;
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_node_experimental);
+
+    use tracing::Dispatch;
+
+    use super::{UtdTracker, MAX_TRACKED_EVENTS};
+    use crate::{error::DecryptionErrorCode, future::BackgroundTask};
+
+    #[wasm_bindgen_test]
+    fn test_evict_oldest_events_past_the_limit() {
+        let room_id = room_id!("!room:example.org");
+        let settings =
+            DecryptionSettings { sender_device_trust_requirement: TrustRequirement::Untrusted };
+        let tracker = UtdTracker::default();
+        tracker.state.borrow_mut().task =
+            Some(BackgroundTask::spawn(futures_util::future::pending(), &Dispatch::none()));
+
+        for i in 0..MAX_TRACKED_EVENTS + 2 {
+            let event = Raw::from_json_string(format!(
+                r#"{{"event_id":"$event{i}","content":{{"session_id":"session{i}"}}}}"#
+            ))
+            .unwrap();
+            tracker.track(room_id, &event, &settings, DecryptionErrorCode::MissingRoomKey);
+        }
+
+        assert_eq!(tracker.state.borrow().order.len(), MAX_TRACKED_EVENTS);
+        assert!(tracker.take(room_id, "session0").is_empty());
+        assert!(tracker.take(room_id, "session1").is_empty());
+        assert_eq!(tracker.take(room_id, "session2").len(), 1);
+        assert_eq!(tracker.take(room_id, &format!("session{}", MAX_TRACKED_EVENTS + 1)).len(), 1);
+        assert_eq!(tracker.state.borrow().order.len(), MAX_TRACKED_EVENTS - 2);
+    }
+}
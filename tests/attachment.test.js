@@ -1,4 +1,4 @@
-const { Attachment, EncryptedAttachment } = require("@matrix-org/matrix-sdk-crypto-wasm");
+const { Attachment, AttachmentDecryptor, AttachmentEncryptor, EncryptedAttachment } = require("@matrix-org/matrix-sdk-crypto-wasm");
 
 describe(Attachment.name, () => {
     const originalData = "hello";
@@ -81,3 +81,69 @@ describe(EncryptedAttachment.name, () => {
         expect(encryptedAttachment.hasMediaEncryptionInfoBeenConsumed).toStrictEqual(true);
     });
 });
+
+describe(AttachmentEncryptor.name, () => {
+    const textEncoder = new TextEncoder();
+    const textDecoder = new TextDecoder();
+
+    test("can encrypt data in chunks", () => {
+        const encryptor = new AttachmentEncryptor();
+        const first = encryptor.push(textEncoder.encode("hello "));
+        const second = encryptor.push(textEncoder.encode("world"));
+        const mediaEncryptionInfo = encryptor.finish();
+
+        expect(first).toHaveLength(6);
+        expect(second).toHaveLength(5);
+        expect(JSON.parse(mediaEncryptionInfo).hashes.sha256).toBeDefined();
+
+        const encryptedAttachment = new EncryptedAttachment(new Uint8Array([...first, ...second]), mediaEncryptionInfo);
+        expect(textDecoder.decode(Attachment.decrypt(encryptedAttachment))).toStrictEqual("hello world");
+    });
+
+    test("can't be used once finished", () => {
+        const encryptor = new AttachmentEncryptor();
+        encryptor.finish();
+
+        expect(() => encryptor.push(textEncoder.encode("hello"))).toThrow();
+        expect(() => encryptor.finish()).toThrow();
+    });
+});
+
+describe(AttachmentDecryptor.name, () => {
+    const textEncoder = new TextEncoder();
+    const textDecoder = new TextDecoder();
+
+    let ciphertext;
+    let mediaEncryptionInfo;
+
+    beforeEach(() => {
+        const encryptedAttachment = Attachment.encrypt(textEncoder.encode("hello world"));
+        ciphertext = encryptedAttachment.encryptedData;
+        mediaEncryptionInfo = encryptedAttachment.mediaEncryptionInfo;
+    });
+
+    test("can decrypt data in chunks", () => {
+        const decryptor = new AttachmentDecryptor(mediaEncryptionInfo);
+        const first = decryptor.push(ciphertext.slice(0, 6));
+        const second = decryptor.push(ciphertext.slice(6));
+        decryptor.finish();
+
+        expect(textDecoder.decode(new Uint8Array([...first, ...second]))).toStrictEqual("hello world");
+    });
+
+    test("fails on a hash mismatch", () => {
+        ciphertext[0] ^= 1;
+
+        const decryptor = new AttachmentDecryptor(mediaEncryptionInfo);
+        decryptor.push(ciphertext);
+
+        expect(() => decryptor.finish()).toThrow();
+    });
+
+    test("fails on truncated data", () => {
+        const decryptor = new AttachmentDecryptor(mediaEncryptionInfo);
+        decryptor.push(ciphertext.slice(0, 6));
+
+        expect(() => decryptor.finish()).toThrow();
+    });
+});
@@ -1,4 +1,4 @@
-const { BackupDecryptionKey } = require("@matrix-org/matrix-sdk-crypto-wasm");
+const { BackupDecryptionKey, RoomId } = require("@matrix-org/matrix-sdk-crypto-wasm");
 
 const aMegolmKey = {
     algorithm: "m.megolm.v1.aes-sha2",
@@ -44,3 +44,75 @@ describe("BackupDecryptionKey", () => {
         }).toThrow();
     });
 });
+
+describe("BackupDecryptionKey.decryptKeysBackupData", () => {
+    const backupKey = BackupDecryptionKey.fromBase64("Ha9cklU/9NqFo9WKdVfGzmqUL/9wlkdxfEitbSIPVXw");
+    const keysBackupData = JSON.stringify({
+        rooms: {
+            "!room:example.org": {
+                sessions: {
+                    good: encryptedMegolm,
+                    bad: { ...encryptedMegolm, session_data: { ...encryptedMegolm.session_data, mac: "AAAAAAAAAAA" } },
+                },
+            },
+        },
+    });
+
+    test("decrypts the room keys and reports the failures", () => {
+        const progress = [];
+        const result = backupKey.decryptKeysBackupData(keysBackupData, "1", (done, total) =>
+            progress.push([done, total]),
+        );
+
+        expect(result.backupVersion).toStrictEqual("1");
+        expect(progress).toStrictEqual([
+            [1, 2],
+            [2, 2],
+        ]);
+
+        const [[roomId, roomKeys]] = [...result.keys.entries()];
+        expect(roomId).toBeInstanceOf(RoomId);
+        expect(roomId.toString()).toStrictEqual("!room:example.org");
+        expect([...roomKeys.keys()]).toStrictEqual(["good"]);
+        expect(roomKeys.get("good").session_key).toStrictEqual(aMegolmKey.session_key);
+
+        expect(result.failures).toHaveLength(1);
+        expect(result.failures[0].roomId.toString()).toStrictEqual("!room:example.org");
+        expect(result.failures[0].sessionId).toStrictEqual("bad");
+        expect(result.failures[0].error).toBeDefined();
+    });
+
+    test("rejects a malformed response", () => {
+        expect(() => backupKey.decryptKeysBackupData("{}", "1")).toThrow();
+    });
+});
+
+describe("BackupDecryptionKey recovery keys", () => {
+    const base64Key = "Ha9cklU/9NqFo9WKdVfGzmqUL/9wlkdxfEitbSIPVXw";
+    const recoveryKey = "EsT9 o94t XK7n JuLr 2XL9 pS3c HCJX umqU 17f4 dZyT WN5W Xu9G";
+
+    test("round-trips through a recovery key", () => {
+        const key = BackupDecryptionKey.fromBase64(base64Key);
+        expect(key.toRecoveryKey().replace(/ /g, "")).toStrictEqual(recoveryKey.replace(/ /g, ""));
+
+        expect(BackupDecryptionKey.fromRecoveryKey(recoveryKey).toBase64()).toStrictEqual(base64Key);
+        expect(BackupDecryptionKey.fromRecoveryKey(recoveryKey.replace(/ /g, "")).toBase64()).toStrictEqual(base64Key);
+    });
+
+    test("explains what is wrong with a mistyped recovery key", () => {
+        expect(() => BackupDecryptionKey.fromRecoveryKey(recoveryKey.replace("o94t", "094t"))).toThrow(
+            "did you mean 'o'?",
+        );
+        expect(() => BackupDecryptionKey.fromRecoveryKey(recoveryKey.slice(0, -1))).toThrow("48 characters");
+        expect(() => BackupDecryptionKey.fromRecoveryKey(recoveryKey.replace("EsT9", "EsT8"))).toThrow(
+            "typed correctly",
+        );
+    });
+
+    test("derives a key from a passphrase", () => {
+        const key = BackupDecryptionKey.fromPassphrase("correct horse battery staple", "saltysalt", 1000);
+        expect(key.toBase64()).toStrictEqual("2Uvmd7sCXFZiW7V72LzAaWbbFabu62UXuop7q9mptrc");
+
+        expect(() => BackupDecryptionKey.fromPassphrase("correct horse battery staple", "saltysalt", 0)).toThrow();
+    });
+});
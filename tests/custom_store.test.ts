@@ -0,0 +1,239 @@
+/*
+Copyright 2026 The Matrix.org Foundation C.I.C.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+import {
+    BackupDecryptionKey,
+    DeviceId,
+    EncryptionSettings,
+    OlmMachine,
+    RequestType,
+    RoomId,
+    StoreHandle,
+    UserId,
+} from "@matrix-org/matrix-sdk-crypto-wasm";
+import "fake-indexeddb/auto";
+
+import { MapAdapter } from "./helper.ts";
+
+describe("StoreHandle.openCustom", () => {
+    const user = new UserId("@alice:example.org");
+    const device = new DeviceId("ALICEDEVICE");
+
+    test("persists the account through the adapter", async () => {
+        const adapter = new MapAdapter();
+
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        const identityKeys = machine.identityKeys;
+        await machine.close();
+        storeHandle.free();
+
+        expect(adapter.collections.get("core")?.has("account")).toBe(true);
+
+        const storeHandle2 = await StoreHandle.openCustom(adapter);
+        const machine2 = await OlmMachine.initFromStore(user, device, storeHandle2);
+        expect(machine2.identityKeys.ed25519.toBase64()).toEqual(identityKeys.ed25519.toBase64());
+        expect(machine2.identityKeys.curve25519.toBase64()).toEqual(identityKeys.curve25519.toBase64());
+    });
+
+    test("reports adapter errors", async () => {
+        const adapter = new MapAdapter();
+        adapter.iterate = async () => {
+            throw new Error("adapter unavailable");
+        };
+
+        await expect(StoreHandle.openCustom(adapter)).rejects.toThrow();
+    });
+});
+
+describe("StoreHandle.verifyCustomIntegrity", () => {
+    const user = new UserId("@alice:example.org");
+    const device = new DeviceId("ALICEDEVICE");
+
+    async function corruptedAdapter(): Promise<MapAdapter> {
+        const adapter = new MapAdapter();
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.close();
+        storeHandle.free();
+
+        await adapter.put("devices", "@bob:example.org|BOBDEVICE", new TextEncoder().encode("not json"));
+        return adapter;
+    }
+
+    test("reports unreadable records", async () => {
+        const adapter = await corruptedAdapter();
+        await expect(StoreHandle.openCustom(adapter)).rejects.toThrow();
+
+        const report = await StoreHandle.verifyCustomIntegrity(adapter);
+        expect(report.isHealthy).toBe(false);
+        expect(report.quarantined).toEqual(0);
+
+        const problems = report.problems();
+        expect(problems).toHaveLength(1);
+        expect(problems[0].collection).toEqual("devices");
+        expect(problems[0].key).toEqual("@bob:example.org|BOBDEVICE");
+        expect(problems[0].quarantined).toBe(false);
+    });
+
+    test("can quarantine unreadable records", async () => {
+        const adapter = await corruptedAdapter();
+
+        const report = await StoreHandle.verifyCustomIntegrity(adapter, { repair: true });
+        expect(report.quarantined).toEqual(1);
+        expect(report.problems()[0].quarantined).toBe(true);
+        expect(adapter.collections.get("quarantine")?.has("devices|@bob:example.org|BOBDEVICE")).toBe(true);
+
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        expect((await storeHandle.verifyIntegrity()).isHealthy).toBe(true);
+        await OlmMachine.initFromStore(user, device, storeHandle);
+    });
+});
+
+describe("OlmMachine.pruneInboundGroupSessions", () => {
+    const user = new UserId("@alice:example.org");
+    const device = new DeviceId("ALICEDEVICE");
+    const room = new RoomId("!room:example.org");
+
+    async function backUpRoomKeys(machine: OlmMachine, version: string) {
+        const backupKey = BackupDecryptionKey.createRandomKey();
+        await machine.enableBackupV1(backupKey.megolmV1PublicKey.publicKeyBase64, version);
+        await machine.saveBackupDecryptionKey(backupKey, version);
+        const request = (await machine.backupRoomKeys())!;
+        await machine.markRequestAsSent(request.id, RequestType.KeysBackup, "{}");
+    }
+
+    test("only deletes sessions which are backed up", async () => {
+        const adapter = new MapAdapter();
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+
+        await expect(machine.pruneInboundGroupSessions({ dryRun: true })).rejects.toThrow();
+
+        await backUpRoomKeys(machine, "1");
+        await machine.shareRoomKey(new RoomId("!late:example.org"), [], new EncryptionSettings());
+
+        const otherRoom = await machine.pruneInboundGroupSessions({ roomIds: ["!other:example.org"], dryRun: true });
+        expect(otherRoom.count).toStrictEqual(0);
+
+        const dryRun = await machine.pruneInboundGroupSessions({ dryRun: true });
+        expect(dryRun.dryRun).toBe(true);
+        expect(dryRun.count).toStrictEqual(1);
+        expect(dryRun.sessions().get(room.toString())?.size).toStrictEqual(1);
+        expect(adapter.collections.get("inbound_group_sessions")?.size).toStrictEqual(2);
+
+        const result = await machine.pruneInboundGroupSessions({});
+        expect(result.dryRun).toBe(false);
+        expect(result.count).toStrictEqual(1);
+        expect(adapter.collections.get("inbound_group_sessions")?.size).toStrictEqual(1);
+
+        expect(await machine.getInboundGroupSessions(room)).toHaveLength(0);
+        const counts = await machine.roomKeyCounts();
+        expect(counts.total).toStrictEqual(1);
+        expect(counts.backedUp).toStrictEqual(0);
+    });
+
+    test("doesn't delete sessions backed up to a previous backup", async () => {
+        const storeHandle = await StoreHandle.openCustom(new MapAdapter());
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+
+        await backUpRoomKeys(machine, "1");
+        expect((await machine.getInboundGroupSessions(room))[0].backedUp).toBe(true);
+
+        await machine.disableBackup();
+        const backupKey = BackupDecryptionKey.createRandomKey();
+        await machine.enableBackupV1(backupKey.megolmV1PublicKey.publicKeyBase64, "2");
+        await machine.saveBackupDecryptionKey(backupKey, "2");
+
+        expect((await machine.pruneInboundGroupSessions({ dryRun: true })).count).toStrictEqual(0);
+        expect((await machine.getInboundGroupSessions(room))[0].backedUp).toBe(false);
+    });
+
+    test("doesn't delete sessions after the backup state is reset", async () => {
+        const adapter = new MapAdapter();
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+
+        await backUpRoomKeys(machine, "1");
+        expect((await machine.pruneInboundGroupSessions({ dryRun: true })).count).toStrictEqual(1);
+
+        const backupKey = BackupDecryptionKey.createRandomKey();
+        await machine.resetBackupState("2", backupKey.megolmV1PublicKey.publicKeyBase64);
+
+        const sessions = await machine.getInboundGroupSessions(room);
+        expect(sessions).toHaveLength(1);
+        expect(sessions[0].backedUp).toBe(false);
+        expect((await machine.pruneInboundGroupSessions({ dryRun: true })).count).toStrictEqual(0);
+        expect((await machine.roomKeyCounts()).backedUp).toStrictEqual(0);
+    });
+
+    test("keeps the previous backup if resetting the backup state fails", async () => {
+        const adapter = new MapAdapter();
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+        await backUpRoomKeys(machine, "1");
+
+        const put = adapter.put.bind(adapter);
+        const backupKey = BackupDecryptionKey.createRandomKey();
+        for (const failingCollection of ["inbound_group_sessions", "core"]) {
+            adapter.put = async (collection, key, value) => {
+                if (collection === failingCollection) throw new Error("adapter unavailable");
+                return put(collection, key, value);
+            };
+
+            await expect(
+                machine.resetBackupState("2", backupKey.megolmV1PublicKey.publicKeyBase64),
+            ).rejects.toThrow();
+
+            expect(await machine.isBackupEnabled()).toBe(true);
+            expect((await machine.getBackupKeys()).backupVersion).toStrictEqual("1");
+            expect((await machine.roomKeyCounts()).backedUp).toStrictEqual(1);
+        }
+
+        adapter.put = put;
+        await machine.resetBackupState("2", backupKey.megolmV1PublicKey.publicKeyBase64);
+        expect((await machine.getBackupKeys()).backupVersion).toStrictEqual("2");
+        expect((await machine.roomKeyCounts()).backedUp).toStrictEqual(0);
+    });
+
+    test.each([
+        ["encrypted", "passphrase"],
+        ["unencrypted", undefined],
+    ])("deletes sessions from %s IndexedDB stores", async (name, passphrase) => {
+        const storeHandle = await StoreHandle.open(`prune-${name}`, passphrase);
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+        await backUpRoomKeys(machine, "1");
+
+        const result = await machine.pruneInboundGroupSessions({});
+        expect(result.count).toStrictEqual(1);
+        expect(await machine.getInboundGroupSessions(room)).toHaveLength(0);
+        expect((await machine.roomKeyCounts()).total).toStrictEqual(0);
+    });
+
+    test("only supports dry runs on memory stores", async () => {
+        const machine = await OlmMachine.initialize(user, device);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+        await backUpRoomKeys(machine, "1");
+
+        expect((await machine.pruneInboundGroupSessions({ dryRun: true })).count).toStrictEqual(1);
+        await expect(machine.pruneInboundGroupSessions({})).rejects.toThrow();
+    });
+});
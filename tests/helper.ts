@@ -15,6 +15,7 @@ limitations under the License.
 */
 
 import {
+    CryptoStoreAdapter,
     DeviceLists,
     KeysQueryRequest,
     KeysUploadRequest,
@@ -217,3 +218,33 @@ export async function encryptEvent(
         content: JSON.parse(await senderMachine.encryptRoomEvent(room, eventType, eventContent)),
     });
 }
+
+/** A `CryptoStoreAdapter` which keeps its records in a `Map`. */
+export class MapAdapter implements CryptoStoreAdapter {
+    public readonly collections = new Map<string, Map<string, Uint8Array>>();
+
+    private collection(name: string): Map<string, Uint8Array> {
+        let collection = this.collections.get(name);
+        if (!collection) {
+            collection = new Map();
+            this.collections.set(name, collection);
+        }
+        return collection;
+    }
+
+    async get(collection: string, key: string): Promise<Uint8Array | undefined> {
+        return this.collection(collection).get(key);
+    }
+
+    async put(collection: string, key: string, value: Uint8Array): Promise<void> {
+        this.collection(collection).set(key, value);
+    }
+
+    async delete(collection: string, key: string): Promise<void> {
+        this.collection(collection).delete(key);
+    }
+
+    async iterate(collection: string): Promise<Array<[string, Uint8Array]>> {
+        return Array.from(this.collection(collection).entries());
+    }
+}
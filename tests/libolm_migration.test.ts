@@ -27,20 +27,20 @@ import {
     Tracing,
     UserId,
 } from "@matrix-org/matrix-sdk-crypto-wasm";
+import { MapAdapter } from "./helper.ts";
 
 beforeAll(() => {
     new Tracing(LoggerLevel.Trace).turnOn();
 });
 
 describe("Migration", () => {
-    test("It should correctly import data", async () => {
-        const TEST_USER_ID = "@vdhtest200713:matrix.org";
-        const TEST_DEVICE_ID = "KMFSTJSMLB";
+    const TEST_USER_ID = "@vdhtest200713:matrix.org";
+    const TEST_DEVICE_ID = "KMFSTJSMLB";
 
+    /** Migrate a libolm account, its backup key and an Olm session into the given store. */
+    async function migrateTestData(store: StoreHandle): Promise<void> {
         const pickleKey = new TextEncoder().encode("+1k2Ppd7HIisUY824v7JtV3/oEE4yX0TqtmNPyhaD7o");
 
-        const store = await StoreHandle.open("testMigration", "testPass");
-
         const testData = new BaseMigrationData();
         testData.userId = new UserId(TEST_USER_ID);
         testData.deviceId = new DeviceId(TEST_DEVICE_ID);
@@ -59,14 +59,10 @@ describe("Migration", () => {
         session1.senderKey = "1QkuYT/03gzKvMDmKQi5slJvfXECt+ca3/Ue3Cj+Cms";
         session1.lastUseTime = new Date(1703693124932);
         await Migration.migrateOlmSessions([session1], pickleKey, store);
+    }
 
-        // now open an olm machine using the store, and check the data.
-        const olmMachine = await OlmMachine.initialize(
-            new UserId(TEST_USER_ID),
-            new DeviceId(TEST_DEVICE_ID),
-            "testMigration",
-            "testPass",
-        );
+    /** Check the data migrated by `migrateTestData`. */
+    async function checkMigratedData(olmMachine: OlmMachine): Promise<void> {
         expect(olmMachine.identityKeys.curve25519.toBase64()).toEqual("LKv0bKbc0EC4h0jknbemv3QalEkeYvuNeUXVRgVVTTU");
         expect(olmMachine.identityKeys.ed25519.toBase64()).toEqual("qK70DEqIXq7T+UU3v/al47Ab4JkMEBLpNrTBMbS5rrw");
         expect(olmMachine.deviceCreationTimeMs).toEqual(0);
@@ -76,5 +72,32 @@ describe("Migration", () => {
         expect(backupKeys.decryptionKey?.toBase64()).toEqual("/FLbqTHzH1ihmQl3740Dm2aWgOzBng8HjYdGuCpuMLU");
 
         // TODO: figure out a way to test cross-signing key import
+    }
+
+    test("It should correctly import data", async () => {
+        const store = await StoreHandle.open("testMigration", "testPass");
+        await migrateTestData(store);
+
+        // now open an olm machine using the store, and check the data.
+        const olmMachine = await OlmMachine.initialize(
+            new UserId(TEST_USER_ID),
+            new DeviceId(TEST_DEVICE_ID),
+            "testMigration",
+            "testPass",
+        );
+        await checkMigratedData(olmMachine);
+    }, 15000);
+
+    test("It should import data into a custom store", async () => {
+        const adapter = new MapAdapter();
+        await migrateTestData(await StoreHandle.openCustom(adapter));
+
+        // reopen the store from the adapter, so that the data is read back from it.
+        const olmMachine = await OlmMachine.initFromStore(
+            new UserId(TEST_USER_ID),
+            new DeviceId(TEST_DEVICE_ID),
+            await StoreHandle.openCustom(adapter),
+        );
+        await checkMigratedData(olmMachine);
     }, 15000);
 });
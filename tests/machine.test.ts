@@ -14,6 +14,7 @@ import {
     getVersions,
     InboundGroupSession,
     InvalidToDeviceEvent,
+    KeysBackupFetchRequest,
     KeysBackupRequest,
     KeysClaimRequest,
     KeysQueryRequest,
@@ -38,6 +39,7 @@ import {
     ToDeviceRequest,
     TrustRequirement,
     UserId,
+    UtdCause,
     UTDToDeviceEvent,
     VerificationRequest,
     Versions,
@@ -52,7 +54,8 @@ type AnyOutgoingRequest =
     | ToDeviceRequest
     | SignatureUploadRequest
     | RoomMessageRequest
-    | KeysBackupRequest;
+    | KeysBackupRequest
+    | KeysBackupFetchRequest;
 
 afterEach(() => {
     // reset fake-indexeddb after each test, to make sure we don't leak data
@@ -337,6 +340,16 @@ describe(OlmMachine.name, () => {
         expect(receiveSyncChanges).toEqual([]);
     });
 
+    test("can receive sync changes as objects", async () => {
+        const m = await machine();
+        const toDeviceEvents = [{ sender: "@alice:example.com", type: "custom.type", content: { value: "foo" } }];
+
+        const processed = await m.receiveSyncChangesObject(toDeviceEvents, new DeviceLists(), new Map(), undefined);
+        expect(processed).toHaveLength(1);
+        expect(processed[0]).toBeInstanceOf(PlainTextToDeviceEvent);
+        expect(JSON.parse(processed[0].rawEvent)).toStrictEqual(toDeviceEvents[0]);
+    });
+
     test("can get the outgoing requests that need to be sent out", async () => {
         const m = await machine();
         const toDeviceEvents = JSON.stringify([]);
@@ -365,6 +378,7 @@ describe(OlmMachine.name, () => {
             const body = JSON.parse(outgoingRequests[0].body);
             expect(body.device_keys).toBeDefined();
             expect(body.one_time_keys).toBeDefined();
+            expect(outgoingRequests[0].bodyObject).toStrictEqual(body);
         }
 
         {
@@ -722,6 +736,92 @@ describe(OlmMachine.name, () => {
             expect(decryptionInfo.shieldState(false)?.color).toStrictEqual(ShieldColor.Red);
             expect(decryptionInfo.shieldState(false)?.code).toStrictEqual(ShieldStateCode.UnsignedDevice);
         });
+
+        test("can decrypt a batch of events", async () => {
+            const content = JSON.parse(
+                await m.encryptRoomEvent(room, "m.room.message", JSON.stringify({ msgtype: "m.text", body: "Hi" })),
+            );
+            const event = (content: object) =>
+                JSON.stringify({
+                    type: "m.room.encrypted",
+                    event_id: "$xxxxx:example.org",
+                    origin_server_ts: Date.now(),
+                    sender: user.toString(),
+                    content,
+                });
+            const unknownSession = { ...content, session_id: "unknown" };
+
+            const decryptionSettings = new DecryptionSettings(TrustRequirement.Untrusted);
+            const results = await m.decryptRoomEvents(
+                [event(content), event(unknownSession), "not json", event(unknownSession)],
+                room,
+                decryptionSettings,
+            );
+
+            expect(results).toHaveLength(4);
+            expect(results[0]).toBeInstanceOf(DecryptedRoomEvent);
+            expect(JSON.parse((results[0] as DecryptedRoomEvent).event).content.body).toStrictEqual("Hi");
+            expect(results[1]).toBeInstanceOf(MegolmDecryptionError);
+            expect((results[1] as MegolmDecryptionError).code).toStrictEqual(DecryptionErrorCode.MissingRoomKey);
+            expect((results[2] as MegolmDecryptionError).code).toStrictEqual(DecryptionErrorCode.UnableToDecrypt);
+            expect((results[3] as MegolmDecryptionError).code).toStrictEqual(DecryptionErrorCode.MissingRoomKey);
+        });
+
+        test("can decrypt a batch of events of the same session in any order", async () => {
+            const encrypt = async (body: string) =>
+                JSON.stringify({
+                    type: "m.room.encrypted",
+                    event_id: `$${body}:example.org`,
+                    origin_server_ts: Date.now(),
+                    sender: user.toString(),
+                    content: JSON.parse(
+                        await m.encryptRoomEvent(room, "m.room.message", JSON.stringify({ msgtype: "m.text", body })),
+                    ),
+                });
+            const first = await encrypt("first");
+            const second = await encrypt("second");
+
+            const decryptionSettings = new DecryptionSettings(TrustRequirement.Untrusted);
+            const results = await m.decryptRoomEvents([second, first, second], room, decryptionSettings);
+
+            const bodies = results.map((result) => JSON.parse((result as DecryptedRoomEvent).event).content.body);
+            expect(bodies).toStrictEqual(["second", "first", "second"]);
+        });
+
+        test("can encrypt and decrypt events as objects", async () => {
+            const content = await m.encryptRoomEventObject(room, "m.room.message", { msgtype: "m.text", body: "Hi" });
+            expect(content.algorithm).toStrictEqual("m.megolm.v1.aes-sha2");
+            expect(content.ciphertext).toBeDefined();
+
+            const event = {
+                type: "m.room.encrypted",
+                event_id: "$object:example.org",
+                origin_server_ts: Date.now(),
+                sender: user.toString(),
+                content,
+            };
+            const decryptionSettings = new DecryptionSettings(TrustRequirement.Untrusted);
+            const decrypted = await m.decryptRoomEventObject(event, room, decryptionSettings);
+            expect(decrypted).toBeInstanceOf(DecryptedRoomEvent);
+            expect(decrypted.eventObject.content).toStrictEqual({ msgtype: "m.text", body: "Hi" });
+            expect(decrypted.eventObject.origin_server_ts).toStrictEqual(event.origin_server_ts);
+            expect(decrypted.eventObject).toBe(decrypted.eventObject);
+
+            const results = await m.decryptRoomEventsObject(
+                [event, { content: Symbol("not JSON") }],
+                room,
+                decryptionSettings,
+            );
+            expect(results).toHaveLength(2);
+            expect((results[0] as DecryptedRoomEvent).eventObject.content).toStrictEqual({
+                msgtype: "m.text",
+                body: "Hi",
+            });
+            expect((results[1] as MegolmDecryptionError).code).toStrictEqual(DecryptionErrorCode.UnableToDecrypt);
+
+            const stateEvent = await m.encryptStateEventObject(room, "m.room.topic", "", { topic: "Objects" });
+            expect(stateEvent.ciphertext).toBeDefined();
+        });
     });
 
     test("failure to decrypt returns a valid error", async () => {
@@ -746,6 +846,84 @@ describe(OlmMachine.name, () => {
         }
     });
 
+    test("decrypts undecryptable events once their room key arrives", async () => {
+        const sender = await machine(new UserId("@bob:example.org"), new DeviceId("BOBDEVICE"));
+        await sender.shareRoomKey(room, [], new EncryptionSettings());
+        const content = await sender.encryptRoomEvent(
+            room,
+            "m.room.message",
+            JSON.stringify({ msgtype: "m.text", body: "Hello, later!" }),
+        );
+        const roomKeys = await sender.exportRoomKeys(() => true);
+
+        const m = await machine();
+        const callback = jest.fn().mockImplementation(() => Promise.resolve(undefined));
+        m.registerEventsDecryptedCallback(callback);
+
+        const event = JSON.stringify({
+            type: "m.room.encrypted",
+            event_id: "$event:example.org",
+            origin_server_ts: Date.now(),
+            sender: "@bob:example.org",
+            content: JSON.parse(content),
+        });
+        const decryptionSettings = new DecryptionSettings(TrustRequirement.Untrusted);
+        await expect(m.decryptRoomEvent(event, room, decryptionSettings)).rejects.toBeInstanceOf(
+            MegolmDecryptionError,
+        );
+
+        await m.importExportedRoomKeys(roomKeys, () => undefined);
+
+        // The events are decrypted in the background.
+        await new Promise((resolve) => setTimeout(resolve, 100));
+        expect(callback).toHaveBeenCalledTimes(1);
+
+        const [roomId, events] = callback.mock.calls[0];
+        expect(roomId.toString()).toStrictEqual(room.toString());
+        expect(events).toHaveLength(1);
+        expect(JSON.parse(events[0].event).content.body).toStrictEqual("Hello, later!");
+    });
+
+    test("can classify decryption failures", async () => {
+        const sender = await machine(new UserId("@bob:example.org"), new DeviceId("BOBDEVICE"));
+        await sender.shareRoomKey(room, [], new EncryptionSettings());
+        const content = JSON.parse(await sender.encryptRoomEvent(room, "m.room.message", JSON.stringify({})));
+
+        const m = await machine();
+        const event = (timestamp: number) =>
+            JSON.stringify({
+                type: "m.room.encrypted",
+                event_id: "$event:example.org",
+                origin_server_ts: timestamp,
+                sender: "@bob:example.org",
+                content,
+            });
+
+        const decryptionSettings = new DecryptionSettings(TrustRequirement.Untrusted);
+        let error: MegolmDecryptionError | undefined;
+        try {
+            await m.decryptRoomEvent(event(Date.now()), room, decryptionSettings);
+        } catch (err) {
+            error = err as MegolmDecryptionError;
+        }
+        expect(error).toBeInstanceOf(MegolmDecryptionError);
+
+        const recent = m.classifyDecryptionError(event(Date.now()), error!);
+        expect(recent.cause).toStrictEqual(UtdCause.Unknown);
+        expect(recent.isExpected).toBe(false);
+        expect(recent.code).toStrictEqual(DecryptionErrorCode.MissingRoomKey);
+        expect(recent.sessionId).toStrictEqual(content.session_id);
+        expect(recent.deviceCreationTimeMs).toStrictEqual(m.deviceCreationTimeMs);
+
+        const old = m.classifyDecryptionError(event(m.deviceCreationTimeMs - 1000), error!);
+        expect(old.cause).toStrictEqual(UtdCause.SentBeforeDeviceCreation);
+        expect(old.isExpected).toBe(true);
+        expect(old.eventTimestampMs).toStrictEqual(m.deviceCreationTimeMs - 1000);
+
+        const beforeJoin = m.classifyDecryptionError(event(Date.now()), error!, Date.now() + 1000);
+        expect(beforeJoin.cause).toStrictEqual(UtdCause.SentBeforeWeJoined);
+    });
+
     test("can read cross-signing status", async () => {
         const m = await machine();
         const crossSigningStatus = await m.crossSigningStatus();
@@ -992,6 +1170,33 @@ describe(OlmMachine.name, () => {
         });
     });
 
+    test("can get the inbound group sessions of a room", async () => {
+        let m = await machine();
+        const before = Date.now();
+        await m.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+        // The time at which the session was stored is recorded in the background.
+        let sessions = await m.getInboundGroupSessions(room);
+        while (sessions[0]?.storedAt === undefined) {
+            await new Promise((resolve) => setTimeout(resolve, 10));
+            sessions = await m.getInboundGroupSessions(room);
+        }
+        expect(sessions).toHaveLength(1);
+
+        const session = sessions[0];
+        expect(session).toBeInstanceOf(InboundGroupSession);
+        expect(session.roomId.toString()).toStrictEqual(room.toString());
+        expect(session.firstKnownIndex).toStrictEqual(0);
+        expect(session.algorithm).toStrictEqual(EncryptionAlgorithm.MegolmV1AesSha2);
+        expect(session.senderClaimedEd25519Key?.toBase64()).toStrictEqual(m.identityKeys.ed25519.toBase64());
+        expect(session.backedUp).toStrictEqual(false);
+        expect(session.storedAt).toBeGreaterThanOrEqual(before);
+        expect(session.storedAt).toBeLessThanOrEqual(Date.now());
+        expect(session.hasBeenImported()).toStrictEqual(false);
+
+        expect(await m.getInboundGroupSessions(new RoomId("!other:example.org"))).toHaveLength(0);
+    });
+
     describe("can process exported room keys", () => {
         let exportedRoomKeys: string;
 
@@ -1409,6 +1614,52 @@ describe(OlmMachine.name, () => {
             expect(newCounts.backedUp).toStrictEqual(1);
         });
 
+        test("re-uploads all room keys after resetting the backup state", async () => {
+            const m = await machine();
+            await m.shareRoomKey(room, [new UserId("@bob:example.org")], new EncryptionSettings());
+
+            const oldKey = BackupDecryptionKey.createRandomKey();
+            await m.enableBackupV1(oldKey.megolmV1PublicKey.publicKeyBase64, "1");
+            const oldRequest = (await m.backupRoomKeys())!;
+            await m.markRequestAsSent(oldRequest.id, oldRequest.type, '{"etag":"1","count":1}');
+            expect((await m.roomKeyCounts()).backedUp).toStrictEqual(1);
+
+            const newKey = BackupDecryptionKey.createRandomKey();
+            await m.saveBackupDecryptionKey(newKey, "1");
+            await m.resetBackupState("2", newKey.megolmV1PublicKey.publicKeyBase64);
+
+            expect(await m.isBackupEnabled()).toStrictEqual(true);
+            expect((await m.getBackupKeys()).backupVersion).toStrictEqual("2");
+            expect((await m.roomKeyCounts()).backedUp).toStrictEqual(0);
+
+            const progressListener = jest.fn();
+            const request = (await m.backupRoomKeys(progressListener))!;
+            expect(request.version).toStrictEqual("2");
+            expect(progressListener).not.toHaveBeenCalled();
+
+            // the room key is encrypted with the new key
+            const sessions = JSON.parse(request.body).rooms[room.toString()].sessions;
+            const sessionData = (Object.values(sessions)[0] as any).session_data;
+            const decrypted = JSON.parse(
+                newKey.decryptV1(sessionData.ephemeral, sessionData.mac, sessionData.ciphertext),
+            );
+            expect(decrypted.algorithm).toStrictEqual("m.megolm.v1.aes-sha2");
+
+            await m.markRequestAsSent(request.id, request.type, '{"etag":"2","count":1}');
+            expect(progressListener).toHaveBeenCalledTimes(1);
+            expect(progressListener).toHaveBeenLastCalledWith(1, 1);
+
+            expect(await m.backupRoomKeys(progressListener)).toBeUndefined();
+            expect(progressListener).toHaveBeenCalledTimes(2);
+            expect(progressListener).toHaveBeenLastCalledWith(1, 1);
+
+            await expect(
+                m.backupRoomKeys(() => {
+                    throw new Error("listener failed");
+                }),
+            ).rejects.toThrow("listener failed");
+        });
+
         test("can save and get private key", async () => {
             let m = await machine();
 
@@ -1472,6 +1723,88 @@ describe(OlmMachine.name, () => {
             expect(progressListener).toHaveBeenCalledWith(0, 2, 1);
         });
 
+        test("fetches missing room keys from the backup", async () => {
+            // back up the room key of an event
+            const sender = await machine(new UserId("@bob:example.org"), new DeviceId("BOBDEVICE"));
+            await sender.shareRoomKey(room, [], new EncryptionSettings());
+            const content = JSON.parse(
+                await sender.encryptRoomEvent(room, "m.room.message", JSON.stringify({ msgtype: "m.text", body: "Hi" })),
+            );
+            const keyBackupKey = BackupDecryptionKey.createRandomKey();
+            await sender.enableBackupV1(keyBackupKey.megolmV1PublicKey.publicKeyBase64, "1");
+            const backup = JSON.parse((await sender.backupRoomKeys())!.body);
+            const keyBackupData = backup.rooms[room.toString()].sessions[content.session_id];
+
+            const m = await machine();
+            await m.saveBackupDecryptionKey(keyBackupKey, "1");
+
+            const event = JSON.stringify({
+                type: "m.room.encrypted",
+                event_id: "$event:example.org",
+                origin_server_ts: Date.now(),
+                sender: "@bob:example.org",
+                content,
+            });
+            const decryptionSettings = new DecryptionSettings(TrustRequirement.Untrusted);
+            const fetchRequests = async () =>
+                (await m.outgoingRequests()).filter((request) => request instanceof KeysBackupFetchRequest);
+
+            await expect(m.decryptRoomEvent(event, room, decryptionSettings)).rejects.toBeInstanceOf(
+                MegolmDecryptionError,
+            );
+
+            const requests = await fetchRequests();
+            expect(requests).toHaveLength(1);
+            const request = requests[0] as KeysBackupFetchRequest;
+            expect(request.type).toStrictEqual(RequestType.KeysBackupFetch);
+            expect(request.roomId).toStrictEqual(room.toString());
+            expect(request.sessionId).toStrictEqual(content.session_id);
+            expect(request.version).toStrictEqual("1");
+
+            // the room key isn't requested again while the request is in flight
+            await expect(m.decryptRoomEvent(event, room, decryptionSettings)).rejects.toBeInstanceOf(
+                MegolmDecryptionError,
+            );
+            expect(await fetchRequests()).toHaveLength(0);
+
+            const response = JSON.stringify(keyBackupData);
+            expect(await m.markRequestAsSent(request.id, request.type, response)).toStrictEqual(true);
+
+            const decrypted = await m.decryptRoomEvent(event, room, decryptionSettings);
+            expect(JSON.parse(decrypted.event).content.body).toStrictEqual("Hi");
+            expect((await m.roomKeyCounts()).backedUp).toStrictEqual(1);
+        });
+
+        test("ignores missing room keys which are not in the backup", async () => {
+            const sender = await machine(new UserId("@bob:example.org"), new DeviceId("BOBDEVICE"));
+            await sender.shareRoomKey(room, [], new EncryptionSettings());
+            const content = JSON.parse(await sender.encryptRoomEvent(room, "m.room.message", JSON.stringify({})));
+
+            const m = await machine();
+            await m.saveBackupDecryptionKey(BackupDecryptionKey.createRandomKey(), "1");
+
+            const event = JSON.stringify({
+                type: "m.room.encrypted",
+                event_id: "$event:example.org",
+                origin_server_ts: Date.now(),
+                sender: "@bob:example.org",
+                content,
+            });
+            const decryptionSettings = new DecryptionSettings(TrustRequirement.Untrusted);
+            await expect(m.decryptRoomEvent(event, room, decryptionSettings)).rejects.toBeInstanceOf(
+                MegolmDecryptionError,
+            );
+
+            const request = (await m.outgoingRequests()).find(
+                (request) => request instanceof KeysBackupFetchRequest,
+            ) as KeysBackupFetchRequest;
+            expect(request.sessionId).toStrictEqual(content.session_id);
+
+            const notFound = JSON.stringify({ errcode: "M_NOT_FOUND", error: "No room key found" });
+            expect(await m.markRequestAsSent(request.id, request.type, notFound)).toStrictEqual(true);
+            expect((await m.roomKeyCounts()).total).toStrictEqual(0);
+        });
+
         test("can check and set if all room keys have been downloaded", async () => {
             const m = await machine();
             await expect(m.hasDownloadedAllRoomKeys(room)).resolves.toBe(false);
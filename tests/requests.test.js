@@ -18,5 +18,6 @@ describe("RequestType", () => {
         expect(RequestType.SignatureUpload).toStrictEqual(4);
         expect(RequestType.RoomMessage).toStrictEqual(5);
         expect(RequestType.KeysBackup).toStrictEqual(6);
+        expect(RequestType.KeysBackupFetch).toStrictEqual(7);
     });
 });
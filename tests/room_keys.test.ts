@@ -0,0 +1,174 @@
+/*
+Copyright 2026 The Matrix.org Foundation C.I.C.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+import {
+    CollectStrategy,
+    DeviceId,
+    EncryptionSettings,
+    HistoryVisibility,
+    KeysClaimRequest,
+    KeysQueryRequest,
+    KeysUploadRequest,
+    OlmMachine,
+    RequestType,
+    RoomId,
+    RoomKeyWithheldReason,
+    ToDeviceRequest,
+    UserId,
+} from "@matrix-org/matrix-sdk-crypto-wasm";
+import { addMachineToMachine, establishOlmSession } from "./helper.ts";
+
+const room = new RoomId("!room:example.org");
+
+describe("OlmMachine.getOutboundGroupSessionInfo", () => {
+    let alice: OlmMachine;
+    let bob: OlmMachine;
+
+    beforeEach(async () => {
+        alice = await OlmMachine.initialize(new UserId("@alice:example.org"), new DeviceId("ALICE"));
+        bob = await OlmMachine.initialize(new UserId("@bob:example.org"), new DeviceId("BOB"));
+        await addMachineToMachine(bob, alice);
+        await addMachineToMachine(alice, bob);
+    });
+
+    test("returns undefined without a session", async () => {
+        expect(await alice.getOutboundGroupSessionInfo(room, new EncryptionSettings())).toBeUndefined();
+    });
+
+    test("describes the current session", async () => {
+        await establishOlmSession(alice, bob);
+        const before = Date.now();
+        const requests = await alice.shareRoomKey(room, [bob.userId], new EncryptionSettings());
+        for (const request of requests) {
+            await alice.markRequestAsSent(request.id, RequestType.ToDevice, "{}");
+        }
+
+        const info = (await alice.getOutboundGroupSessionInfo(room, new EncryptionSettings()))!;
+        expect(info.sessionId).toBeDefined();
+        expect(info.creationTimeMs).toBeGreaterThanOrEqual(Math.floor(before / 1000) * 1000);
+        expect(info.messageCount).toStrictEqual(0);
+        expect(info.shared).toBe(true);
+        expect(info.invalidated).toBe(false);
+        expect(info.rotationDue).toBe(false);
+        expect(info.sharedWith().get("@bob:example.org")?.get("BOB")).toStrictEqual(0);
+        expect(info.withheldFrom().size).toStrictEqual(0);
+
+        const settings = new EncryptionSettings();
+        settings.historyVisibility = HistoryVisibility.Joined;
+        expect((await alice.getOutboundGroupSessionInfo(room, settings))!.rotationDue).toBe(true);
+    });
+});
+
+describe("OlmMachine.planRoomKeyShare", () => {
+    let alice: OlmMachine;
+    let bob: OlmMachine;
+    let carol: OlmMachine;
+
+    beforeEach(async () => {
+        alice = await OlmMachine.initialize(new UserId("@alice:example.org"), new DeviceId("ALICE"));
+        bob = await OlmMachine.initialize(new UserId("@bob:example.org"), new DeviceId("BOB"));
+        carol = await OlmMachine.initialize(new UserId("@carol:example.org"), new DeviceId("CAROL"));
+        await addMachineToMachine(bob, alice);
+        await addMachineToMachine(alice, bob);
+        await addMachineToMachine(carol, alice);
+        await establishOlmSession(alice, bob);
+    });
+
+    test("lists the recipients and the withheld devices", async () => {
+        const plan = await alice.planRoomKeyShare(room, [bob.userId, carol.userId], new EncryptionSettings());
+
+        expect(plan.wouldFail).toBe(false);
+        expect(plan.recipients().get("@bob:example.org")).toStrictEqual(["BOB"]);
+        expect(plan.recipients().has("@carol:example.org")).toBe(false);
+        expect(plan.withheld().get("@carol:example.org")?.get("CAROL")).toStrictEqual(
+            RoomKeyWithheldReason.NoOlmSession,
+        );
+    });
+
+    test("applies the collect strategy", async () => {
+        const settings = new EncryptionSettings();
+        settings.sharingStrategy = CollectStrategy.onlyTrustedDevices();
+        const plan = await alice.planRoomKeyShare(room, [bob.userId], settings);
+
+        expect(plan.recipients().size).toStrictEqual(0);
+        expect(plan.withheld().get("@bob:example.org")?.get("BOB")).toStrictEqual(RoomKeyWithheldReason.Unverified);
+    });
+
+    test("fails with the identity-based strategy without cross-signing", async () => {
+        const settings = new EncryptionSettings();
+        settings.sharingStrategy = CollectStrategy.identityBasedStrategy();
+        const plan = await alice.planRoomKeyShare(room, [bob.userId], settings);
+
+        expect(plan.wouldFail).toBe(true);
+        expect(plan.withheld().get("@bob:example.org")?.get("BOB")).toStrictEqual(RoomKeyWithheldReason.Unverified);
+    });
+
+    test("doesn't create a session", async () => {
+        await alice.planRoomKeyShare(room, [bob.userId], new EncryptionSettings());
+
+        expect(await alice.getOutboundGroupSessionInfo(room, new EncryptionSettings())).toBeUndefined();
+    });
+});
+
+describe("OlmMachine.prepareRoomForEncryption", () => {
+    let alice: OlmMachine;
+    let bob: OlmMachine;
+    let bobKeys: any;
+
+    beforeEach(async () => {
+        alice = await OlmMachine.initialize(new UserId("@alice:example.org"), new DeviceId("ALICE"));
+        bob = await OlmMachine.initialize(new UserId("@bob:example.org"), new DeviceId("BOB"));
+
+        const outgoing = await bob.outgoingRequests();
+        bobKeys = JSON.parse(outgoing.find((request) => request instanceof KeysUploadRequest)!.body);
+    });
+
+    test("shares the room key, sending the requests with the transport", async () => {
+        const sent: Array<RequestType> = [];
+        const transport = async (request: any): Promise<string> => {
+            sent.push(request.type);
+
+            if (request instanceof KeysQueryRequest) {
+                return JSON.stringify({ device_keys: { "@bob:example.org": { BOB: bobKeys.device_keys } } });
+            } else if (request instanceof KeysClaimRequest) {
+                const [keyId, key] = Object.entries(bobKeys.one_time_keys)[0];
+                return JSON.stringify({ one_time_keys: { "@bob:example.org": { BOB: { [keyId]: key } } } });
+            } else if (request instanceof ToDeviceRequest) {
+                return "{}";
+            }
+
+            throw new Error(`Unexpected request ${request.type}`);
+        };
+
+        // The outgoing requests of Alice are never processed.
+        await alice.prepareRoomForEncryption(room, [alice.userId, bob.userId], new EncryptionSettings(), transport);
+
+        expect(sent).toStrictEqual([RequestType.KeysQuery, RequestType.KeysClaim, RequestType.ToDevice]);
+        const info = (await alice.getOutboundGroupSessionInfo(room, new EncryptionSettings()))!;
+        expect(info.shared).toBe(true);
+        expect(info.sharedWith().get("@bob:example.org")?.has("BOB")).toBe(true);
+    });
+
+    test("rejects with the error of the transport", async () => {
+        const transport = async (): Promise<string> => {
+            throw new Error("Offline");
+        };
+
+        await expect(
+            alice.prepareRoomForEncryption(room, [bob.userId], new EncryptionSettings(), transport),
+        ).rejects.toThrow("Offline");
+    });
+});
@@ -0,0 +1,155 @@
+/*
+Copyright 2026 The Matrix.org Foundation C.I.C.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+import {
+    DeviceId,
+    OlmMachine,
+    SecretStorageKey,
+    SignatureUploadRequest,
+    UserId,
+} from "@matrix-org/matrix-sdk-crypto-wasm";
+
+describe("SecretStorageKey", () => {
+    test("describes a new key", () => {
+        const key = SecretStorageKey.createRandomKey();
+
+        expect(key.eventType).toStrictEqual(`m.secret_storage.key.${key.keyId}`);
+        expect(JSON.parse(key.eventContent)).toMatchObject({
+            algorithm: "m.secret_storage.v1.aes-hmac-sha2",
+            iv: expect.any(String),
+            mac: expect.any(String),
+        });
+    });
+
+    test("can be imported with its recovery key", () => {
+        const key = SecretStorageKey.createRandomKey();
+
+        const imported = SecretStorageKey.fromAccountData(key.toRecoveryKey(), key.keyId, key.eventContent);
+        expect(imported.keyId).toStrictEqual(key.keyId);
+        expect(imported.toRecoveryKey()).toStrictEqual(key.toRecoveryKey());
+    });
+
+    test("can be imported with its passphrase", () => {
+        const key = SecretStorageKey.createFromPassphrase("correct horse battery staple");
+        expect(JSON.parse(key.eventContent).passphrase.algorithm).toStrictEqual("m.pbkdf2");
+
+        const imported = SecretStorageKey.fromAccountData("correct horse battery staple", key.keyId, key.eventContent);
+        expect(imported.toRecoveryKey()).toStrictEqual(key.toRecoveryKey());
+    });
+
+    test("checks the MAC of the description", () => {
+        const key = SecretStorageKey.createRandomKey();
+        const otherKey = SecretStorageKey.createRandomKey();
+
+        expect(() => SecretStorageKey.fromAccountData(otherKey.toRecoveryKey(), key.keyId, key.eventContent)).toThrow();
+    });
+
+    test("encrypts and decrypts secrets", () => {
+        const key = SecretStorageKey.createRandomKey();
+
+        const content = key.encryptSecret("m.megolm_backup.v1", "bXlzZWNyZXQ");
+        expect(Object.keys(JSON.parse(content).encrypted)).toStrictEqual([key.keyId]);
+        expect(key.decryptSecret("m.megolm_backup.v1", content)).toStrictEqual("bXlzZWNyZXQ");
+
+        // The name of the secret is part of the MAC.
+        expect(() => key.decryptSecret("m.cross_signing.master", content)).toThrow();
+    });
+
+    test("adds secrets to the existing content", () => {
+        const key = SecretStorageKey.createRandomKey();
+        const otherKey = SecretStorageKey.createRandomKey();
+
+        const content = otherKey.encryptSecret(
+            "org.example.custom",
+            "my secret",
+            key.encryptSecret("org.example.custom", "my secret"),
+        );
+
+        expect(key.decryptSecret("org.example.custom", content)).toStrictEqual("my secret");
+        expect(otherKey.decryptSecret("org.example.custom", content)).toStrictEqual("my secret");
+        expect(() =>
+            SecretStorageKey.createRandomKey().decryptSecret("org.example.custom", content),
+        ).toThrow("not encrypted with the key");
+    });
+});
+
+describe("OlmMachine recovery", () => {
+    const user = new UserId("@alice:example.org");
+
+    test("sets up recovery", async () => {
+        const machine = await OlmMachine.initialize(user, new DeviceId("ALICE"));
+        const setup = await machine.setupRecovery();
+
+        const accountData = setup.accountData();
+        const keyId = JSON.parse(accountData.get("m.secret_storage.default_key")!).key;
+        expect([...accountData.keys()].sort()).toStrictEqual([
+            "m.cross_signing.master",
+            "m.cross_signing.self_signing",
+            "m.cross_signing.user_signing",
+            "m.megolm_backup.v1",
+            "m.secret_storage.default_key",
+            `m.secret_storage.key.${keyId}`,
+        ]);
+
+        const key = SecretStorageKey.fromAccountData(
+            setup.recoveryKey,
+            keyId,
+            accountData.get(`m.secret_storage.key.${keyId}`)!,
+        );
+        expect(key.decryptSecret("m.megolm_backup.v1", accountData.get("m.megolm_backup.v1")!)).toStrictEqual(
+            setup.backupDecryptionKey.toBase64(),
+        );
+
+        const backupVersion = JSON.parse(setup.backupVersionBody);
+        expect(backupVersion.algorithm).toStrictEqual("m.megolm_backup.v1.curve25519-aes-sha2");
+        expect(backupVersion.auth_data.public_key).toStrictEqual(
+            setup.backupDecryptionKey.megolmV1PublicKey.publicKeyBase64,
+        );
+        expect(backupVersion.auth_data.signatures["@alice:example.org"]).toBeDefined();
+
+        // The backup key is only saved once the backup version is created.
+        const backupKeys = await machine.getBackupKeys();
+        expect(backupKeys.decryptionKey).toBeUndefined();
+    });
+
+    test("recovers on another device", async () => {
+        const machine = await OlmMachine.initialize(user, new DeviceId("ALICE"));
+        const setup = await machine.setupRecovery("correct horse battery staple");
+
+        const otherMachine = await OlmMachine.initialize(user, new DeviceId("OTHER"));
+        const request = await otherMachine.recover("correct horse battery staple", setup.accountData(), "1");
+        expect(request).toBeInstanceOf(SignatureUploadRequest);
+
+        const status = await otherMachine.crossSigningStatus();
+        expect(status.hasMaster).toStrictEqual(true);
+        expect(status.hasSelfSigning).toStrictEqual(true);
+        expect(status.hasUserSigning).toStrictEqual(true);
+
+        const backupKeys = await otherMachine.getBackupKeys();
+        expect(backupKeys.decryptionKey?.toBase64()).toStrictEqual(setup.backupDecryptionKey.toBase64());
+        expect(backupKeys.backupVersion).toStrictEqual("1");
+    });
+
+    test("rejects the wrong recovery key", async () => {
+        const machine = await OlmMachine.initialize(user, new DeviceId("ALICE"));
+        const setup = await machine.setupRecovery();
+
+        const otherMachine = await OlmMachine.initialize(user, new DeviceId("OTHER"));
+        expect(() =>
+            otherMachine.recover(SecretStorageKey.createRandomKey().toRecoveryKey(), setup.accountData()),
+        ).toThrow();
+    });
+});
@@ -0,0 +1,424 @@
+/*
+Copyright 2026 The Matrix.org Foundation C.I.C.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+import "fake-indexeddb/auto";
+import {
+    DeviceId,
+    EncryptionSettings,
+    OlmMachine,
+    RoomId,
+    RoomSettings,
+    StoreHandle,
+    UserId,
+} from "@matrix-org/matrix-sdk-crypto-wasm";
+import * as crypto from "node:crypto";
+
+import { MapAdapter } from "./helper.ts";
+
+afterEach(() => {
+    // reset fake-indexeddb after each test, to make sure we don't leak data
+    // eslint-disable-next-line no-global-assign
+    indexedDB = new IDBFactory();
+});
+
+jest.setTimeout(15000);
+
+const user = new UserId("@alice:example.org");
+const device = new DeviceId("ALICEDEVICE");
+const room = new RoomId("!room:example.org");
+
+describe("StoreHandle snapshots", () => {
+    /** Read every record of every object store of an IndexedDB database. */
+    async function dumpDatabase(name: string): Promise<Record<string, [IDBValidKey[], unknown[]]>> {
+        const result = <T>(request: IDBRequest<T>) =>
+            new Promise<T>((resolve, reject) => {
+                request.onsuccess = () => resolve(request.result);
+                request.onerror = () => reject(request.error);
+            });
+
+        const db = await result(indexedDB.open(name));
+        const dump: Record<string, [IDBValidKey[], unknown[]]> = {};
+        for (const storeName of Array.from(db.objectStoreNames)) {
+            const objectStore = db.transaction(storeName).objectStore(storeName);
+            dump[storeName] = [await result(objectStore.getAllKeys()), await result(objectStore.getAll())];
+        }
+        db.close();
+        return dump;
+    }
+
+    /** Read both databases of a store, leaving out the store cipher, which is protected by the store passphrase. */
+    async function dumpStore(storeName: string) {
+        const meta = await dumpDatabase(`${storeName}::matrix-sdk-crypto-meta`);
+        const [keys, values] = meta["matrix-sdk-crypto"];
+        const cipherIndex = keys.indexOf("store_cipher");
+        expect(cipherIndex).not.toEqual(-1);
+        keys.splice(cipherIndex, 1);
+        values.splice(cipherIndex, 1);
+
+        return { data: await dumpDatabase(`${storeName}::matrix-sdk-crypto`), meta };
+    }
+
+    /** The non-empty collections of an adapter. */
+    function nonEmptyCollections(adapter: MapAdapter): Map<string, Map<string, Uint8Array>> {
+        return new Map(Array.from(adapter.collections.entries()).filter(([, records]) => records.size > 0));
+    }
+
+    test("can be exported and imported into a new store", async () => {
+        const storeHandle = await StoreHandle.open("snapshotSource", "sourcePassphrase");
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        // sharing a room key creates an inbound group session for our own device
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+        const snapshot = await storeHandle.exportSnapshot("snapshotPassphrase");
+        expect(snapshot).toBeInstanceOf(Uint8Array);
+
+        // Bytes are encoded as base64 rather than as arrays of numbers.
+        const envelope = JSON.parse(new TextDecoder().decode(snapshot));
+        expect(typeof envelope.cipher).toEqual("string");
+        expect(typeof envelope.data.ciphertext).toEqual("string");
+
+        const restored = await StoreHandle.importSnapshot(
+            snapshot,
+            "snapshotPassphrase",
+            "snapshotTarget",
+            "targetPassphrase",
+        );
+        const restoredMachine = await OlmMachine.initFromStore(user, device, restored);
+        expect(restoredMachine.identityKeys.ed25519.toBase64()).toEqual(machine.identityKeys.ed25519.toBase64());
+        expect(restoredMachine.identityKeys.curve25519.toBase64()).toEqual(
+            machine.identityKeys.curve25519.toBase64(),
+        );
+        expect((await restoredMachine.roomKeyCounts()).total).toEqual(1);
+    });
+
+    test("copies every record of an IndexedDB-based store", async () => {
+        const storeHandle = await StoreHandle.open("snapshotSource", "sourcePassphrase");
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+        await machine.setRoomSettings(room, new RoomSettings());
+        await machine.outgoingRequests();
+        await machine.close();
+
+        const snapshot = await storeHandle.exportSnapshot("snapshotPassphrase");
+        const restored = await StoreHandle.importSnapshot(
+            snapshot,
+            "snapshotPassphrase",
+            "snapshotTarget",
+            "targetPassphrase",
+        );
+        restored.free();
+
+        expect(await dumpStore("snapshotTarget")).toEqual(await dumpStore("snapshotSource"));
+    });
+
+    test("copies every record of a custom store", async () => {
+        const adapter = new MapAdapter();
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+        await machine.setRoomSettings(room, new RoomSettings());
+        await machine.outgoingRequests();
+        await machine.close();
+
+        const snapshot = await storeHandle.exportSnapshot("snapshotPassphrase");
+        const target = new MapAdapter();
+        const restored = await StoreHandle.importCustomSnapshot(snapshot, "snapshotPassphrase", target);
+
+        expect(nonEmptyCollections(target)).toEqual(nonEmptyCollections(adapter));
+        const restoredMachine = await OlmMachine.initFromStore(user, device, restored);
+        expect((await restoredMachine.roomKeyCounts()).total).toEqual(1);
+    });
+
+    test("cannot be imported with the wrong passphrase", async () => {
+        const storeHandle = await StoreHandle.open("snapshotSource", "sourcePassphrase");
+        await OlmMachine.initFromStore(user, device, storeHandle);
+        const snapshot = await storeHandle.exportSnapshot("snapshotPassphrase");
+
+        await expect(
+            StoreHandle.importSnapshot(snapshot, "wrongPassphrase", "snapshotTarget", "targetPassphrase"),
+        ).rejects.toThrow("wrong passphrase");
+    });
+
+    test("cannot be exported from a memory store", async () => {
+        const storeHandle = await StoreHandle.open();
+        await OlmMachine.initFromStore(user, device, storeHandle);
+
+        await expect(storeHandle.exportSnapshot("snapshotPassphrase")).rejects.toThrow("memory stores");
+    });
+
+    test("refuses to overwrite an existing store", async () => {
+        const storeHandle = await StoreHandle.open("snapshotSource", "sourcePassphrase");
+        await OlmMachine.initFromStore(user, device, storeHandle);
+        const snapshot = await storeHandle.exportSnapshot("snapshotPassphrase");
+
+        const target = await StoreHandle.open("snapshotTarget", "targetPassphrase");
+        const targetMachine = await OlmMachine.initFromStore(user, new DeviceId("OTHERDEVICE"), target);
+        targetMachine.close();
+        target.free();
+
+        await expect(
+            StoreHandle.importSnapshot(snapshot, "snapshotPassphrase", "snapshotTarget", "targetPassphrase"),
+        ).rejects.toThrow("already exists");
+    });
+
+    test("refuses to import an encrypted store into an unencrypted one", async () => {
+        const storeHandle = await StoreHandle.open("snapshotSource", "sourcePassphrase");
+        await OlmMachine.initFromStore(user, device, storeHandle);
+        const snapshot = await storeHandle.exportSnapshot("snapshotPassphrase");
+
+        await expect(StoreHandle.importSnapshot(snapshot, "snapshotPassphrase", "snapshotTarget")).rejects.toThrow(
+            "a passphrase is needed",
+        );
+        expect((await indexedDB.databases()).filter((db) => db.name?.startsWith("snapshotTarget::"))).toHaveLength(0);
+    });
+
+    test("refuses to write to a non-empty adapter", async () => {
+        const adapter = new MapAdapter();
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        await OlmMachine.initFromStore(user, device, storeHandle);
+        const snapshot = await storeHandle.exportSnapshot("snapshotPassphrase");
+
+        const target = new MapAdapter();
+        await target.put("custom_values", "something", new Uint8Array([1]));
+
+        await expect(StoreHandle.importCustomSnapshot(snapshot, "snapshotPassphrase", target)).rejects.toThrow(
+            "The adapter is not empty",
+        );
+        expect(nonEmptyCollections(target).size).toEqual(1);
+    });
+
+    test("wipes the adapter if the import fails", async () => {
+        const adapter = new MapAdapter();
+        const storeHandle = await StoreHandle.openCustom(adapter);
+        await OlmMachine.initFromStore(user, device, storeHandle);
+        const snapshot = await storeHandle.exportSnapshot("snapshotPassphrase");
+
+        const target = new MapAdapter();
+        let writes = 0;
+        target.put = async (collection: string, key: string, value: Uint8Array) => {
+            if (++writes > 1) throw new Error("disk full");
+            target.collections.set(collection, new Map([[key, value]]));
+        };
+
+        await expect(StoreHandle.importCustomSnapshot(snapshot, "snapshotPassphrase", target)).rejects.toThrow();
+        expect(nonEmptyCollections(target).size).toEqual(0);
+    });
+});
+
+describe("StoreHandle.rekey", () => {
+    async function identityKey(storeHandle: StoreHandle): Promise<string> {
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        const key = machine.identityKeys.ed25519.toBase64();
+        await machine.close();
+        storeHandle.free();
+        return key;
+    }
+
+    test("changes the store passphrase", async () => {
+        const key = await identityKey(await StoreHandle.open("rekeyStore", "oldPassphrase"));
+
+        await StoreHandle.rekey("rekeyStore", "oldPassphrase", "newPassphrase");
+
+        await expect(StoreHandle.open("rekeyStore", "oldPassphrase")).rejects.toThrow();
+        expect(await identityKey(await StoreHandle.open("rekeyStore", "newPassphrase"))).toEqual(key);
+
+        // Running it again is harmless.
+        await StoreHandle.rekey("rekeyStore", "oldPassphrase", "newPassphrase");
+    });
+
+    test("moves from a passphrase to a key", async () => {
+        const storeKey = new Uint8Array(32);
+        crypto.getRandomValues(storeKey);
+
+        const key = await identityKey(await StoreHandle.open("rekeyStore", "oldPassphrase"));
+
+        await StoreHandle.rekey("rekeyStore", "oldPassphrase", storeKey);
+
+        expect(await identityKey(await StoreHandle.openWithKey("rekeyStore", storeKey))).toEqual(key);
+    });
+
+    test("rejects a wrong secret", async () => {
+        await identityKey(await StoreHandle.open("rekeyStore", "oldPassphrase"));
+
+        await expect(StoreHandle.rekey("rekeyStore", "wrongPassphrase", "newPassphrase")).rejects.toThrow(
+            "The old secret doesn't match the crypto store",
+        );
+    });
+
+    test("rejects a wrong secret once the store has been rekeyed", async () => {
+        await identityKey(await StoreHandle.open("rekeyStore", "oldPassphrase"));
+        await StoreHandle.rekey("rekeyStore", "oldPassphrase", "newPassphrase");
+
+        await expect(StoreHandle.rekey("rekeyStore", "wrongPassphrase", "newPassphrase")).rejects.toThrow(
+            "The old secret doesn't match the crypto store",
+        );
+    });
+});
+
+describe("StoreHandle.delete", () => {
+    const byStoreName = (name: string) => (db: IDBDatabaseInfo) => db.name?.startsWith(`${name}::`);
+
+    test("removes the databases of the store", async () => {
+        const storeHandle = await StoreHandle.open("deletedStore", "passphrase");
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.close();
+        storeHandle.free();
+
+        expect(await StoreHandle.delete("deletedStore")).toEqual([
+            "deletedStore::matrix-sdk-crypto",
+            "deletedStore::matrix-sdk-crypto-meta",
+        ]);
+        expect((await indexedDB.databases()).filter(byStoreName("deletedStore"))).toHaveLength(0);
+    });
+
+    test("fails while the store is still open", async () => {
+        const storeHandle = await StoreHandle.open("deletedStore", "passphrase");
+        await OlmMachine.initFromStore(user, device, storeHandle);
+
+        await expect(StoreHandle.delete("deletedStore")).rejects.toThrow("still open elsewhere");
+    });
+
+    test("reports nothing for an unknown store", async () => {
+        expect(await StoreHandle.delete("unknownStore")).toEqual([]);
+        expect((await indexedDB.databases()).filter(byStoreName("unknownStore"))).toHaveLength(0);
+    });
+
+    test("can be requested when closing an OlmMachine", async () => {
+        const machine = await OlmMachine.initialize(user, device, "wipedStore", "passphrase");
+
+        await machine.close(true);
+
+        expect((await indexedDB.databases()).filter(byStoreName("wipedStore"))).toHaveLength(0);
+    });
+
+    test("waits for the background tasks of the OlmMachine to stop", async () => {
+        const machine = await OlmMachine.initialize(user, device, "wipedStore", "passphrase");
+        machine.registerEventsDecryptedCallback(async () => {});
+
+        await machine.close(true);
+
+        expect((await indexedDB.databases()).filter(byStoreName("wipedStore"))).toHaveLength(0);
+    });
+});
+
+describe("StoreHandle.stats", () => {
+    test("counts the records of an IndexedDB store", async () => {
+        const storeHandle = await StoreHandle.open("statsStore", "passphrase");
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+
+        const stats = await storeHandle.stats();
+        expect(stats.inboundGroupSessions).toEqual(1);
+        expect(stats.inboundGroupSessionsPerRoom()).toEqual(new Map([[room.toString(), 1]]));
+        expect(stats.olmSessions).toEqual(0);
+        expect(stats.pendingSecrets).toEqual(0);
+
+        const collections = stats.collections();
+        expect(collections.length).toBeGreaterThan(0);
+        const totalBytes = collections.reduce((total, collection) => total + collection.approximateBytes, 0);
+        expect(totalBytes).toBeGreaterThan(0);
+
+        const machineStats = await machine.storeStats();
+        expect(machineStats.inboundGroupSessions).toEqual(1);
+    });
+
+    test("has no collections for a memory store", async () => {
+        const storeHandle = await StoreHandle.open();
+        await OlmMachine.initFromStore(user, device, storeHandle);
+
+        const stats = await storeHandle.stats();
+        expect(stats.inboundGroupSessions).toEqual(0);
+        expect(stats.collections()).toEqual([]);
+    });
+});
+
+describe("StoreLock", () => {
+    test("is held by one holder at a time", async () => {
+        const storeHandle = await StoreHandle.open("lockedStore", "passphrase");
+        const otherStoreHandle = await StoreHandle.open("lockedStore", "passphrase");
+
+        const lock = storeHandle.createLock("tab1");
+        const otherLock = otherStoreHandle.createLock("tab2");
+
+        expect(await lock.acquire()).toBe(true);
+        expect(lock.isHeld).toBe(true);
+        expect(await otherLock.acquire()).toBe(false);
+        expect(await lock.renew()).toBe(true);
+
+        await lock.release();
+        expect(lock.isHeld).toBe(false);
+        expect(await otherLock.acquire()).toBe(true);
+
+        await otherLock.release();
+    });
+
+    test("is not held once the lease has expired", async () => {
+        const storeHandle = await StoreHandle.open("lockedStore", "passphrase");
+        const lock = storeHandle.createLock("tab1", 1000);
+        expect(await lock.acquire()).toBe(true);
+
+        // Pretend that this context has been suspended past the lease.
+        const now = Date.now();
+        const dateNow = jest.spyOn(Date, "now").mockReturnValue(now + 2000);
+        try {
+            expect(lock.isHeld).toBe(false);
+        } finally {
+            dateNow.mockRestore();
+        }
+
+        await lock.release();
+    });
+
+    test("is released when freed", async () => {
+        const storeHandle = await StoreHandle.open("lockedStore", "passphrase");
+        const otherStoreHandle = await StoreHandle.open("lockedStore", "passphrase");
+
+        const lock = storeHandle.createLock("tab1");
+        const otherLock = otherStoreHandle.createLock("tab2");
+        expect(await lock.acquire()).toBe(true);
+
+        lock.free();
+
+        // The lease is given up in the background.
+        let acquired = false;
+        for (let attempt = 0; attempt < 50 && !acquired; attempt++) {
+            acquired = await otherLock.acquire();
+            if (!acquired) await new Promise((resolve) => setTimeout(resolve, 10));
+        }
+        expect(acquired).toBe(true);
+
+        await otherLock.release();
+    });
+});
+
+describe("StoreHandle.verifyIntegrity", () => {
+    test("reports a healthy store", async () => {
+        const storeHandle = await StoreHandle.open("checkedStore", "passphrase");
+        const machine = await OlmMachine.initFromStore(user, device, storeHandle);
+        await machine.shareRoomKey(room, [], new EncryptionSettings());
+
+        const report = await storeHandle.verifyIntegrity();
+        expect(report.isHealthy).toBe(true);
+        expect(report.checked).toBeGreaterThan(0);
+        expect(report.problems()).toEqual([]);
+    });
+
+    test("cannot repair an IndexedDB store", async () => {
+        const storeHandle = await StoreHandle.open("checkedStore", "passphrase");
+        await OlmMachine.initFromStore(user, device, storeHandle);
+
+        await expect(storeHandle.verifyIntegrity({ repair: true })).rejects.toThrow("can be repaired");
+    });
+});
@@ -0,0 +1,75 @@
+/*
+Copyright 2026 The Matrix.org Foundation C.I.C.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+import {
+    CollectStrategy,
+    DeviceId,
+    DeviceLists,
+    OlmMachine,
+    ToDeviceRequest,
+    UserId,
+} from "@matrix-org/matrix-sdk-crypto-wasm";
+import { addMachineToMachine, establishOlmSession } from "./helper.ts";
+
+describe("OlmMachine.encryptToDeviceEventForUsers", () => {
+    let alice: OlmMachine;
+    let bob: OlmMachine;
+    let carol: OlmMachine;
+
+    beforeEach(async () => {
+        alice = await OlmMachine.initialize(new UserId("@alice:example.org"), new DeviceId("ALICE"));
+        bob = await OlmMachine.initialize(new UserId("@bob:example.org"), new DeviceId("BOB"));
+        carol = await OlmMachine.initialize(new UserId("@carol:example.org"), new DeviceId("CAROL"));
+        await addMachineToMachine(bob, alice);
+        await addMachineToMachine(carol, alice);
+        await addMachineToMachine(alice, bob);
+    });
+
+    test("encrypts for the devices we have a session with", async () => {
+        // Alice only has an Olm session with Bob.
+        await establishOlmSession(alice, bob);
+
+        const result = await alice.encryptToDeviceEventForUsers(
+            "custom.type",
+            { foo: "bar" },
+            [bob.userId, carol.userId],
+            CollectStrategy.allDevices(),
+        );
+
+        const requests = result.requests();
+        expect(requests).toHaveLength(1);
+        expect(requests[0]).toBeInstanceOf(ToDeviceRequest);
+        expect(requests[0].event_type).toStrictEqual("m.room.encrypted");
+
+        const body = JSON.parse(requests[0].body);
+        const content = body.messages["@bob:example.org"]["BOB"];
+
+        const skipped = result.skipped();
+        expect(skipped).toHaveLength(1);
+        expect(skipped[0].userId.toString()).toStrictEqual("@carol:example.org");
+        expect(skipped[0].deviceId.toString()).toStrictEqual("CAROL");
+        expect(skipped[0].reason).toStrictEqual("m.no_olm");
+
+        // Bob can decrypt the event.
+        const processed = await bob.receiveSyncChangesObject(
+            [{ type: "m.room.encrypted", sender: "@alice:example.org", content }],
+            new DeviceLists(),
+            new Map(),
+            undefined,
+        );
+        expect(JSON.parse(processed[0].rawEvent).content).toStrictEqual({ foo: "bar" });
+    });
+});